@@ -0,0 +1,118 @@
+//! Timings of the iteration kernels and the coloring at standard views,
+//! run with `cargo bench`, a name given after `--` selects the cases
+//! containing it
+
+use mandelbrust::app::{self, DrawSettings};
+use mandelbrust::fractal::{Fractal, SharedFormula};
+use mandelbrust::mandelbrot::{self, RenderContext};
+use mandelbrust::options::Options;
+use mandelbrust::palette::RgbaBuffer;
+use mandelbrust::types::{MandelImage, Transform, View, DEFAULT_VIEW};
+
+use num::complex::Complex;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Size of the rendered images
+const SIZE: (u32, u32) = (800, 600);
+
+/// Maximum iterations of the renders
+const MAX_ITERATIONS: u32 = 1000;
+
+/// Runs of each case, the fastest and the median run are reported
+const RUNS: usize = 10;
+
+/// Views of different cost: the whole set, the seahorse valley with
+/// many slowly escaping pixels, and a minibrot mostly inside the set
+fn views() -> Vec<(&'static str, View)> {
+    vec![
+        ("full", DEFAULT_VIEW),
+        (
+            "seahorse",
+            View {
+                center: Complex::new(-0.745, 0.113),
+                height: 0.01,
+            },
+        ),
+        (
+            "minibrot",
+            View {
+                center: Complex::new(-1.7685, 0.0),
+                height: 0.002,
+            },
+        ),
+    ]
+}
+
+fn bench<F: FnMut()>(filter: &Option<String>, name: &str, mut run: F) {
+    if filter
+        .as_ref()
+        .is_some_and(|filter| !name.contains(filter.as_str()))
+    {
+        return;
+    }
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    println!(
+        "{:<32} fastest {:>12?} median {:>12?}",
+        name,
+        times[0],
+        times[RUNS / 2]
+    );
+}
+
+fn main() {
+    // cargo passes --bench, the other argument selects the cases
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let formula: SharedFormula = Arc::new(Fractal::Mandelbrot);
+    let options = Options::parse(std::iter::empty()).unwrap();
+    let mut context = RenderContext::new();
+
+    for (view_name, view) in views() {
+        let mut transform = Transform::new(SIZE);
+        transform.set_view(view);
+
+        bench(&filter, &format!("mandel/{}", view_name), || {
+            for y in (0..SIZE.1 as i32).step_by(10) {
+                for x in (0..SIZE.0 as i32).step_by(10) {
+                    mandelbrot::mandel(&transform.pos_to_complex(x, y), MAX_ITERATIONS);
+                }
+            }
+        });
+
+        let mut image = MandelImage::new(SIZE.0, SIZE.1, MAX_ITERATIONS);
+        bench(&filter, &format!("generate/{}", view_name), || {
+            mandelbrot::generate_image_thread(
+                &mut context,
+                &transform,
+                &formula,
+                1,
+                None,
+                &mut image,
+            );
+        });
+
+        bench(&filter, &format!("equalize/{}", view_name), || {
+            mandelbrot::equalize_image(&mut context, &mut image, None)
+        });
+
+        let mut settings = DrawSettings::new();
+        let mut rgba = RgbaBuffer::new(SIZE.0, SIZE.1);
+        bench(&filter, &format!("colorize/{}", view_name), || {
+            app::paint(&image, &transform, &settings, &options, &mut rgba)
+        });
+        settings.use_histogram = true;
+        bench(
+            &filter,
+            &format!("colorize_histogram/{}", view_name),
+            || app::paint(&image, &transform, &settings, &options, &mut rgba),
+        );
+    }
+}
@@ -0,0 +1,2296 @@
+use crate::backend::Backend;
+use crate::cache::{ImageCache, ImageKey};
+use crate::deep::{self, Precision};
+use crate::export;
+use crate::formula::FormulaFile;
+use crate::fractal::{
+    Bailout, Cubic, Fractal, Hybrid, Julia, Morph, Recorded, SharedFormula, SinglePrecision,
+};
+use crate::lyapunov;
+use crate::mandelbrot::{self, Cancel, Limits, RenderContext};
+use crate::options::Options;
+use crate::palette::{self, ColorScheme, ColorTable, Mapping, RgbaBuffer};
+use crate::power::PowerSaving;
+use crate::quaternion;
+use crate::share::{Session, SharedView};
+use crate::stats::SessionStats;
+use crate::timing;
+use crate::trap::{self, Trap};
+use crate::types::MandelImage;
+use crate::types::PixelRect;
+use crate::types::PlaneMap;
+use crate::types::Projection;
+use crate::types::Row;
+use crate::types::Transform;
+use crate::types::View;
+use crate::viewport::Viewport;
+use crate::workers;
+
+use num::complex::Complex;
+use sdl2::pixels::Color;
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+/// Renders taking longer than this show a coarse preview first
+const PREVIEW_AFTER: Duration = Duration::from_millis(100);
+
+/// Pixel block size of the preview, one value for 8x8 pixels
+const PREVIEW_BLOCK: u32 = 8;
+
+/// Images of recently shown views kept for returning to them
+const CACHED_IMAGES: usize = 4;
+
+/// Change of the distance shading strength per key press
+const SHADING_STEP: f64 = 0.25;
+
+/// Range of the palette scale, the number of palette repeats
+const PALETTE_SCALES: (f64, f64) = (1.0 / 64.0, 64.0);
+
+/// Distance to the set in pixels below which distance shading darkens colors
+const SHADING_DISTANCE: f64 = 4.0;
+
+/// Interior distance in pixels from which the inside of the set is white
+const INTERIOR_DISTANCE: f64 = 32.0;
+
+/// Brightness of escaped pixels with a negative imaginary part in binary decomposition
+const BINARY_SHADE: f64 = 0.35;
+
+/// Iterations per brightness cycle of the angle coloring
+const ANGLE_PERIOD: f64 = 16.0;
+
+/// Trap distance in complex units beyond which orbits get the darkest color
+const TRAP_RANGE: f64 = 0.5;
+
+/// Distance in pixels the orbit trap moves per key press
+const TRAP_STEP: f64 = 10.0;
+
+/// Change of the morph parameter per key press
+pub const MORPH_STEP: f64 = 0.1;
+
+/// Escape radii selected in turn after the radius of the formula
+const BAILOUTS: [f64; 3] = [10.0, 1e3, 1e6];
+
+/// Escape radius used for smooth coloring if none was selected
+const SMOOTH_BAILOUT: f64 = 1e3;
+
+/// Formula opened in the editor if none was given
+const DEFAULT_FORMULA: &str = "z^2 + c";
+
+/// Keeps the draw settings
+#[derive(Clone)]
+pub struct DrawSettings {
+    pub run: bool,
+    pub update_image: bool,
+    pub update_texture: bool,
+    pub use_histogram: bool,
+    /// interpolate the colors by the fractional iteration count
+    pub smooth: bool,
+    pub auto_iterations: bool,
+    /// raise the maximum iterations with the zoom depth
+    pub zoom_iterations: bool,
+    pub show_colors: bool,
+    pub split_position: Option<i32>,
+    pub color_scheme: ColorScheme,
+    /// rotation and repeats of the palette over the iterations
+    pub palette_mapping: Mapping,
+    pub fractal: Fractal,
+    pub lyapunov: bool,
+    pub use_formula: bool,
+    pub use_hybrid: bool,
+    pub schedule: Vec<Fractal>,
+    pub export_orbits: bool,
+    pub quaternion: bool,
+    /// grid of Julia thumbnails across the view
+    pub julia_map: bool,
+    /// parameter of the Julia set shown instead of the parameter plane
+    pub julia: Option<Complex<f64>>,
+    pub cubic: bool,
+    /// second parameter of the cubic family
+    pub cubic_a: Complex<f64>,
+    /// starting point between 0 and the pixel, from 0 to 1
+    pub morph: f64,
+    /// strength of the distance estimate shading from 0 (off) to 1
+    pub shading: f64,
+    /// color by the distance estimate instead of the iterations
+    pub distance_mode: bool,
+    /// shade the inside of the set by the interior distance instead of a solid fill
+    pub interior_distance: bool,
+    /// darken escaped pixels by the sign of the imaginary part of the last z
+    pub binary_decomposition: bool,
+    /// color escaped pixels by the angle of the last z
+    pub angle_coloring: bool,
+    /// color escaped pixels by the continuous potential
+    pub potential_coloring: bool,
+    /// color pixels by the iteration of the smallest |z| of their orbit
+    pub atom_domain: bool,
+    /// follow the view of the host of a joined session
+    pub follow: bool,
+    /// escape radius, the radius of the formula if None
+    pub bailout: Option<f64>,
+    /// pixels left out of the histogram equalization statistics
+    pub exclusion: Option<PixelRect>,
+    /// color by the closest approach of the orbits to the trap
+    pub trap: Option<Trap>,
+}
+
+impl Default for DrawSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DrawSettings {
+    pub fn new() -> Self {
+        DrawSettings {
+            run: true,
+            update_image: true,
+            update_texture: true,
+            use_histogram: false,
+            smooth: false,
+            auto_iterations: false,
+            zoom_iterations: false,
+            show_colors: false,
+            split_position: None,
+            color_scheme: ColorScheme::Green,
+            palette_mapping: Mapping::default(),
+            fractal: Fractal::Mandelbrot,
+            lyapunov: false,
+            use_formula: false,
+            use_hybrid: false,
+            schedule: Vec::new(),
+            export_orbits: false,
+            quaternion: false,
+            julia_map: false,
+            julia: None,
+            cubic: false,
+            cubic_a: Complex::new(0.0, 0.0),
+            morph: 0.0,
+            shading: 0.0,
+            distance_mode: false,
+            interior_distance: false,
+            binary_decomposition: false,
+            angle_coloring: false,
+            potential_coloring: false,
+            atom_domain: false,
+            follow: true,
+            bailout: None,
+            exclusion: None,
+            trap: None,
+        }
+    }
+}
+
+/// Defined the different user events
+#[derive(Clone)]
+pub enum MandelEvent {
+    Idle,
+    /// the window needs to be drawn again, e.g. after it was uncovered
+    Redraw,
+    Quit,
+    Zoom(f64),
+    /// zooms to the magnification at the current center
+    ZoomTo(f64),
+    Scheme(ColorScheme),
+    /// cycles the registered palettes
+    NextPalette,
+    /// rotates the palette by a fraction of its length
+    ShiftPalette(f64),
+    /// multiplies the number of palette repeats
+    ScalePalette(f64),
+    Center(i32, i32),
+    ChangeIterations(f64),
+    SetIterations(u32),
+    /// runs the actions bound to the function key
+    Macro(String),
+    ToggleAutoIterations,
+    ToggleZoomIterations,
+    ToggleHistogram,
+    ToggleSmooth,
+    TogglePowerSaving,
+    ToggleSplit,
+    Drag(i32),
+    /// corners of the exclusion zone, pressed and released
+    SelectStart(i32, i32),
+    SelectEnd(i32, i32),
+    ToggleColorschemes,
+    NextFractal,
+    ToggleLyapunov,
+    ToggleFormula,
+    EditFormula,
+    ToggleHybrid,
+    AppendHybrid,
+    RemoveHybrid,
+    ShowInfo(i32, i32),
+    Export,
+    ToggleOrbitExport,
+    ToggleQuaternion,
+    /// toggles the Julia map or returns from a Julia set to the parameter plane
+    ToggleJuliaMap,
+    ToggleInverse,
+    ToggleExponential,
+    ToggleCubic,
+    Morph(f64),
+    NextBailout,
+    ShowStats,
+    NextShading,
+    ToggleDistance,
+    ToggleInterior,
+    ToggleBinary,
+    ToggleAngle,
+    TogglePotential,
+    ToggleAtomDomain,
+    ToggleFollow,
+    NextStrategy,
+    NextBackend,
+    NextTrap,
+    /// moves the orbit trap by a number of steps right and up
+    MoveTrap(i32, i32),
+    MoveSlice(f64, f64),
+}
+
+/// Render running on the background thread, it returns the context
+/// and the image, and whether the image is complete
+struct RenderJob {
+    handle: JoinHandle<(RenderContext, MandelImage, bool)>,
+    /// tiles finished by the render threads
+    progress: Receiver<PixelRect>,
+    /// colored coarse image shown until the render is complete
+    preview: Receiver<RgbaBuffer>,
+    tiles: usize,
+    /// what the image of the render shows
+    key: ImageKey,
+    start: SystemTime,
+}
+
+/// The viewer state machine without any window, driven by events.
+/// The SDL frontend and tests feed it the same events.
+pub struct App {
+    options: Options,
+    settings: DrawSettings,
+    transform: Transform,
+    /// image of the last complete render, shown and colored
+    image: MandelImage,
+    /// image rendered next, reused between renders except in low memory
+    /// mode
+    back: Option<MandelImage>,
+    /// buffers of the render path, away while a render is running
+    context: Option<RenderContext>,
+    /// aborts the running render
+    cancel: Cancel,
+    job: Option<RenderJob>,
+    /// duration of the last complete render
+    last_render: Duration,
+    /// count of changes of the image settings, moving or zooming the
+    /// view is not counted
+    generation: u32,
+    /// what the shown image shows and the iterations it was shown with,
+    /// None before the first render
+    shown: Option<(ImageKey, u32)>,
+    /// images of recently shown views
+    cache: ImageCache,
+    stats: SessionStats,
+    /// shows the session statistics in the status
+    show_stats: bool,
+    rgba: RgbaBuffer,
+    /// arithmetic of the last render
+    precision: Precision,
+    /// formula being edited in an external editor
+    formula_file: Option<FormulaFile>,
+    /// error shown until the next successful formula reload
+    message: Option<String>,
+    /// throttles rendering on battery power
+    power: PowerSaving,
+    /// first corner of the exclusion zone being selected
+    selection: Option<(i32, i32)>,
+    /// shared exploration hosted or joined
+    session: Option<Session>,
+    /// view received from the host, applied while following
+    received_view: Option<SharedView>,
+    /// view of the parameter plane while a Julia set is shown
+    parameter_view: Option<Transform>,
+}
+
+impl App {
+    pub fn new(options: Options) -> Self {
+        let image = new_image(&options, options.width, options.height, options.iterations);
+        let mut transform = Transform::new((image.width, image.height));
+        transform.set_view(options.view());
+        transform.zoom(options.zoom);
+        if let Some(center) = &options.center {
+            transform.set_center(center);
+        }
+        let mut settings = DrawSettings::new();
+        settings.schedule = options.schedule.clone();
+        settings.bailout = options.bailout;
+        let mut power = PowerSaving::new();
+        power.poll();
+        let context = RenderContext::new();
+        // low memory mode keeps no images besides the shown one
+        let cache = ImageCache::new(match options.low_mem {
+            true => 0,
+            false => CACHED_IMAGES,
+        });
+
+        App {
+            rgba: RgbaBuffer::new(image.width, image.height),
+            options,
+            settings,
+            transform,
+            image,
+            back: None,
+            cancel: context.cancel(),
+            context: Some(context),
+            job: None,
+            last_render: Duration::ZERO,
+            generation: 0,
+            shown: None,
+            cache,
+            stats: SessionStats::default(),
+            show_stats: false,
+            precision: Precision::F64,
+            formula_file: None,
+            message: None,
+            power,
+            selection: None,
+            session: None,
+            received_view: None,
+            parameter_view: None,
+        }
+    }
+
+    pub fn set_session(&mut self, session: Option<Session>) {
+        self.session = session;
+    }
+
+    /// The view shared with viewers of a hosted session
+    fn shared_view(&self) -> SharedView {
+        SharedView {
+            center: self.transform.precise_center(),
+            zoom: self.transform.zoom_factor(),
+            iterations: self.image.max_iterations,
+        }
+    }
+
+    /// Sends the view to the viewers or follows the view of the host
+    fn sync_session(&mut self) {
+        let view = match self.session {
+            Some(Session::Host(_)) => Some(self.shared_view()),
+            _ => None,
+        };
+        match &mut self.session {
+            Some(Session::Host(host)) => host.broadcast(&view.unwrap()),
+            Some(Session::Viewer(viewer)) => {
+                if let Some(view) = viewer.poll() {
+                    self.received_view = Some(view);
+                }
+            }
+            None => (),
+        }
+
+        if !self.settings.follow {
+            return;
+        }
+        if let Some(view) = self.received_view.take() {
+            self.transform
+                .zoom(view.zoom / self.transform.zoom_factor());
+            self.transform.set_center(&view.center);
+            self.image.max_iterations = view.iterations;
+            self.settings.auto_iterations = false;
+            self.settings.zoom_iterations = false;
+            self.settings.update_image = true;
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.settings.run
+    }
+
+    pub fn settings(&self) -> &DrawSettings {
+        &self.settings
+    }
+
+    /// True if rendering is throttled to save power
+    pub fn power_saving(&self) -> bool {
+        self.power.active()
+    }
+
+    /// Size of the image in pixels, event positions are in image coordinates
+    pub fn image_size(&self) -> (u32, u32) {
+        (self.image.width, self.image.height)
+    }
+
+    /// The colored image of the last tick
+    pub fn frame(&self) -> &RgbaBuffer {
+        &self.rgba
+    }
+
+    /// Checks if the pixels are too small for the arithmetic of the last
+    /// render, the image then degrades into blocks
+    pub fn precision_exhausted(&self) -> bool {
+        Precision::select(&self.transform) != self.precision
+    }
+
+    /// Zoom, pixel size and arithmetic of the view for the window title
+    pub fn status(&self) -> String {
+        let kernel = match single_precision(&self.transform, &self.settings, &self.options) {
+            true => "f32",
+            false => self.precision.name(),
+        };
+        let status = format!(
+            "zoom {:.3e}, pixel {:.3e}, {}",
+            self.transform.zoom_factor(),
+            self.transform.pixel_size(),
+            kernel
+        );
+        let status = match self.precision_exhausted() {
+            true => format!("{} - f64 precision exhausted", status),
+            false => status,
+        };
+        let status = match &self.job {
+            Some(job) => format!("{} - rendering, {} tiles done", status, job.tiles),
+            None => status,
+        };
+        let status = match &self.session {
+            Some(Session::Host(host)) => format!("{} - {} viewers", status, host.viewer_count()),
+            Some(Session::Viewer(viewer)) if !viewer.connected() => {
+                format!("{} - disconnected from host", status)
+            }
+            Some(Session::Viewer(_)) if !self.settings.follow => {
+                format!("{} - not following host", status)
+            }
+            _ => status,
+        };
+        let status = match &self.message {
+            Some(message) => format!("{} - {}", status, message),
+            None => status,
+        };
+        let status = match self.show_stats {
+            true => format!("{} - {}", status, self.stats.summary()),
+            false => status,
+        };
+        match (
+            self.options.frame_diagnostics,
+            timing::summary(timing::FRAME),
+        ) {
+            (true, Some(frames)) => format!("{} - {:.0} fps", status, frames.rate()),
+            _ => status,
+        }
+    }
+
+    pub fn handle_event(&mut self, e: MandelEvent) {
+        if let MandelEvent::Macro(key) = &e {
+            let actions = self
+                .options
+                .macros
+                .iter()
+                .find(|m| &m.key == key)
+                .map(|m| m.actions.clone());
+            for action in actions.unwrap_or_default() {
+                self.handle_event(action);
+            }
+            return;
+        }
+
+        // moving and zooming the view keep the settings of the image
+        let view_only = matches!(
+            e,
+            MandelEvent::Center(..) | MandelEvent::Zoom(_) | MandelEvent::ZoomTo(_)
+        ) && !self.settings.julia_map;
+        let pending = self.settings.update_image;
+        self.settings.update_image = false;
+
+        let App {
+            options,
+            settings,
+            transform,
+            image,
+            context,
+            stats,
+            show_stats,
+            formula_file,
+            power,
+            selection,
+            parameter_view,
+            generation,
+            ..
+        } = self;
+
+        match e {
+            MandelEvent::Quit => settings.run = false,
+            MandelEvent::Zoom(factor) => {
+                if factor > 0.0 {
+                    transform.zoom(factor);
+                } else {
+                    transform.reset();
+                    image.max_iterations = options.iterations;
+                }
+                settings.update_image = true;
+            }
+            MandelEvent::ZoomTo(zoom) => {
+                transform.zoom(zoom / transform.zoom_factor());
+                image.max_iterations =
+                    depth_iterations(options.iterations, zoom, options.iteration_curve);
+                println!(
+                    "Zoom to {:e} with {} iterations",
+                    zoom, image.max_iterations
+                );
+                settings.update_image = true;
+            }
+            MandelEvent::Center(x, y) if settings.julia_map => {
+                let c = mandelbrot::julia_map_parameter(transform, x, y);
+                println!("Julia set of {}", c);
+                *parameter_view = Some(transform.clone());
+                transform.set_view(View {
+                    center: Complex::new(0.0, 0.0),
+                    height: mandelbrot::JULIA_VIEW_HEIGHT,
+                });
+                settings.julia = Some(c);
+                settings.julia_map = false;
+                settings.update_image = true;
+            }
+            MandelEvent::Center(x, y) => match settings.split_position {
+                // in split mode the left mouse button moves the divider
+                Some(_) => {
+                    settings.split_position = Some(x);
+                    settings.update_texture = true;
+                }
+                None => {
+                    transform.recenter(x, y);
+                    settings.update_image = true;
+                }
+            },
+            MandelEvent::Drag(x) if settings.split_position.is_some() => {
+                settings.split_position = Some(x);
+                settings.update_texture = true;
+            }
+            MandelEvent::SelectStart(x, y) => *selection = Some((x, y)),
+            MandelEvent::SelectEnd(x, y) => {
+                if let Some(start) = selection.take() {
+                    // a click without dragging removes the zone
+                    let rect = PixelRect::new(start, (x, y));
+                    settings.exclusion = match rect.is_empty() {
+                        true => None,
+                        false => Some(rect),
+                    };
+                    println!("Equalization exclusion zone: {:?}", settings.exclusion);
+                    match context {
+                        Some(context) if !settings.lyapunov => equalize(
+                            context,
+                            settings,
+                            options,
+                            settings.exclusion.as_ref(),
+                            image,
+                        ),
+                        Some(_) => (),
+                        // the running render is replaced by one with the zone
+                        None => settings.update_image = true,
+                    }
+                    settings.update_texture = true;
+                }
+            }
+            MandelEvent::Scheme(scheme) => {
+                settings.color_scheme = scheme;
+                settings.update_texture = true;
+            }
+            MandelEvent::NextPalette => {
+                let palettes = palette::registered();
+                if palettes.is_empty() {
+                    println!("No palettes registered, load them with --palettes <dir>");
+                } else {
+                    let current = palettes
+                        .iter()
+                        .position(|scheme| scheme.name() == settings.color_scheme.name());
+                    let next = current.map_or(0, |index| (index + 1) % palettes.len());
+                    settings.color_scheme = palettes[next];
+                    println!("Color scheme: {}", settings.color_scheme.name());
+                    settings.update_texture = true;
+                }
+            }
+            MandelEvent::ShiftPalette(step) => {
+                let mapping = &mut settings.palette_mapping;
+                mapping.offset = (mapping.offset + step).rem_euclid(1.0);
+                println!("Palette offset: {:.3}", mapping.offset);
+                settings.update_texture = true;
+            }
+            MandelEvent::ScalePalette(factor) => {
+                let mapping = &mut settings.palette_mapping;
+                let (min, max) = PALETTE_SCALES;
+                mapping.scale = (mapping.scale * factor).clamp(min, max);
+                println!("Palette scale: {:.3}", mapping.scale);
+                settings.update_texture = true;
+            }
+            MandelEvent::ToggleHistogram | MandelEvent::ToggleSplit if options.low_mem => {
+                println!("Histogram equalization is not available in low memory mode");
+            }
+            MandelEvent::ToggleHistogram => {
+                settings.use_histogram = !settings.use_histogram;
+                equalize_shown(context, settings, options, image);
+                settings.update_texture = true;
+            }
+            MandelEvent::TogglePowerSaving => {
+                power.toggle();
+                println!("Power saving: {}", power.active());
+                settings.update_image = true;
+            }
+            MandelEvent::ToggleSmooth => {
+                settings.smooth = !settings.smooth;
+                println!("Smooth coloring: {}", settings.smooth);
+                // the automatic escape radius changes with the coloring
+                settings.update_image = settings.bailout.is_none();
+                settings.update_texture = true;
+            }
+            MandelEvent::ToggleSplit => {
+                settings.split_position = match settings.split_position {
+                    Some(_) => None,
+                    None => Some(image.width as i32 / 2),
+                };
+                equalize_shown(context, settings, options, image);
+                settings.update_texture = true;
+            }
+            MandelEvent::ToggleColorschemes => {
+                settings.show_colors = !settings.show_colors;
+                settings.update_texture = true;
+            }
+            MandelEvent::NextFractal => {
+                let previous_view = settings.fractal.default_view();
+                settings.fractal = settings.fractal.cycle();
+                let view = settings.fractal.default_view();
+                if view != previous_view {
+                    transform.set_view(view.unwrap_or_else(|| options.view()));
+                }
+                println!("Fractal: {:?}", settings.fractal);
+                settings.update_image = true;
+            }
+            MandelEvent::ToggleFormula => match &options.formula {
+                Some(formula) => {
+                    settings.use_formula = !settings.use_formula;
+                    match settings.use_formula {
+                        true => println!("Formula: {}", formula.source()),
+                        false => println!("Fractal: {:?}", settings.fractal),
+                    }
+                    settings.update_image = true;
+                }
+                None => println!("No formula given, use the --formula option"),
+            },
+            MandelEvent::EditFormula => {
+                // editing again reopens the file of this session
+                if formula_file.is_none() {
+                    let source = options
+                        .formula
+                        .as_ref()
+                        .map_or(DEFAULT_FORMULA, |formula| formula.source());
+                    match FormulaFile::create(&std::env::temp_dir(), source) {
+                        Ok(file) => *formula_file = Some(file),
+                        Err(e) => println!("{}", e),
+                    }
+                }
+                if let Some(file) = formula_file {
+                    match file.edit() {
+                        Ok(()) => println!("Editing formula in {:?}", file.path()),
+                        Err(e) => println!("{}", e),
+                    }
+                }
+            }
+            MandelEvent::ToggleHybrid => {
+                settings.use_hybrid = !settings.use_hybrid;
+                match settings.use_hybrid {
+                    true => println!("Hybrid: {:?}", settings.schedule),
+                    false => println!("Fractal: {:?}", settings.fractal),
+                }
+                settings.update_image = true;
+            }
+            MandelEvent::AppendHybrid => {
+                settings.schedule.push(settings.fractal);
+                println!("Hybrid: {:?}", settings.schedule);
+                settings.update_image = settings.use_hybrid;
+            }
+            MandelEvent::RemoveHybrid => {
+                // keep at least one formula in the schedule
+                if settings.schedule.len() > 1 {
+                    settings.schedule.pop();
+                }
+                println!("Hybrid: {:?}", settings.schedule);
+                settings.update_image = settings.use_hybrid;
+            }
+            MandelEvent::ToggleJuliaMap => {
+                match parameter_view.take() {
+                    Some(view) if settings.julia.is_some() => {
+                        *transform = view;
+                        settings.julia = None;
+                    }
+                    _ => settings.julia_map = !settings.julia_map,
+                }
+                println!(
+                    "Julia map: {}, Julia set: {:?}",
+                    settings.julia_map, settings.julia
+                );
+                settings.update_image = true;
+            }
+            MandelEvent::ToggleQuaternion => {
+                settings.quaternion = !settings.quaternion;
+                println!(
+                    "Quaternion Julia: {}",
+                    if settings.quaternion { "on" } else { "off" }
+                );
+                settings.update_image = true;
+            }
+            MandelEvent::MoveSlice(j, k) if settings.quaternion => {
+                transform.move_slice(j, k);
+                println!("Slice: {:?}", transform.slice());
+                settings.update_image = true;
+            }
+            MandelEvent::ToggleCubic => {
+                settings.cubic = !settings.cubic;
+                match settings.cubic {
+                    true => println!("Cubic: a = {}", settings.cubic_a),
+                    false => println!("Fractal: {:?}", settings.fractal),
+                }
+                settings.update_image = true;
+            }
+            MandelEvent::MoveSlice(re, im) if settings.cubic => {
+                settings.cubic_a += Complex::new(re, im);
+                println!("Cubic: a = {}", settings.cubic_a);
+                settings.update_image = true;
+            }
+            MandelEvent::ToggleInverse => {
+                let map = match transform.map() {
+                    PlaneMap::Identity => PlaneMap::Inverse,
+                    PlaneMap::Inverse => PlaneMap::Identity,
+                };
+                transform.reset();
+                transform.set_map(map);
+                if map == PlaneMap::Inverse {
+                    // the inverted set reaches from about -2 to 4 along the real axis
+                    transform.zoom(0.5);
+                    transform.center_at(&Complex::new(1.5, 0.0));
+                }
+                println!("Plane map: {:?}", map);
+                settings.update_image = true;
+            }
+            MandelEvent::ToggleExponential => {
+                let projection = match transform.projection() {
+                    Projection::Flat => Projection::Exponential,
+                    Projection::Exponential => Projection::Flat,
+                };
+                transform.set_projection(projection);
+                println!("Projection: {:?}", projection);
+                settings.update_image = true;
+            }
+            MandelEvent::ToggleLyapunov => {
+                settings.lyapunov = !settings.lyapunov;
+                transform.reset();
+                if settings.lyapunov {
+                    transform.zoom(4.0);
+                    transform.center_at(&lyapunov::default_center());
+                }
+                settings.update_image = true;
+            }
+            MandelEvent::ChangeIterations(factor) => {
+                image.max_iterations = (image.max_iterations as f64 * factor).round() as u32;
+                manual_iterations(settings);
+                settings.update_image = true;
+            }
+            MandelEvent::SetIterations(iterations) => {
+                image.max_iterations = iterations;
+                manual_iterations(settings);
+                settings.update_image = true;
+            }
+            MandelEvent::ToggleZoomIterations => {
+                settings.zoom_iterations = !settings.zoom_iterations;
+                settings.auto_iterations = false;
+                println!("Iterations by zoom depth: {}", settings.zoom_iterations);
+                settings.update_image = settings.zoom_iterations;
+            }
+            MandelEvent::ToggleAutoIterations => {
+                settings.zoom_iterations = false;
+                settings.auto_iterations = !settings.auto_iterations;
+                println!(
+                    "Auto iterations: {}",
+                    if settings.auto_iterations {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                );
+                settings.update_image = settings.auto_iterations;
+            }
+            MandelEvent::Morph(step) => {
+                // round to whole steps so repeated steps return to exactly 0
+                let morph = ((settings.morph + step) / MORPH_STEP).round() * MORPH_STEP;
+                settings.morph = morph.clamp(0.0, 1.0);
+                println!("Morph: {:.1}", settings.morph);
+                settings.update_image = true;
+            }
+            MandelEvent::NextBailout => {
+                settings.bailout = match settings.bailout {
+                    None => Some(BAILOUTS[0]),
+                    Some(radius) => BAILOUTS.iter().copied().find(|&r| r > radius),
+                };
+                match settings.bailout {
+                    Some(radius) => println!("Bailout radius: {}", radius),
+                    None => println!("Bailout radius: formula default"),
+                }
+                settings.update_image = true;
+            }
+            MandelEvent::ShowStats => {
+                *show_stats = !*show_stats;
+                if *show_stats {
+                    stats.print();
+                    timing::print();
+                }
+            }
+            MandelEvent::ToggleDistance => {
+                settings.distance_mode = !settings.distance_mode;
+                println!("Distance estimate rendering: {}", settings.distance_mode);
+                settings.update_image = true;
+            }
+            MandelEvent::NextTrap => {
+                settings.trap = trap::next_trap(settings.trap);
+                match settings.trap {
+                    Some(trap) => println!("Orbit trap: {} at {}", trap.name(), trap.center),
+                    None => println!("Orbit trap: off"),
+                }
+                settings.update_image = true;
+            }
+            MandelEvent::MoveTrap(dx, dy) => {
+                if let Some(trap) = &mut settings.trap {
+                    let step = transform.pixel_size() * TRAP_STEP;
+                    trap.center += Complex::new(dx as f64 * step, dy as f64 * step);
+                    println!("Orbit trap: {} at {}", trap.name(), trap.center);
+                    settings.update_image = true;
+                }
+            }
+            MandelEvent::ToggleInterior => {
+                settings.interior_distance = !settings.interior_distance;
+                println!("Interior distance shading: {}", settings.interior_distance);
+                settings.update_image = true;
+            }
+            MandelEvent::ToggleBinary => {
+                settings.binary_decomposition = !settings.binary_decomposition;
+                println!("Binary decomposition: {}", settings.binary_decomposition);
+                settings.update_texture = true;
+            }
+            MandelEvent::ToggleAngle => {
+                settings.angle_coloring = !settings.angle_coloring;
+                println!("Angle coloring: {}", settings.angle_coloring);
+                settings.update_texture = true;
+            }
+            MandelEvent::TogglePotential => {
+                settings.potential_coloring = !settings.potential_coloring;
+                println!("Potential coloring: {}", settings.potential_coloring);
+                settings.update_texture = true;
+            }
+            MandelEvent::ToggleAtomDomain => {
+                settings.atom_domain = !settings.atom_domain;
+                println!("Atom domain coloring: {}", settings.atom_domain);
+                // the domains are recorded while iterating
+                match settings.atom_domain {
+                    true => settings.update_image = true,
+                    false => settings.update_texture = true,
+                }
+            }
+            MandelEvent::ToggleFollow => {
+                settings.follow = !settings.follow;
+                println!("Follow host: {}", settings.follow);
+            }
+            MandelEvent::NextStrategy => {
+                options.strategy = options.strategy.next();
+                println!("Renderer: {}", options.strategy.name());
+                settings.update_image = true;
+            }
+            MandelEvent::NextBackend => {
+                options.backend = match options.backend {
+                    None => Some(Backend::Scalar),
+                    Some(Backend::Simd) => None,
+                    Some(backend) => Some(backend.next()),
+                };
+                let name = options.backend.map_or("formula", Backend::name);
+                println!("Backend: {}", name);
+                settings.update_image = true;
+            }
+            MandelEvent::NextShading => {
+                settings.shading = match settings.shading >= 1.0 {
+                    true => 0.0,
+                    false => settings.shading + SHADING_STEP,
+                };
+                println!("Distance shading: {}", settings.shading);
+                settings.update_image = true;
+            }
+            MandelEvent::ShowInfo(x, y) => {
+                let z = transform.pos_to_complex(x, y);
+                if settings.lyapunov {
+                    println!("a: {}, b: {}, exponent: {}", z.re, z.im, image.value(x, y));
+                } else {
+                    println!(
+                        "Comples: [{}, {}i], iterations: {}",
+                        z.re,
+                        z.im,
+                        image.iterations(x, y)
+                    );
+                    if let Some(stats) = image.stats(x, y) {
+                        println!(
+                            "min |z|: {} at iteration {}, last z: {}, derivative: {:?}, trap: {}",
+                            stats.min_norm,
+                            stats.min_iteration,
+                            stats.z,
+                            stats.derivative,
+                            stats.trap
+                        );
+                    }
+                    if settings.export_orbits && !settings.quaternion {
+                        match export_orbit(settings, options, &z, image.max_iterations) {
+                            Ok(path) => println!("Exported orbit to {:?}", path),
+                            Err(e) => println!("Orbit export failed: {}", e),
+                        }
+                    }
+                }
+            }
+            MandelEvent::ToggleOrbitExport => {
+                settings.export_orbits = !settings.export_orbits;
+                println!(
+                    "Orbit export: {}",
+                    if settings.export_orbits { "on" } else { "off" }
+                );
+            }
+            MandelEvent::Export => {
+                match export_image(transform, settings, options, image.max_iterations) {
+                    Ok(path) => println!("Exported image to {:?}", path),
+                    Err(e) => println!("Export failed: {}", e),
+                }
+            }
+            _ => {}
+        }
+
+        if settings.update_image && !view_only {
+            *generation += 1;
+        }
+        settings.update_image |= pending;
+    }
+
+    /// Starts a render if events changed the image and colors the image
+    /// of a finished render, returns true if a new frame is available
+    pub fn tick(&mut self) -> bool {
+        self.sync_session();
+
+        if self.power.poll() {
+            println!("Power saving: {}", self.power.active());
+            self.settings.update_image = true;
+            self.generation += 1;
+        }
+
+        if let Some(result) = self.formula_file.as_mut().and_then(FormulaFile::poll) {
+            match result {
+                Ok(formula) => {
+                    println!("Formula: {}", formula.source());
+                    self.options.formula = Some(Arc::new(formula));
+                    self.settings.use_formula = true;
+                    self.settings.update_image = true;
+                    self.generation += 1;
+                    self.message = None;
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    self.message = Some(e);
+                }
+            }
+        }
+
+        // a render still running for the old view is not needed anymore
+        if self.settings.update_image && self.job.is_some() {
+            self.cancel.cancel();
+        }
+        let preview = self.finish_render();
+        if self.settings.update_image && self.job.is_none() {
+            self.start_render();
+        }
+
+        if self.settings.update_texture {
+            paint(
+                &self.image,
+                &self.transform,
+                &self.settings,
+                &self.options,
+                &mut self.rgba,
+            );
+            self.settings.update_texture = false;
+            return true;
+        }
+
+        preview
+    }
+
+    /// True while a render is running on the background thread
+    pub fn rendering(&self) -> bool {
+        self.job.is_some()
+    }
+
+    /// Hands the context and the back image to a render thread for the
+    /// current view, the shown image stays until the render is complete
+    fn start_render(&mut self) {
+        if self.settings.zoom_iterations {
+            self.image.max_iterations = depth_iterations(
+                self.options.iterations,
+                self.transform.zoom_factor(),
+                self.options.iteration_curve,
+            );
+        }
+        let precision = precision(&self.transform, &self.settings, &self.options);
+        if precision != self.precision {
+            println!("Precision: {}", precision.name());
+            self.precision = precision;
+        }
+
+        let key = ImageKey {
+            transform: self.transform.clone(),
+            max_iterations: self.image.max_iterations,
+            generation: self.generation,
+        };
+        // automatic iterations depend on the view, cached images may differ
+        if !self.settings.auto_iterations {
+            if let Some(mut image) = self.cache.take(&key) {
+                println!("Showing the cached image of the view");
+                // the histogram may have been turned on since it was cached
+                equalize_shown(
+                    &mut self.context,
+                    &mut self.settings,
+                    &self.options,
+                    &mut image,
+                );
+                self.show(key, image);
+                self.settings.update_image = false;
+                return;
+            }
+        }
+
+        let mut context = self.context.take().expect("Render context is in use");
+        let limits = render_limits(&self.options, &self.power);
+        let (tx, progress) = mpsc::channel();
+        let (preview_tx, preview) = mpsc::channel();
+        self.cancel.reset();
+
+        let mut image = match self.back.take() {
+            Some(image) => image,
+            None => new_image(
+                &self.options,
+                self.image.width,
+                self.image.height,
+                self.image.max_iterations,
+            ),
+        };
+        image.max_iterations = self.image.max_iterations;
+
+        // after moving the center the pixels still in view are copied,
+        // from the shown or a cached image, whichever shows more of them
+        let shown = match &self.shown {
+            Some((previous, _)) => key.pan_offset(previous).map(|offset| (&self.image, offset)),
+            None => None,
+        };
+        let source = shown
+            .into_iter()
+            .chain(self.cache.overlapping(&key))
+            .max_by_key(|(_, offset)| key.overlap(*offset));
+        let known = match source {
+            Some(_) if self.settings.auto_iterations => None,
+            Some((source, (dx, dy))) => Some(image.copy_shifted(source, dx, dy)),
+            None => None,
+        };
+        // fast renders are shown without a preview that would only flicker
+        let coarse = self.last_render >= PREVIEW_AFTER
+            && !self.settings.show_colors
+            && known.is_none()
+            && !self.options.deterministic;
+
+        let transform = self.transform.clone();
+        let settings = self.settings.clone();
+        let options = self.options.clone();
+        let handle = thread::Builder::new()
+            .name("render".to_string())
+            .spawn(move || {
+                let exclusion = settings.exclusion.as_ref();
+                if coarse {
+                    context.set_limits(Limits {
+                        block: PREVIEW_BLOCK.max(limits.block),
+                        ..limits
+                    });
+                    // the full pass reuses the samples of the preview, which
+                    // skips the orbit statistics and distances
+                    context.set_refine(true);
+                    let settings = DrawSettings {
+                        shading: 0.0,
+                        distance_mode: false,
+                        interior_distance: false,
+                        ..settings.clone()
+                    };
+                    let options = Options {
+                        orbit_stats: false,
+                        ..options.clone()
+                    };
+                    if render(
+                        &mut context,
+                        &transform,
+                        &settings,
+                        &options,
+                        exclusion,
+                        None,
+                        &mut image,
+                    ) {
+                        let mut rgba = RgbaBuffer::new(image.width, image.height);
+                        paint(&image, &transform, &settings, &options, &mut rgba);
+                        let _ = preview_tx.send(rgba);
+                    }
+                }
+                context.set_limits(limits);
+                context.set_progress(Some(tx));
+                let complete = !context.cancel().is_canceled()
+                    && render(
+                        &mut context,
+                        &transform,
+                        &settings,
+                        &options,
+                        exclusion,
+                        known.as_ref(),
+                        &mut image,
+                    );
+                context.set_progress(None);
+                context.set_refine(false);
+                if options.low_mem {
+                    context.reference = None;
+                }
+                (context, image, complete)
+            })
+            .expect("Failed to start render thread");
+
+        self.job = Some(RenderJob {
+            handle,
+            progress,
+            preview,
+            tiles: 0,
+            key,
+            start: SystemTime::now(),
+        });
+        self.settings.update_image = false;
+    }
+
+    /// Shows a complete image, the image shown before is cached
+    fn show(&mut self, key: ImageKey, image: MandelImage) {
+        let iterations = image.max_iterations;
+        let mut previous = std::mem::replace(&mut self.image, image);
+        let unused = match self.shown.replace((key, iterations)) {
+            Some((shown, iterations)) => {
+                // events may have changed the iterations of the shown image
+                previous.max_iterations = iterations;
+                self.cache.put(shown, previous)
+            }
+            None => Some(previous),
+        };
+        if self.back.is_none() && !self.options.low_mem {
+            self.back = unused;
+        }
+        self.settings.update_texture = true;
+    }
+
+    /// Counts the finished tiles of the running render and shows its image
+    /// once it is complete, a canceled image is dropped. Returns true if
+    /// the frame shows a new preview.
+    fn finish_render(&mut self) -> bool {
+        let job = match &mut self.job {
+            Some(job) => job,
+            None => return false,
+        };
+        job.tiles += job.progress.try_iter().count();
+        let preview = match job.preview.try_recv() {
+            Ok(rgba) => {
+                self.rgba = rgba;
+                true
+            }
+            Err(_) => false,
+        };
+        if !job.handle.is_finished() {
+            return preview;
+        }
+
+        let job = self.job.take().unwrap();
+        let (context, image, complete) = match job.handle.join() {
+            Ok(result) => result,
+            Err(_) => {
+                println!("Render thread failed, rendering again");
+                let context = RenderContext::new();
+                self.cancel = context.cancel();
+                self.context = Some(context);
+                self.settings.update_image = true;
+                return preview;
+            }
+        };
+        self.context = Some(context);
+        if !complete {
+            // the view changed, the next render shows it
+            timing::record("canceled", job.start, format_args!("Render canceled"));
+            if !self.options.low_mem {
+                self.back = Some(image);
+            }
+            return preview;
+        }
+
+        self.last_render = job.start.elapsed().unwrap();
+        self.stats
+            .record(&job.key.transform, image.max_iterations, self.last_render);
+        self.show(job.key, image);
+        if self.precision_exhausted() {
+            println!("Warning: f64 precision exhausted, the image degrades into blocks");
+        }
+        self.settings.update_texture = true;
+        preview
+    }
+}
+
+/// Colors the image with the settings, or draws the color bar
+pub fn paint(
+    image: &MandelImage,
+    transform: &Transform,
+    settings: &DrawSettings,
+    options: &Options,
+    rgba: &mut RgbaBuffer,
+) {
+    match settings.show_colors {
+        true => draw_color_bar(image, settings, rgba),
+        false => {
+            let colorizer = Colorizer::new(
+                settings,
+                image.max_iterations,
+                settings.split_position,
+                distance_available(transform, settings, options),
+                recorded_available(transform, settings, options),
+            );
+            colorize(image, &colorizer.for_image(image), rgba);
+        }
+    }
+}
+
+/// Returns the formula selected by the settings
+fn selected_formula(settings: &DrawSettings, options: &Options) -> SharedFormula {
+    let formula: SharedFormula = match &options.formula {
+        Some(formula) if settings.use_formula => formula.clone(),
+        _ if settings.use_hybrid => {
+            Arc::new(Hybrid::new(settings.schedule.clone()).expect("Hybrid schedule is empty"))
+        }
+        _ if settings.cubic => Arc::new(Cubic::new(settings.cubic_a)),
+        _ => Arc::new(settings.fractal),
+    };
+
+    let formula: SharedFormula = match settings.morph > 0.0 {
+        true => Arc::new(Morph::new(formula, settings.morph)),
+        false => formula,
+    };
+
+    let formula: SharedFormula = match settings.julia {
+        Some(c) => Arc::new(Julia::new(formula, c)),
+        None => formula,
+    };
+
+    match escape_radius(settings) {
+        Some(radius) => Arc::new(Bailout::new(formula, radius)),
+        None => formula,
+    }
+}
+
+/// Escape radius of the render, the selected radius or a large radius for
+/// smooth coloring, which shows bands of the escape circle with small radii.
+/// None keeps the radius of the formula for integer coloring
+fn escape_radius(settings: &DrawSettings) -> Option<f64> {
+    match settings.smooth {
+        true => settings.bailout.or(Some(SMOOTH_BAILOUT)),
+        false => settings.bailout,
+    }
+}
+
+/// Writes the orbit of c to a file in the configured format
+fn export_orbit(
+    settings: &DrawSettings,
+    options: &Options,
+    c: &Complex<f64>,
+    max_iterations: u32,
+) -> Result<PathBuf, String> {
+    let formula = selected_formula(settings, options);
+    let orbit = mandelbrot::orbit(&*formula, c, max_iterations);
+    let path = export::timestamped_path("mandelbrust_orbit", &options.orbit_format)?;
+    export::write_orbit(&path, c, &orbit)?;
+    Ok(path)
+}
+
+/// Checks if the classic Mandelbrot set is shown without any other
+/// formula or plane map
+fn plain_mandelbrot(transform: &Transform, settings: &DrawSettings, options: &Options) -> bool {
+    let replaced = (settings.use_formula && options.formula.is_some())
+        || settings.use_hybrid
+        || settings.cubic
+        || settings.morph > 0.0
+        || settings.lyapunov
+        || settings.quaternion
+        || settings.julia_map
+        || settings.julia.is_some();
+    !replaced && settings.fractal == Fractal::Mandelbrot && transform.map() == PlaneMap::Identity
+}
+
+/// Maximum iterations for a zoom depth, escape times near the boundary
+/// grow with the number of digits zoomed into, raised to the curve exponent
+pub fn depth_iterations(iterations: u32, zoom: f64, curve: f64) -> u32 {
+    (iterations as f64 * zoom.log10().max(1.0).powf(curve)).round() as u32
+}
+
+/// Turns off the automatic iteration modes after the iterations were set by hand
+fn manual_iterations(settings: &mut DrawSettings) {
+    if settings.auto_iterations || settings.zoom_iterations {
+        println!("Auto iterations: off");
+        settings.auto_iterations = false;
+        settings.zoom_iterations = false;
+    }
+}
+
+/// Checks if the escape times are iterated in f32, only for the plain
+/// Mandelbrot set without recorded orbits and as long as f32 tells the
+/// pixels apart
+fn single_precision(transform: &Transform, settings: &DrawSettings, options: &Options) -> bool {
+    options.single_precision
+        && plain_mandelbrot(transform, settings, options)
+        && settings.trap.is_none()
+        && !settings.atom_domain
+        && Precision::f32_sufficient(transform)
+}
+
+/// Arithmetic used for the view, only the plain Mandelbrot set has
+/// a deep zoom renderer, other fractals pixelate beyond f64 precision
+fn precision(transform: &Transform, settings: &DrawSettings, options: &Options) -> Precision {
+    match plain_mandelbrot(transform, settings, options) {
+        true => Precision::select(transform),
+        false => Precision::F64,
+    }
+}
+
+/// Checks if the trap distances and atom domains are recorded, only in
+/// f64 precision for fractals iterating the complex plane
+fn recorded_available(transform: &Transform, settings: &DrawSettings, options: &Options) -> bool {
+    !settings.lyapunov
+        && !settings.quaternion
+        && !settings.julia_map
+        && precision(transform, settings, options) == Precision::F64
+}
+
+/// Checks if the distance estimate is available, only for the plain
+/// Mandelbrot set in f64 precision
+fn distance_available(transform: &Transform, settings: &DrawSettings, options: &Options) -> bool {
+    plain_mandelbrot(transform, settings, options) && Precision::select(transform) == Precision::F64
+}
+
+/// Generates the image data for the transform with the current settings,
+/// the iterations of the known pixels are kept if they are calculated
+/// with f64. Returns false if the render was canceled before it was complete.
+fn render(
+    context: &mut RenderContext,
+    transform: &Transform,
+    settings: &DrawSettings,
+    options: &Options,
+    exclusion: Option<&PixelRect>,
+    known: Option<&PixelRect>,
+    image: &mut MandelImage,
+) -> bool {
+    image.clear_stats();
+    if settings.lyapunov {
+        lyapunov::generate_image_thread(context, transform, &options.sequence, image);
+    } else if settings.quaternion {
+        quaternion::generate_image_thread(context, transform, quaternion::DEFAULT_C, image);
+        equalize(context, settings, options, exclusion, image);
+    } else if settings.julia_map {
+        let formula = selected_formula(settings, options);
+        mandelbrot::julia_map_thread(context, transform, &formula, image);
+        equalize(context, settings, options, exclusion, image);
+    } else if precision(transform, settings, options) != Precision::F64 {
+        let bailout = escape_radius(settings).unwrap_or(deep::BAILOUT);
+        deep::generate_image_thread(context, transform, bailout, image);
+        equalize(context, settings, options, exclusion, image);
+    } else {
+        let formula = selected_formula(settings, options);
+        if settings.auto_iterations {
+            image.max_iterations =
+                mandelbrot::estimate_iterations(context, transform, &*formula, image);
+        }
+        let kernel: SharedFormula = match single_precision(transform, settings, options) {
+            true => Arc::new(SinglePrecision::new(
+                escape_radius(settings).unwrap_or(deep::BAILOUT),
+            )),
+            false if settings.trap.is_some() || settings.atom_domain => {
+                Arc::new(Recorded::new(formula.clone(), settings.trap))
+            }
+            false => formula.clone(),
+        };
+        match backend(context, transform, settings, options, known) {
+            Some(backend) => {
+                let buffer = backend.render(context, transform, image.max_iterations);
+                image.set_iterations(&buffer.rect(), &buffer.outcomes);
+            }
+            None => mandelbrot::generate_image_thread(
+                context,
+                transform,
+                &kernel,
+                options.jitter,
+                known,
+                image,
+            ),
+        }
+        if context.cancel().is_canceled() {
+            return false;
+        }
+        equalize(context, settings, options, exclusion, image);
+        if options.orbit_stats {
+            let derivative = plain_mandelbrot(transform, settings, options);
+            let trap = settings.trap;
+            mandelbrot::stats_image_thread(context, transform, &formula, trap, derivative, image);
+        }
+        let distance_used =
+            settings.shading > 0.0 || settings.distance_mode || settings.interior_distance;
+        if distance_used && distance_available(transform, settings, options) {
+            mandelbrot::distance_image_thread(
+                context,
+                transform,
+                settings.interior_distance,
+                image,
+            );
+        }
+    }
+    !context.cancel().is_canceled()
+}
+
+/// Backend selected for the view, only for full renders of the plain
+/// Mandelbrot set with the default escape radius and one sample per pixel
+fn backend(
+    context: &RenderContext,
+    transform: &Transform,
+    settings: &DrawSettings,
+    options: &Options,
+    known: Option<&PixelRect>,
+) -> Option<Backend> {
+    let supported = plain_mandelbrot(transform, settings, options)
+        && !single_precision(transform, settings, options)
+        && escape_radius(settings).is_none()
+        && settings.trap.is_none()
+        && !settings.atom_domain
+        && options.jitter <= 1
+        && known.is_none()
+        && context.outcomes.limits.block == 1;
+    options.backend.filter(|_| supported)
+}
+
+/// Render thread limits for the memory mode and the power state
+fn render_limits(options: &Options, power: &PowerSaving) -> Limits {
+    let mut limits = Limits {
+        strategy: options.strategy,
+        deterministic: options.deterministic,
+        ..Limits::default()
+    };
+    if power.active() && !options.deterministic {
+        limits = limits.power_saving();
+    }
+    if options.low_mem {
+        limits = limits.low_memory();
+    }
+    limits
+}
+
+/// Image for the options, low memory mode keeps only the iteration counts
+fn new_image(options: &Options, width: u32, height: u32, max_iterations: u32) -> MandelImage {
+    match options.low_mem {
+        true => MandelImage::low_memory(width, height, max_iterations),
+        false => MandelImage::new(width, height, max_iterations),
+    }
+}
+
+/// Equalizes the iteration counts if the histogram colors are shown,
+/// skipped in low memory mode where they are not available
+fn equalize(
+    context: &mut RenderContext,
+    settings: &DrawSettings,
+    options: &Options,
+    exclusion: Option<&PixelRect>,
+    image: &mut MandelImage,
+) {
+    let histogram = settings.use_histogram || settings.split_position.is_some();
+    if histogram && !options.low_mem {
+        mandelbrot::equalize_image(context, image, exclusion);
+    }
+}
+
+/// Equalizes the shown image when the histogram colors were turned on, a
+/// running render is restarted as it skips the equalization
+fn equalize_shown(
+    context: &mut Option<RenderContext>,
+    settings: &mut DrawSettings,
+    options: &Options,
+    image: &mut MandelImage,
+) {
+    match context {
+        Some(context) if !settings.lyapunov => equalize(
+            context,
+            settings,
+            options,
+            settings.exclusion.as_ref(),
+            image,
+        ),
+        Some(_) => (),
+        None => settings.update_image = settings.use_histogram || settings.split_position.is_some(),
+    }
+}
+
+/// Selects the pixel colors for the current settings
+struct Colorizer {
+    scheme: ColorScheme,
+    /// colors of the iteration counts of the scheme
+    table: Arc<ColorTable>,
+    max_iter: u32,
+    mode: ColorMode,
+    /// raw colors use the fractional iteration count
+    smooth: bool,
+    /// strength of the darkening near the set by the distance estimate
+    shading: f64,
+    /// inside of the set shaded by the interior distance
+    interior: bool,
+    /// escaped pixels darkened by the sign of Im z
+    binary: bool,
+}
+
+enum ColorMode {
+    Raw,
+    Equalized,
+    /// equalized colors left of the divider and raw colors right of it
+    Split(i32),
+    Signed,
+    /// brightness by the distance to the set
+    Distance,
+    /// closest approach of the orbit to the trap
+    Trap,
+    /// hue by the angle of the last z
+    Angle,
+    /// log scaled continuous potential
+    Potential,
+    /// hue by the iteration of the smallest |z|
+    AtomDomain,
+}
+
+impl Colorizer {
+    fn new(
+        settings: &DrawSettings,
+        max_iter: u32,
+        split: Option<i32>,
+        distance: bool,
+        recorded: bool,
+    ) -> Self {
+        let mode = match (split, settings.use_histogram) {
+            _ if settings.lyapunov => ColorMode::Signed,
+            _ if settings.distance_mode && distance => ColorMode::Distance,
+            _ if settings.trap.is_some() && recorded => ColorMode::Trap,
+            _ if settings.atom_domain && recorded => ColorMode::AtomDomain,
+            _ if settings.angle_coloring => ColorMode::Angle,
+            _ if settings.potential_coloring => ColorMode::Potential,
+            (Some(split), _) => ColorMode::Split(split),
+            (None, true) => ColorMode::Equalized,
+            (None, false) => ColorMode::Raw,
+        };
+
+        Colorizer {
+            scheme: settings.color_scheme,
+            table: ColorTable::shared(settings.color_scheme, max_iter, settings.palette_mapping),
+            max_iter,
+            mode,
+            smooth: settings.smooth,
+            shading: match distance {
+                true => settings.shading,
+                false => 0.0,
+            },
+            interior: settings.interior_distance && distance,
+            binary: settings.binary_decomposition && !settings.lyapunov,
+        }
+    }
+
+    /// Falls back to the raw colors for what a low memory image lacks,
+    /// the values, trap distances and distance estimates are only there if
+    /// they were rendered
+    fn for_image(mut self, image: &MandelImage) -> Self {
+        if image.is_low_memory() {
+            self.smooth = false;
+            self.binary = false;
+            if let ColorMode::Equalized
+            | ColorMode::Split(_)
+            | ColorMode::Angle
+            | ColorMode::Potential
+            | ColorMode::AtomDomain = self.mode
+            {
+                self.mode = ColorMode::Raw;
+            }
+        }
+        if image.value.is_empty() {
+            if let ColorMode::Signed = self.mode {
+                self.mode = ColorMode::Raw;
+            }
+        }
+        if image.interior.is_empty() {
+            self.interior = false;
+        }
+        if image.distance.is_empty() {
+            self.shading = 0.0;
+            if let ColorMode::Distance = self.mode {
+                self.mode = ColorMode::Raw;
+            }
+        }
+        if image.trap.is_empty() {
+            if let ColorMode::Trap = self.mode {
+                self.mode = ColorMode::Raw;
+            }
+        }
+        self
+    }
+
+    fn paint(&self, n: u32, converged: bool) -> Color {
+        match converged {
+            true => self.table.converged(n),
+            false => self.table.color(n),
+        }
+    }
+
+    /// Raw color of the pixel, interpolated if smooth coloring is on
+    fn paint_raw(&self, image: &MandelImage, i: usize) -> Color {
+        let (n, converged) = (image.iterations.get(i), image.converged[i]);
+        match self.smooth && !converged && n < self.max_iter {
+            true => self.table.smooth(image.smooth[i]),
+            false => self.paint(n, converged),
+        }
+    }
+
+    /// Color of the pixel, the orbit statistics are only recorded for some
+    /// renders, modes needing them fall back to the raw colors without
+    fn color(&self, image: &MandelImage, i: usize) -> Color {
+        let (n, converged) = (image.iterations.get(i), image.converged[i]);
+        if self.interior && image.interior[i] > 0.0 {
+            return palette::color_distance(image.interior[i], INTERIOR_DISTANCE);
+        }
+        let distance = image.distance.get(i).copied().unwrap_or(0.0);
+
+        let color = match self.mode {
+            ColorMode::Raw => self.paint_raw(image, i),
+            ColorMode::Equalized => self.paint(image.iterations_equalized[i], converged),
+            ColorMode::Split(split) if image.position(i).0 < split => {
+                self.paint(image.iterations_equalized[i], converged)
+            }
+            ColorMode::Split(_) => self.paint_raw(image, i),
+            ColorMode::Signed => palette::color_signed(self.scheme, image.value[i], 2.0),
+            ColorMode::Distance => palette::color_distance(distance, SHADING_DISTANCE),
+            ColorMode::Trap => palette::color_trap(self.scheme, image.trap[i], TRAP_RANGE),
+            ColorMode::Angle | ColorMode::Potential if converged || n >= self.max_iter => {
+                self.paint_raw(image, i)
+            }
+            ColorMode::Angle => {
+                palette::color_angle(image.z[i].arg(), image.smooth[i], ANGLE_PERIOD)
+            }
+            ColorMode::Potential => {
+                palette::color_potential(self.scheme, log_potential(n, image.z[i]), self.max_iter)
+            }
+            ColorMode::AtomDomain => palette::color_atom_domain(image.period[i], image.min_norm[i]),
+        };
+
+        // darken pixels close to the set to accentuate the filaments
+        let color = match self.shading > 0.0 {
+            true => {
+                let closeness = 1.0 - (distance.max(0.0) / SHADING_DISTANCE).min(1.0).sqrt();
+                palette::shade(color, 1.0 - self.shading * closeness)
+            }
+            false => color,
+        };
+
+        // the sign of Im z at escape splits the bands along the external rays
+        let escaped = !converged && n < self.max_iter;
+        match self.binary && escaped && image.z[i].im < 0.0 {
+            true => palette::shade(color, BINARY_SHADE),
+            false => color,
+        }
+    }
+}
+
+/// Negative base 2 logarithm of the potential `ln|z| / 2^n` of an escaped
+/// pixel, calculated in logarithms as 2^n overflows for deep escapes
+fn log_potential(iterations: u32, z: Complex<f64>) -> f64 {
+    let log_z = z.norm().ln();
+    match log_z > 0.0 {
+        true => iterations as f64 - log_z.log2(),
+        false => iterations as f64,
+    }
+}
+
+/// Draws the gradient of the color scheme with the iteration histogram of
+/// the image on top, the curve shows which colors the current view uses
+fn draw_color_bar(image: &MandelImage, settings: &DrawSettings, buffer: &mut RgbaBuffer) {
+    let start = SystemTime::now();
+
+    let (width, height) = (buffer.width as usize, buffer.height as usize);
+    let max = image.max_iterations;
+    let mut counts = vec![0u32; max as usize];
+    if !settings.lyapunov {
+        let mut count = |n: u32| {
+            if n < max {
+                counts[n as usize] += 1;
+            }
+        };
+        match settings.use_histogram {
+            true => image.iterations_equalized.iter().for_each(|&n| count(n)),
+            false => image.iterations.iter().for_each(count),
+        }
+    }
+
+    // each column covers at least one iteration count
+    let first = |x: usize| (x as u64 * max as u64 / width as u64) as usize;
+    let histogram: Vec<u32> = (0..width)
+        .map(|x| {
+            counts[first(x)..first(x + 1).max(first(x) + 1)]
+                .iter()
+                .sum()
+        })
+        .collect();
+
+    // log scale, so rare iteration counts still show up
+    let peak = (*histogram.iter().max().unwrap_or(&0) as f64).ln_1p();
+    let curve: Vec<usize> = histogram
+        .iter()
+        .map(|&count| match peak > 0.0 {
+            true => height - 1 - ((count as f64).ln_1p() / peak * (height - 1) as f64) as usize,
+            false => height - 1,
+        })
+        .collect();
+
+    let table = ColorTable::shared(settings.color_scheme, max, settings.palette_mapping);
+    for x in 0..width {
+        let c = table.color(first(x) as u32);
+        let (top, bottom) = match x {
+            0 => (curve[0], curve[0]),
+            _ => (curve[x].min(curve[x - 1]), curve[x].max(curve[x - 1])),
+        };
+        for y in 0..height {
+            let rgba = match peak > 0.0 && y >= top && y <= bottom {
+                true => [255, 255, 255, 255],
+                false => [c.r, c.g, c.b, c.a],
+            };
+            let index = (y * width + x) * 4;
+            buffer.data[index..index + 4].copy_from_slice(&rgba);
+        }
+    }
+
+    timing::record("color", start, format_args!("Color bar drawn"));
+}
+
+/// Renders the current view into an image file of the export size,
+/// the view is extended to match the aspect ratio of the export
+fn export_image(
+    transform: &Transform,
+    settings: &DrawSettings,
+    options: &Options,
+    max_iterations: u32,
+) -> Result<PathBuf, String> {
+    let start = SystemTime::now();
+
+    let (width, height) = options
+        .export_size
+        .unwrap_or_else(|| transform.window_size());
+    let export_transform = Viewport::new(width, height).fit(transform);
+    let center = transform.center();
+    let metadata = [
+        ("Palette", settings.color_scheme.name().to_string()),
+        (
+            "Palette stops",
+            settings.color_scheme.palette().definition(),
+        ),
+        (
+            "Palette offset",
+            settings.palette_mapping.offset.to_string(),
+        ),
+        ("Palette scale", settings.palette_mapping.scale.to_string()),
+        ("Center", format!("{},{}", center.re, center.im)),
+        ("Zoom", format!("{:e}", transform.zoom_factor())),
+        ("Iterations", max_iterations.to_string()),
+    ];
+    let path = export::timestamped_path("mandelbrust", &options.export_format)?;
+    if let Some(rows) = options.export_bands {
+        export_bands(
+            transform,
+            &export_transform,
+            settings,
+            options,
+            max_iterations,
+            rows,
+            &path,
+            &metadata,
+        )?;
+        timing::record("export", start, format_args!("Image exported in bands"));
+        return Ok(path);
+    }
+
+    let exclusion = settings
+        .exclusion
+        .map(|rect| map_exclusion(&rect, transform, &export_transform));
+    let mut image = MandelImage::new(width, height, max_iterations);
+    render(
+        &mut RenderContext::new(),
+        &export_transform,
+        settings,
+        options,
+        exclusion.as_ref(),
+        None,
+        &mut image,
+    );
+
+    let colorizer = Colorizer::new(
+        settings,
+        image.max_iterations,
+        None,
+        distance_available(&export_transform, settings, options),
+        recorded_available(&export_transform, settings, options),
+    );
+    let mut buffer = RgbaBuffer::new(width, height);
+    colorize(&image, &colorizer, &mut buffer);
+
+    export::write_image(
+        &path,
+        buffer.width,
+        buffer.height,
+        &buffer.data,
+        options.quality,
+        &metadata,
+    )?;
+
+    timing::record("export", start, format_args!("Image exported"));
+    Ok(path)
+}
+
+/// Pixels of the preview that fixes the iteration limit and the histogram
+/// of exports in bands
+const BAND_PREVIEW_PIXELS: f64 = 1_000_000.0;
+
+/// Renders the export in bands of rows that are colored and appended to
+/// the PNG file, only one band is in memory at a time. A small preview of
+/// the whole view gives the iteration limit and the equalization, so the
+/// bands match each other.
+#[allow(clippy::too_many_arguments)]
+fn export_bands(
+    transform: &Transform,
+    export_transform: &Transform,
+    settings: &DrawSettings,
+    options: &Options,
+    max_iterations: u32,
+    rows: u32,
+    path: &Path,
+    metadata: &[(&str, String)],
+) -> Result<(), String> {
+    if options.export_format != "png" {
+        return Err("Exports in bands are only written as PNG".to_string());
+    }
+    if export_transform.projection() != Projection::Flat {
+        return Err("Exports in bands need the flat projection".to_string());
+    }
+    let (width, height) = export_transform.window_size();
+    let mut context = RenderContext::new();
+
+    let shrink = (BAND_PREVIEW_PIXELS / (width as f64 * height as f64))
+        .sqrt()
+        .min(1.0);
+    let preview_size = |size: u32| ((size as f64 * shrink) as u32).max(1);
+    let preview_transform = Viewport::new(preview_size(width), preview_size(height)).fit(transform);
+    let exclusion = settings
+        .exclusion
+        .map(|rect| map_exclusion(&rect, transform, &preview_transform));
+    let (preview_width, preview_height) = preview_transform.window_size();
+    let mut preview = MandelImage::new(preview_width, preview_height, max_iterations);
+    render(
+        &mut context,
+        &preview_transform,
+        settings,
+        options,
+        exclusion.as_ref(),
+        None,
+        &mut preview,
+    );
+    let max_iterations = preview.max_iterations;
+    let equalized = settings.use_histogram || settings.split_position.is_some();
+    drop(preview);
+
+    // the bands keep the iterations and the equalization of the preview
+    let mut band_settings = settings.clone();
+    band_settings.auto_iterations = false;
+    band_settings.use_histogram = false;
+    band_settings.split_position = None;
+    let colorizer = Colorizer::new(
+        settings,
+        max_iterations,
+        None,
+        distance_available(export_transform, settings, options),
+        recorded_available(export_transform, settings, options),
+    );
+
+    let mut png = export::PngStream::create(path, width, height, metadata)?;
+    for top in (0..height).step_by(rows as usize) {
+        let band_rows = rows.min(height - top);
+        let band_transform = export_transform.band(top as i32, band_rows);
+        let mut image = MandelImage::new(width, band_rows, max_iterations);
+        render(
+            &mut context,
+            &band_transform,
+            &band_settings,
+            options,
+            None,
+            None,
+            &mut image,
+        );
+        if equalized && !options.low_mem {
+            mandelbrot::apply_equalization(&context, &mut image);
+        }
+        let mut buffer = RgbaBuffer::new(width, band_rows);
+        colorize(&image, &colorizer, &mut buffer);
+        png.write_rows(&buffer.data)?;
+    }
+    png.finish()
+}
+
+/// Maps the exclusion zone of the screen onto an export of the same view,
+/// the export transform shares the center and differs in scale
+fn map_exclusion(rect: &PixelRect, screen: &Transform, export: &Transform) -> PixelRect {
+    let ratio = export.scale() / screen.scale();
+    let (screen_width, screen_height) = screen.window_size();
+    let (export_width, export_height) = export.window_size();
+    let map = |pos: i32, screen: u32, export: u32| {
+        ((pos as f64 - screen as f64 / 2.0) * ratio + export as f64 / 2.0).round() as i32
+    };
+
+    PixelRect::new(
+        (
+            map(rect.left, screen_width, export_width),
+            map(rect.top, screen_height, export_height),
+        ),
+        (
+            map(rect.right, screen_width, export_width),
+            map(rect.bottom, screen_height, export_height),
+        ),
+    )
+}
+
+/// Colors all pixels of the image into the buffer on multiple threads,
+/// used for both the screen and the exported images so they look the same
+fn colorize(image: &MandelImage, colorizer: &Colorizer, buffer: &mut RgbaBuffer) {
+    let start = SystemTime::now();
+
+    let width = image.width as usize;
+    let rows_per_thread = (image.height as usize)
+        .div_ceil(workers::global().size())
+        .max(1);
+    let rows: Vec<Row> = image.rows().collect();
+    thread::scope(|scope| {
+        let bytes = buffer.data.chunks_mut(rows_per_thread * width * 4);
+        for (rows, bytes) in rows.chunks(rows_per_thread).zip(bytes) {
+            scope.spawn(move || {
+                let pixels = rows.iter().flat_map(|row| row.pixels.clone());
+                for (i, pixel) in pixels.zip(bytes.chunks_mut(4)) {
+                    let c = colorizer.color(image, i);
+                    pixel.copy_from_slice(&[c.r, c.g, c.b, c.a]);
+                }
+            });
+        }
+    });
+
+    timing::record("color", start, format_args!("Image colored"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ticks until the renders started by the events are shown, returns
+    /// true if there was a new frame
+    fn frame(app: &mut App) -> bool {
+        let mut new_frame = false;
+        loop {
+            new_frame |= app.tick();
+            if !app.rendering() && !app.settings.update_image {
+                return new_frame;
+            }
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_low_memory() {
+        let mut options = Options::parse(std::iter::empty()).unwrap();
+        options.width = 40;
+        options.height = 30;
+        options.low_mem = true;
+        let mut app = App::new(options);
+        assert!(frame(&mut app));
+        app.handle_event(MandelEvent::ToggleSmooth);
+        app.handle_event(MandelEvent::Zoom(2.0));
+        assert!(frame(&mut app));
+
+        assert!(app.image.is_low_memory());
+        assert!(app.image.iterations_equalized.is_empty());
+        assert!(app.back.is_none());
+    }
+
+    #[test]
+    fn test_headless() {
+        let mut options = Options::parse(std::iter::empty()).unwrap();
+        options.width = 40;
+        options.height = 30;
+        options
+            .macros
+            .push(crate::macros::parse_macro("F2=iterations 300; zoom 2").unwrap());
+        let mut app = App::new(options);
+
+        assert!(frame(&mut app));
+        assert_eq!(app.frame().data.len(), 40 * 30 * 4);
+        assert!(!app.tick());
+
+        // the palette mapping only colors the image again
+        let before = app.frame().data.clone();
+        app.handle_event(MandelEvent::ShiftPalette(0.5));
+        assert!(!app.settings.update_image && !app.rendering());
+        assert!(frame(&mut app));
+        assert!(app.frame().data != before);
+        app.handle_event(MandelEvent::ShiftPalette(0.5));
+
+        app.handle_event(MandelEvent::Zoom(2.0));
+        assert!(frame(&mut app));
+
+        app.handle_event(MandelEvent::ToggleDistance);
+        assert!(frame(&mut app));
+        let data = &app.frame().data;
+        assert!(data.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2]));
+        assert!(data.chunks(4).any(|p| p[0] > 0));
+        app.handle_event(MandelEvent::ToggleDistance);
+
+        app.handle_event(MandelEvent::ZoomTo(1e6));
+        assert!((app.transform.zoom_factor() / 1e6 - 1.0).abs() < 1e-9);
+        assert_eq!(app.image.max_iterations, 6 * 150);
+        assert!(frame(&mut app));
+
+        app.handle_event(MandelEvent::ToggleColorschemes);
+        assert!(frame(&mut app));
+        assert!(app.settings().show_colors);
+
+        assert!(app.status().contains("f64"));
+        app.handle_event(MandelEvent::ShowStats);
+        assert!(app.status().contains("renders in"));
+        app.handle_event(MandelEvent::ShowStats);
+        assert!(!app.status().contains("renders in"));
+        app.handle_event(MandelEvent::NextFractal);
+        app.handle_event(MandelEvent::Zoom(1e15));
+        frame(&mut app);
+        assert!(app.precision_exhausted());
+
+        app.handle_event(MandelEvent::Macro("F2".to_string()));
+        assert_eq!(app.image.max_iterations, 300);
+
+        app.handle_event(MandelEvent::Quit);
+        assert!(!app.running());
+    }
+
+    #[test]
+    fn test_render_faults() {
+        use crate::mandelbrot::Fault;
+
+        let new_app = || {
+            let mut options = Options::parse(std::iter::empty()).unwrap();
+            options.width = 40;
+            options.height = 30;
+            App::new(options)
+        };
+
+        let mut clean = new_app();
+        let mut faulty = new_app();
+        faulty.context.as_mut().unwrap().outcomes.faults =
+            vec![Some(Fault::Panic), Some(Fault::Slow(20)), Some(Fault::Drop)];
+        for app in [&mut clean, &mut faulty] {
+            assert!(frame(app));
+            app.handle_event(MandelEvent::Zoom(2.0));
+            assert!(frame(app));
+        }
+        assert!(clean.frame().data == faulty.frame().data);
+    }
+
+    #[test]
+    fn test_golden_images() {
+        // FNV-1a hashes of the frames of standard views, a change of the
+        // math kernels that alters any pixel changes the hash
+        let golden = [
+            ("-0.5,0", "3", 0x1415_c12f_3fe2_793e),
+            ("-0.745,0.113", "0.01", 0xbbaa_3212_2329_72e2),
+            ("-1.7685,0", "0.002", 0xc9d4_a92b_ad71_6c27),
+        ];
+        for (center, height, hash) in golden {
+            let args = [
+                "--width",
+                "80",
+                "--height",
+                "60",
+                "--iterations",
+                "500",
+                "--center",
+                center,
+                "--view-height",
+                height,
+            ];
+            let mut options = Options::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+            options.deterministic = true;
+            options.log_timings = false;
+            let mut app = App::new(options);
+            assert!(frame(&mut app));
+            let frame_hash = app
+                .frame()
+                .data
+                .iter()
+                .fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+                    (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+                });
+            assert_eq!(
+                frame_hash, hash,
+                "view at {} with height {}",
+                center, height
+            );
+        }
+    }
+
+    #[test]
+    fn test_background_render() {
+        let mut options = Options::parse(std::iter::empty()).unwrap();
+        options.width = 160;
+        options.height = 120;
+        let mut direct = App::new(options.clone());
+        direct.handle_event(MandelEvent::Zoom(2.0));
+        assert!(frame(&mut direct));
+
+        // events are handled while the first render runs and replace it
+        let mut app = App::new(options);
+        app.tick();
+        assert!(app.rendering());
+        assert!(app.status().contains("rendering"));
+        app.handle_event(MandelEvent::Zoom(2.0));
+        assert!(frame(&mut app));
+        assert!(!app.status().contains("rendering"));
+        assert!(app.frame().data == direct.frame().data);
+
+        // slow renders show a preview of blocks of pixels first, the view
+        // was never shown so the cache can't skip the render
+        app.last_render = PREVIEW_AFTER;
+        app.handle_event(MandelEvent::Zoom(0.25));
+        app.tick();
+        let preview = app.job.as_ref().unwrap().preview.recv().unwrap();
+        let block = PREVIEW_BLOCK as usize * 4;
+        assert!(preview
+            .data
+            .chunks(block)
+            .all(|pixels| pixels.chunks(4).all(|p| p == &pixels[..4])));
+        assert!(frame(&mut app));
+        assert!(app.frame().data != preview.data);
+        // the full pass refining the preview renders the same image
+        direct.last_render = Duration::ZERO;
+        direct.handle_event(MandelEvent::Zoom(0.25));
+        assert!(frame(&mut direct));
+        assert!(app.frame().data == direct.frame().data);
+    }
+
+    #[test]
+    fn test_image_cache() {
+        let mut options = Options::parse(std::iter::empty()).unwrap();
+        options.width = 40;
+        options.height = 30;
+        let mut app = App::new(options);
+        assert!(frame(&mut app));
+        let first = app.frame().data.clone();
+        app.handle_event(MandelEvent::Zoom(2.0));
+        assert!(frame(&mut app));
+
+        // zooming back out shows the cached image without rendering
+        app.handle_event(MandelEvent::Zoom(0.5));
+        assert!(app.tick());
+        assert!(!app.rendering());
+        assert!(app.frame().data == first);
+
+        app.handle_event(MandelEvent::ChangeIterations(2.0));
+        app.tick();
+        assert!(app.rendering());
+    }
+
+    #[test]
+    fn test_cached_histogram() {
+        let mut options = Options::parse(std::iter::empty()).unwrap();
+        options.width = 40;
+        options.height = 30;
+        let mut app = App::new(options.clone());
+        assert!(frame(&mut app));
+        app.handle_event(MandelEvent::Zoom(2.0));
+        assert!(frame(&mut app));
+        app.handle_event(MandelEvent::ToggleHistogram);
+        frame(&mut app);
+
+        // the cached image was shown without histogram colors
+        app.handle_event(MandelEvent::Zoom(0.5));
+        assert!(app.tick());
+        assert!(!app.rendering());
+
+        let mut fresh = App::new(options);
+        fresh.handle_event(MandelEvent::ToggleHistogram);
+        frame(&mut fresh);
+        assert!(app.image.iterations_equalized == fresh.image.iterations_equalized);
+        assert!(app.frame().data == fresh.frame().data);
+    }
+
+    #[test]
+    fn test_julia_map() {
+        let mut options = Options::parse(std::iter::empty()).unwrap();
+        options.width = 128;
+        options.height = 64;
+        let mut app = App::new(options);
+        frame(&mut app);
+        let zoom = app.transform.zoom_factor();
+
+        app.handle_event(MandelEvent::ToggleJuliaMap);
+        assert!(frame(&mut app));
+        // the corner of every cell starts outside the Julia set
+        assert!(app.image.iterations(0, 0) < 5);
+        assert!(app.image.iterations(64, 0) < 5);
+
+        app.handle_event(MandelEvent::Center(96, 40));
+        let c = app.settings.julia.unwrap();
+        assert_eq!(
+            c,
+            mandelbrot::julia_map_parameter(&app.parameter_view.clone().unwrap(), 70, 63)
+        );
+        assert!(!app.settings.julia_map);
+        assert!(frame(&mut app));
+
+        app.handle_event(MandelEvent::ToggleJuliaMap);
+        assert!(app.settings.julia.is_none() && !app.settings.julia_map);
+        assert_eq!(app.transform.zoom_factor(), zoom);
+    }
+
+    #[test]
+    fn test_escape_radius() {
+        let mut settings = DrawSettings::new();
+        assert_eq!(escape_radius(&settings), None);
+        settings.smooth = true;
+        assert_eq!(escape_radius(&settings), Some(SMOOTH_BAILOUT));
+        settings.bailout = Some(BAILOUTS[2]);
+        assert_eq!(escape_radius(&settings), Some(BAILOUTS[2]));
+    }
+
+    #[test]
+    fn test_depth_iterations() {
+        assert_eq!(depth_iterations(100, 0.5, 1.0), 100);
+        assert_eq!(depth_iterations(100, 1e6, 1.0), 600);
+        assert_eq!(depth_iterations(100, 1e6, 2.0), 3600);
+        assert_eq!(depth_iterations(100, 1e6, 0.5), 245);
+    }
+}
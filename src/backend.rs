@@ -0,0 +1,262 @@
+use crate::fractal::Outcome;
+use crate::mandelbrot::{self, RenderContext};
+use crate::timing;
+use crate::types::{PixelRect, Transform};
+
+use num::complex::Complex;
+
+use std::time::SystemTime;
+
+/// Pixels iterated together by the SIMD backend
+pub const LANES: usize = 4;
+
+/// Outcomes of all pixels of a view, row by row
+pub struct IterationBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub outcomes: Vec<Outcome>,
+}
+
+impl IterationBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        IterationBuffer {
+            width,
+            height,
+            outcomes: vec![Outcome::default(); width as usize * height as usize],
+        }
+    }
+
+    /// The whole view as a pixel rectangle
+    pub fn rect(&self) -> PixelRect {
+        PixelRect::new((0, 0), (self.width as i32, self.height as i32))
+    }
+}
+
+/// Calculates the escape times of the plain Mandelbrot set for a view,
+/// implementations are interchangeable and give the same outcomes
+pub trait RenderBackend {
+    fn name(&self) -> &'static str;
+
+    fn render(
+        &self,
+        context: &mut RenderContext,
+        transform: &Transform,
+        max_iterations: u32,
+    ) -> IterationBuffer;
+}
+
+/// One pixel at a time with `mandelbrot::mandel`
+pub struct Scalar;
+
+impl RenderBackend for Scalar {
+    fn name(&self) -> &'static str {
+        "scalar"
+    }
+
+    fn render(
+        &self,
+        context: &mut RenderContext,
+        transform: &Transform,
+        max_iterations: u32,
+    ) -> IterationBuffer {
+        let (width, height) = transform.window_size();
+        let mut buffer = IterationBuffer::new(width, height);
+        let trans = transform.clone();
+        mandelbrot::generate_tiles(
+            &mut context.outcomes,
+            width,
+            height,
+            move |x, y| mandelbrot::mandel(&trans.pos_to_complex(x, y), max_iterations),
+            |tile, outcomes| {
+                let tile_width = tile.width() as usize;
+                for (y, row) in (tile.top..tile.bottom).zip(outcomes.chunks(tile_width)) {
+                    let start = y as usize * width as usize + tile.left as usize;
+                    buffer.outcomes[start..start + tile_width].copy_from_slice(row);
+                }
+            },
+        );
+        buffer
+    }
+}
+
+/// LANES neighboring pixels of a row at a time with `mandel_lanes`
+pub struct Simd;
+
+impl RenderBackend for Simd {
+    fn name(&self) -> &'static str {
+        "simd"
+    }
+
+    fn render(
+        &self,
+        context: &mut RenderContext,
+        transform: &Transform,
+        max_iterations: u32,
+    ) -> IterationBuffer {
+        let (width, height) = transform.window_size();
+        let mut buffer = IterationBuffer::new(width, height);
+        let trans = transform.clone();
+        // each value of the tiles holds the pixels of a group of lanes
+        let groups = (width as usize).div_ceil(LANES);
+        mandelbrot::generate_tiles(
+            &mut context.lanes,
+            groups as u32,
+            height,
+            move |group, y| {
+                let mut c = [Complex::new(0.0, 0.0); LANES];
+                for (lane, c) in c.iter_mut().enumerate() {
+                    *c = trans.pos_to_complex(group * LANES as i32 + lane as i32, y);
+                }
+                mandel_lanes(&c, max_iterations)
+            },
+            |tile, lanes| {
+                let tile_width = tile.width() as usize;
+                for (y, row) in (tile.top..tile.bottom).zip(lanes.chunks(tile_width)) {
+                    let start = y as usize * width as usize;
+                    let first = tile.left as usize * LANES;
+                    // the last group may reach beyond the right border
+                    let end = (first + tile_width * LANES).min(width as usize);
+                    let pixels = &mut buffer.outcomes[start + first..start + end];
+                    for (pixel, outcome) in pixels.iter_mut().zip(row.iter().flatten()) {
+                        *pixel = *outcome;
+                    }
+                }
+            },
+        );
+        buffer
+    }
+}
+
+/// Iterates LANES points in lockstep on plain arrays, so each step can
+/// run in vector registers. Lanes that escaped keep their last z while
+/// the others continue. Gives the same outcomes as `mandelbrot::mandel`.
+pub fn mandel_lanes(c: &[Complex<f64>; LANES], max_iter: u32) -> [Outcome; LANES] {
+    let mut outcomes = [Outcome::new(max_iter, false); LANES];
+    let mut active = [true; LANES];
+    for lane in 0..LANES {
+        active[lane] = !mandelbrot::in_set(&c[lane]);
+    }
+    let (mut re, mut im) = ([0.0; LANES], [0.0; LANES]);
+    let (mut re2, mut im2) = ([0.0; LANES], [0.0; LANES]);
+
+    for iter in 0..max_iter {
+        for lane in 0..LANES {
+            if active[lane] && re2[lane] + im2[lane] >= 4.0 {
+                active[lane] = false;
+                outcomes[lane] = Outcome::escaped(iter, Complex::new(re[lane], im[lane]));
+            }
+        }
+        if !active.contains(&true) {
+            break;
+        }
+        for lane in 0..LANES {
+            let next_im = (re[lane] + re[lane]) * im[lane] + c[lane].im;
+            let next_re = re2[lane] - im2[lane] + c[lane].re;
+            if active[lane] {
+                re[lane] = next_re;
+                im[lane] = next_im;
+                re2[lane] = next_re * next_re;
+                im2[lane] = next_im * next_im;
+            }
+        }
+    }
+    outcomes
+}
+
+/// Backends selectable at runtime
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Backend {
+    Scalar,
+    Simd,
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        self.renderer().name()
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Backend::Scalar => Backend::Simd,
+            Backend::Simd => Backend::Scalar,
+        }
+    }
+
+    pub fn renderer(self) -> Box<dyn RenderBackend> {
+        match self {
+            Backend::Scalar => Box::new(Scalar),
+            Backend::Simd => Box::new(Simd),
+        }
+    }
+
+    /// Renders the view with the backend and logs the time for comparing
+    /// the backends
+    pub fn render(
+        self,
+        context: &mut RenderContext,
+        transform: &Transform,
+        max_iterations: u32,
+    ) -> IterationBuffer {
+        let start = SystemTime::now();
+        let buffer = self.renderer().render(context, transform, max_iterations);
+        timing::record(
+            "generate",
+            start,
+            format_args!(
+                "Generated image with the {} backend and max iterations {}",
+                self.name(),
+                max_iterations
+            ),
+        );
+        buffer
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "scalar" => Ok(Backend::Scalar),
+            "simd" => Ok(Backend::Simd),
+            _ => Err(format!(
+                "Unknown backend '{}', expected scalar or simd",
+                name
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backends() {
+        let c = [
+            Complex::new(-0.75, 0.1),
+            Complex::new(0.0, 0.0),
+            Complex::new(0.3, 0.5),
+            Complex::new(2.0, 2.0),
+        ];
+        for (lane, outcome) in mandel_lanes(&c, 500).iter().enumerate() {
+            let expected = mandelbrot::mandel(&c[lane], 500);
+            assert_eq!(outcome.iterations, expected.iterations);
+            assert_eq!(outcome.z, expected.z);
+        }
+
+        // a width that is not a multiple of the lanes
+        let mut transform = Transform::new((61, 40));
+        transform.zoom(3.0);
+        let mut context = RenderContext::new();
+        let scalar = Backend::Scalar.render(&mut context, &transform, 200);
+        let simd = Backend::Simd.render(&mut context, &transform, 200);
+        assert_eq!(simd.outcomes.len(), 61 * 40);
+        let iterations = |buffer: &IterationBuffer| -> Vec<u32> {
+            buffer.outcomes.iter().map(|o| o.iterations).collect()
+        };
+        assert_eq!(iterations(&scalar), iterations(&simd));
+        assert_eq!("simd".parse(), Ok(Backend::Simd));
+        assert_eq!(Backend::Simd.next(), Backend::Scalar);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::types::{MandelImage, Transform};
+
+use std::collections::VecDeque;
+
+/// What a rendered image shows: the view, the iteration limit and the
+/// generation of the other settings it was rendered with
+#[derive(Clone)]
+pub struct ImageKey {
+    pub transform: Transform,
+    pub max_iterations: u32,
+    pub generation: u32,
+}
+
+impl ImageKey {
+    /// Whole pixels the view moved since the previous key, None if
+    /// anything but the center changed
+    pub fn pan_offset(&self, previous: &ImageKey) -> Option<(i32, i32)> {
+        let same_settings = self.max_iterations == previous.max_iterations
+            && self.generation == previous.generation;
+        match same_settings {
+            true => self.transform.pan_offset(&previous.transform),
+            false => None,
+        }
+    }
+
+    /// Pixels of the view an image moved by the offset still shows
+    pub fn overlap(&self, (dx, dy): (i32, i32)) -> i32 {
+        let (width, height) = self.transform.window_size();
+        (width as i32 - dx.abs()) * (height as i32 - dy.abs())
+    }
+}
+
+/// Images of recently shown views, so zooming back out or returning to
+/// a view shows it without rendering it again
+pub struct ImageCache {
+    /// most recently used first
+    entries: VecDeque<(ImageKey, MandelImage)>,
+    capacity: usize,
+}
+
+impl ImageCache {
+    pub fn new(capacity: usize) -> Self {
+        ImageCache {
+            entries: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Keeps the image, images of older settings are dropped. Returns the
+    /// least recently used image if the cache is full, for reuse.
+    pub fn put(&mut self, key: ImageKey, image: MandelImage) -> Option<MandelImage> {
+        self.entries
+            .retain(|(cached, _)| cached.generation == key.generation);
+        self.entries.push_front((key, image));
+        match self.entries.len() > self.capacity {
+            true => self.entries.pop_back().map(|(_, image)| image),
+            false => None,
+        }
+    }
+
+    /// Takes the image showing exactly the view of the key out of the cache
+    pub fn take(&mut self, key: &ImageKey) -> Option<MandelImage> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(cached, _)| key.pan_offset(cached) == Some((0, 0)))?;
+        self.entries.remove(index).map(|(_, image)| image)
+    }
+
+    /// The cached image showing most of the view of the key, with the
+    /// offset of the view to its pixels
+    pub fn overlapping(&self, key: &ImageKey) -> Option<(&MandelImage, (i32, i32))> {
+        self.entries
+            .iter()
+            .filter_map(|(cached, image)| key.pan_offset(cached).map(|offset| (image, offset)))
+            .max_by_key(|(_, offset)| key.overlap(*offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let key = |zoom: f64, generation: u32| {
+            let mut transform = Transform::new((8, 6));
+            transform.zoom(zoom);
+            ImageKey {
+                transform,
+                max_iterations: 100,
+                generation,
+            }
+        };
+        let mut cache = ImageCache::new(2);
+        assert!(cache
+            .put(key(1.0, 0), MandelImage::new(8, 6, 100))
+            .is_none());
+        assert!(cache
+            .put(key(2.0, 0), MandelImage::new(8, 6, 100))
+            .is_none());
+        // the least recently used view is dropped
+        assert!(cache
+            .put(key(4.0, 0), MandelImage::new(8, 6, 100))
+            .is_some());
+        assert!(cache.take(&key(1.0, 0)).is_none());
+        assert!(cache.take(&key(2.0, 0)).is_some());
+        assert_eq!(cache.entries.len(), 1);
+
+        let other = ImageKey {
+            max_iterations: 200,
+            ..key(4.0, 0)
+        };
+        assert!(cache.take(&other).is_none());
+        // views moved by whole pixels reuse the cached image showing most
+        // of them
+        assert!(cache.overlapping(&key(2.0, 0)).is_none());
+        let mut moved = key(4.0, 0);
+        moved.transform.recenter(1, 4);
+        let (_, offset) = cache.overlapping(&moved).unwrap();
+        assert_eq!((offset, moved.overlap(offset)), ((-3, 1), 25));
+        // images of older settings are dropped
+        cache.put(key(1.0, 1), MandelImage::new(8, 6, 100));
+        assert!(cache.take(&key(4.0, 0)).is_none());
+        assert_eq!(cache.entries.len(), 1);
+    }
+}
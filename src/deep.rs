@@ -0,0 +1,705 @@
+use crate::double::{self, DoubleDouble};
+use crate::fractal::Outcome;
+use crate::mandelbrot::{self, RenderContext};
+use crate::timing;
+use crate::types::MandelImage;
+use crate::types::Transform;
+
+use num::bigint::BigInt;
+use num::complex::Complex;
+use num::{Signed, ToPrimitive, Zero};
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Smallest pixel size in units of the last place of the coordinates,
+/// smaller pixels need the next precision
+const PIXEL_ULPS: f64 = 16.0;
+
+/// Extra fraction bits kept beyond the pixel size
+const GUARD_BITS: u32 = 64;
+
+/// Largest decimal exponent and number of digits accepted when parsing,
+/// far beyond any reachable zoom but small enough to parse instantly
+const MAX_DIGITS: usize = 4000;
+
+/// Escape radius of the deep zoom renderer unless set by the user
+pub const BAILOUT: f64 = 2.0;
+
+/// Largest relative size of the truncated series terms
+const SERIES_TOLERANCE: f64 = 1e-6;
+
+/// Arithmetic of the pixel coordinates, from fastest to most precise
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Precision {
+    F64,
+    /// perturbation around a double-double reference orbit
+    DoubleDouble,
+    /// perturbation around a bignum reference orbit
+    Bignum,
+}
+
+impl Precision {
+    /// Selects the fastest arithmetic that can still tell the pixels of
+    /// the transform apart
+    pub fn select(transform: &Transform) -> Self {
+        let ulps = pixel_ulps(transform);
+        if ulps >= PIXEL_ULPS * f64::EPSILON {
+            Precision::F64
+        } else if ulps >= PIXEL_ULPS * double::EPSILON {
+            Precision::DoubleDouble
+        } else {
+            Precision::Bignum
+        }
+    }
+
+    /// Checks if the f32 kernel can still tell the pixels apart, which is
+    /// only the case at low magnification
+    pub fn f32_sufficient(transform: &Transform) -> bool {
+        pixel_ulps(transform) >= PIXEL_ULPS * f32::EPSILON as f64
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Precision::F64 => "f64",
+            Precision::DoubleDouble => "double-double",
+            Precision::Bignum => "bignum",
+        }
+    }
+}
+
+/// Pixel size relative to the largest coordinate of the view
+fn pixel_ulps(transform: &Transform) -> f64 {
+    let pixel = transform.pixel_size();
+    let (width, height) = transform.window_size();
+    let magnitude = transform.center().norm() + pixel * width.max(height) as f64;
+    pixel / magnitude
+}
+
+/// Fixed point number, the value is mantissa / 2^bits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fixed {
+    mantissa: BigInt,
+    bits: u32,
+}
+
+impl Fixed {
+    pub fn zero(bits: u32) -> Self {
+        Fixed {
+            mantissa: BigInt::zero(),
+            bits,
+        }
+    }
+
+    /// Converts a finite f64 without rounding if bits is large enough
+    pub fn from_f64(value: f64, bits: u32) -> Self {
+        let raw = value.to_bits();
+        let exponent = ((raw >> 52) & 0x7ff) as i32;
+        let fraction = raw & 0x000f_ffff_ffff_ffff;
+        let significand = match exponent {
+            0 => fraction << 1,
+            _ => fraction | 0x0010_0000_0000_0000,
+        };
+        let mut mantissa = BigInt::from(significand);
+        if raw >> 63 == 1 {
+            mantissa = -mantissa;
+        }
+
+        // value = significand * 2^(exponent - 1075)
+        let shift = exponent - 1075 + bits as i32;
+        let mantissa = match shift >= 0 {
+            true => mantissa << shift as usize,
+            false => mantissa >> (-shift) as usize,
+        };
+        Fixed { mantissa, bits }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // keep the 64 most significant bits so the conversion can't overflow
+        let drop = (self.mantissa.bits() as u32).saturating_sub(64);
+        let top = (&self.mantissa >> drop as usize).to_f64().unwrap_or(0.0);
+        top * 2f64.powi(drop as i32 - self.bits as i32)
+    }
+
+    /// Parses a decimal number of any length like "-1.25" or "3e-40" without
+    /// rounding it to f64, the precision follows from the number of digits
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid number '{}'", value);
+        let text = value.trim();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (digits, exponent) = match text.find(['e', 'E']) {
+            Some(i) => (
+                &text[..i],
+                text[i + 1..].parse::<i32>().map_err(|_| invalid())?,
+            ),
+            None => (text, 0),
+        };
+        let (integer, fraction) = match digits.find('.') {
+            Some(i) => (&digits[..i], &digits[i + 1..]),
+            None => (digits, ""),
+        };
+        let all_digits = format!("{}{}", integer, fraction);
+        if all_digits.is_empty() || !all_digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if all_digits.len() > MAX_DIGITS || exponent.unsigned_abs() as usize > MAX_DIGITS {
+            return Err(format!(
+                "Number '{}' has more than {} digits or a larger exponent",
+                value, MAX_DIGITS
+            ));
+        }
+
+        // value = digits * 10^scale
+        let scale = exponent - fraction.len() as i32;
+        let decimals = (-scale).max(0) as f64;
+        let bits = (decimals * std::f64::consts::LOG2_10).ceil() as u32 + GUARD_BITS;
+        let mut mantissa = all_digits.parse::<BigInt>().map_err(|_| invalid())? << bits as usize;
+        let power = num::pow(BigInt::from(10), scale.unsigned_abs() as usize);
+        mantissa = match scale >= 0 {
+            true => mantissa * power,
+            false => mantissa / power,
+        };
+        if negative {
+            mantissa = -mantissa;
+        }
+        Ok(Fixed { mantissa, bits })
+    }
+
+    /// Rounds to the closest double-double
+    pub fn to_double_double(&self) -> DoubleDouble {
+        let hi = self.to_f64();
+        let rest = Fixed {
+            mantissa: &self.mantissa - Fixed::from_f64(hi, self.bits).mantissa,
+            bits: self.bits,
+        };
+        DoubleDouble::new(hi, rest.to_f64())
+    }
+
+    /// Returns the value with the given number of fraction bits
+    pub fn with_bits(&self, bits: u32) -> Self {
+        let mantissa = match bits >= self.bits {
+            true => &self.mantissa << (bits - self.bits) as usize,
+            false => &self.mantissa >> (self.bits - bits) as usize,
+        };
+        Fixed { mantissa, bits }
+    }
+}
+
+impl fmt::Display for Fixed {
+    /// Writes all decimal digits, mantissa / 2^bits has exactly bits
+    /// fraction digits as mantissa * 5^bits / 10^bits
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bits = self.bits as usize;
+        let scaled = self.mantissa.abs() * num::pow(BigInt::from(5), bits);
+        let digits = format!("{:0>width$}", scaled.to_string(), width = bits + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - bits);
+        let fraction = fraction.trim_end_matches('0');
+        let sign = match self.mantissa.is_negative() {
+            true => "-",
+            false => "",
+        };
+        match fraction.is_empty() {
+            true => write!(f, "{}{}", sign, integer),
+            false => write!(f, "{}{}.{}", sign, integer, fraction),
+        }
+    }
+}
+
+/// Complex number of two fixed point numbers with equal precision
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixedComplex {
+    pub re: Fixed,
+    pub im: Fixed,
+}
+
+impl FixedComplex {
+    /// Combines two fixed point numbers at the larger precision
+    pub fn new(re: Fixed, im: Fixed) -> Self {
+        let bits = re.bits.max(im.bits);
+        FixedComplex {
+            re: re.with_bits(bits),
+            im: im.with_bits(bits),
+        }
+    }
+
+    pub fn zero(bits: u32) -> Self {
+        FixedComplex {
+            re: Fixed::zero(bits),
+            im: Fixed::zero(bits),
+        }
+    }
+
+    pub fn from_f64(z: &Complex<f64>, bits: u32) -> Self {
+        FixedComplex {
+            re: Fixed::from_f64(z.re, bits),
+            im: Fixed::from_f64(z.im, bits),
+        }
+    }
+
+    pub fn to_f64(&self) -> Complex<f64> {
+        Complex::new(self.re.to_f64(), self.im.to_f64())
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.re.bits
+    }
+
+    /// Returns the value with at least the given number of fraction bits
+    pub fn with_bits(&self, bits: u32) -> Self {
+        let bits = bits.max(self.bits());
+        FixedComplex {
+            re: self.re.with_bits(bits),
+            im: self.im.with_bits(bits),
+        }
+    }
+
+    /// Difference to another complex number rounded to f64
+    pub fn offset_from(&self, other: &FixedComplex) -> Complex<f64> {
+        let bits = self.bits().max(other.bits());
+        let difference = |a: &Fixed, b: &Fixed| Fixed {
+            mantissa: a.with_bits(bits).mantissa - b.with_bits(bits).mantissa,
+            bits,
+        };
+        Complex::new(
+            difference(&self.re, &other.re).to_f64(),
+            difference(&self.im, &other.im).to_f64(),
+        )
+    }
+
+    /// Adds a small offset, the precision is raised to at least bits
+    pub fn add_f64(&self, offset: &Complex<f64>, bits: u32) -> Self {
+        let bits = bits.max(self.bits());
+        let offset = FixedComplex::from_f64(offset, bits);
+        FixedComplex {
+            re: Fixed {
+                mantissa: self.re.with_bits(bits).mantissa + offset.re.mantissa,
+                bits,
+            },
+            im: Fixed {
+                mantissa: self.im.with_bits(bits).mantissa + offset.im.mantissa,
+                bits,
+            },
+        }
+    }
+}
+
+/// Number of fraction bits needed to tell pixels apart at the given scale
+pub fn precision_bits(scale: f64) -> u32 {
+    scale.log2().max(0.0).ceil() as u32 + GUARD_BITS
+}
+
+/// Iterates the reference point c in full precision, the orbit starts with
+/// z = 0 and ends after max_iter steps or when it escapes
+pub fn reference_orbit(c: &FixedComplex, max_iter: u32) -> Vec<Complex<f64>> {
+    let bits = c.bits();
+    let (mut re, mut im) = (BigInt::zero(), BigInt::zero());
+    let mut orbit = Vec::with_capacity(max_iter as usize + 1);
+    orbit.push(Complex::new(0.0, 0.0));
+
+    for _ in 0..max_iter {
+        let re2 = (&re * &re) >> bits as usize;
+        let im2 = (&im * &im) >> bits as usize;
+        let re_im = (&re * &im) >> (bits - 1) as usize;
+        re = re2 - im2 + &c.re.mantissa;
+        im = re_im + &c.im.mantissa;
+
+        let z = Complex::new(
+            Fixed {
+                mantissa: re.clone(),
+                bits,
+            }
+            .to_f64(),
+            Fixed {
+                mantissa: im.clone(),
+                bits,
+            }
+            .to_f64(),
+        );
+        orbit.push(z);
+        if z.norm_sqr() > 4.0 {
+            break;
+        }
+    }
+
+    orbit
+}
+
+/// Same as reference_orbit in double-double arithmetic, only accurate
+/// for the double-double precision
+pub fn reference_orbit_double(c: &FixedComplex, max_iter: u32) -> Vec<Complex<f64>> {
+    let (c_re, c_im) = (c.re.to_double_double(), c.im.to_double_double());
+    let (mut re, mut im) = (DoubleDouble::default(), DoubleDouble::default());
+    let mut orbit = Vec::with_capacity(max_iter as usize + 1);
+    orbit.push(Complex::new(0.0, 0.0));
+
+    for _ in 0..max_iter {
+        let re_im = re * im * 2.0;
+        re = re.square() - im.square() + c_re;
+        im = re_im + c_im;
+
+        let z = Complex::new(re.to_f64(), im.to_f64());
+        orbit.push(z);
+        if z.norm_sqr() > 4.0 {
+            break;
+        }
+    }
+
+    orbit
+}
+
+/// Reference orbit kept between frames, reused as long as the reference
+/// point stays within the view and the orbit is precise and long enough
+pub struct Reference {
+    c: FixedComplex,
+    max_iter: u32,
+    precision: Precision,
+    orbit: Arc<Vec<Complex<f64>>>,
+}
+
+impl Reference {
+    /// Iterates the reference point c with the precision needed for the transform
+    pub fn new(
+        c: &FixedComplex,
+        transform: &Transform,
+        max_iter: u32,
+        precision: Precision,
+    ) -> Self {
+        let c = c.with_bits(precision_bits(1.0 / transform.pixel_size()));
+        let orbit = match precision {
+            Precision::Bignum => reference_orbit(&c, max_iter),
+            _ => reference_orbit_double(&c, max_iter),
+        };
+        Reference {
+            c,
+            max_iter,
+            precision,
+            orbit: Arc::new(orbit),
+        }
+    }
+
+    /// Returns the offset of the view origin from the reference point if the
+    /// orbit can be used for the view of the given radius around the origin
+    pub fn offset(
+        &self,
+        transform: &Transform,
+        max_iter: u32,
+        precision: Precision,
+        radius: f64,
+    ) -> Option<Complex<f64>> {
+        // half of the guard bits may be used up by zooming in
+        let precise =
+            self.c.bits() + GUARD_BITS / 2 >= precision_bits(1.0 / transform.pixel_size());
+        let escaped = self.orbit.len() <= self.max_iter as usize;
+        let long = max_iter <= self.max_iter || escaped;
+        if precision != self.precision || !precise || !long {
+            return None;
+        }
+
+        let offset = transform.origin().offset_from(&self.c);
+        match offset.norm() <= radius {
+            true => Some(offset),
+            false => None,
+        }
+    }
+}
+
+/// Third order series of the difference to the reference orbit in the pixel
+/// offset dc, dz = a*dc + b*dc^2 + c*dc^3, valid for the first skip iterations
+/// of all offsets up to the radius of the view
+pub struct Series {
+    pub skip: usize,
+    a: Complex<f64>,
+    b: Complex<f64>,
+    c: Complex<f64>,
+}
+
+impl Series {
+    pub fn new(orbit: &[Complex<f64>], radius: f64) -> Self {
+        let zero = Complex::new(0.0, 0.0);
+        let mut series = Series {
+            skip: 0,
+            a: zero,
+            b: zero,
+            c: zero,
+        };
+
+        // the last point of the orbit is needed to continue perturbing
+        for (n, z) in orbit.iter().enumerate().take(orbit.len().saturating_sub(2)) {
+            let a = z * series.a * 2.0 + 1.0;
+            let b = z * series.b * 2.0 + series.a * series.a;
+            let c = z * series.c * 2.0 + series.a * series.b * 2.0;
+
+            // stop before the terms left out could matter at the edge of the view
+            let (a_term, b_term) = (a.norm() * radius, b.norm() * radius * radius);
+            let c_term = c.norm() * radius * radius * radius;
+            if c_term > SERIES_TOLERANCE * b_term || b_term > SERIES_TOLERANCE.sqrt() * a_term {
+                break;
+            }
+            series = Series {
+                skip: n + 1,
+                a,
+                b,
+                c,
+            };
+        }
+
+        series
+    }
+
+    /// Difference to the reference orbit after skip iterations
+    pub fn evaluate(&self, dc: Complex<f64>) -> Complex<f64> {
+        ((self.c * dc + self.b) * dc + self.a) * dc
+    }
+}
+
+/// Calculates the number of iterations for the point at offset dc from the
+/// reference to escape the bailout radius, starting at iteration skip with
+/// the difference dz.
+/// Only the small difference to the reference orbit is iterated in f64, it
+/// is rebased onto the start of the reference when it gets larger than the
+/// full value or the reference ends.
+pub fn perturb(
+    orbit: &[Complex<f64>],
+    dc: Complex<f64>,
+    skip: usize,
+    dz: Complex<f64>,
+    bailout: f64,
+    max_iter: u32,
+) -> Outcome {
+    let mut dz = dz;
+    let bailout_sqr = bailout * bailout;
+    let mut m = skip;
+
+    for n in skip as u32..max_iter {
+        dz = orbit[m] * dz * 2.0 + dz * dz + dc;
+        m += 1;
+
+        let z = orbit[m] + dz;
+        if z.norm_sqr() > bailout_sqr {
+            return Outcome::escaped(n + 1, z);
+        }
+        if z.norm_sqr() < dz.norm_sqr() || m == orbit.len() - 1 {
+            dz = z;
+            m = 0;
+        }
+    }
+
+    Outcome::new(max_iter, false)
+}
+
+/// multithreaded Mandelbrot image generation for pixels too small for f64
+pub fn generate_image_thread(
+    context: &mut RenderContext,
+    transform: &Transform,
+    bailout: f64,
+    image: &mut MandelImage,
+) {
+    let start = SystemTime::now();
+
+    let max_iter = image.max_iterations;
+    let precision = Precision::select(transform);
+    let (width, height) = (image.width as i32, image.height as i32);
+    let radius = [(0, 0), (width, 0), (0, height), (width, height)]
+        .iter()
+        .map(|&(x, y)| transform.pos_to_delta(x, y).norm())
+        .fold(0.0, f64::max);
+
+    let cached = context
+        .reference
+        .as_ref()
+        .and_then(|reference| reference.offset(transform, max_iter, precision, radius));
+    if cached.is_none() {
+        context.reference = Some(Reference::new(
+            transform.origin(),
+            transform,
+            max_iter,
+            precision,
+        ));
+    }
+    let offset = cached.unwrap_or_else(|| Complex::new(0.0, 0.0));
+    let reference = context
+        .reference
+        .as_ref()
+        .expect("Reference orbit is missing");
+    let orbit = reference.orbit.clone();
+    let bits = reference.c.bits();
+    let series = Arc::new(Series::new(&orbit, radius + offset.norm()));
+    let reference_time = start.elapsed().unwrap();
+
+    let trans = transform.clone();
+    let pixel_orbit = orbit.clone();
+    let pixel_series = series.clone();
+    let thread_count = mandelbrot::generate_tiles(
+        &mut context.outcomes,
+        image.width,
+        image.height,
+        move |x, y| {
+            let dc = trans.pos_to_delta(x, y) + offset;
+            let dz = pixel_series.evaluate(dc);
+            perturb(&pixel_orbit, dc, pixel_series.skip, dz, bailout, max_iter)
+        },
+        |tile, outcomes| image.set_iterations(tile, outcomes),
+    );
+
+    timing::record(
+        "generate",
+        start,
+        format_args!(
+            "Generated deep zoom image with {} threads, {} {} reference of {} iterations, {} skipped by series in {:?}, total",
+            thread_count,
+            match cached {
+                Some(_) => "cached",
+                None => "new",
+            },
+            match precision {
+                Precision::Bignum => format!("{} bit", bits),
+                _ => precision.name().to_string(),
+            },
+            orbit.len() - 1,
+            series.skip,
+            reference_time,
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed() {
+        for &v in [0.0, 1.5, -0.75, 1e-30, -3.25e10].iter() {
+            assert_eq!(Fixed::from_f64(v, 200).to_f64(), v);
+        }
+
+        let z = FixedComplex::from_f64(&Complex::new(-0.75, 0.1), 100);
+        let shifted = z.add_f64(&Complex::new(1e-25, -1e-25), 160);
+        assert_eq!(shifted.bits(), 160);
+        assert_eq!(shifted.re.to_f64(), -0.75 + 1e-25);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Fixed::parse("-1.25").unwrap().to_f64(), -1.25);
+        assert_eq!(Fixed::parse("3e2").unwrap().to_f64(), 300.0);
+        assert_eq!(Fixed::parse("+.5E-1").unwrap().to_f64(), 0.05);
+        assert!(Fixed::parse("1.2.3").is_err());
+        assert!(Fixed::parse("NaN").is_err());
+        assert!(Fixed::parse("-").is_err());
+        assert!(Fixed::parse("1e-2000000000").is_err());
+
+        // digits beyond f64 precision are kept
+        let long = Fixed::parse("0.10000000000000000000000000001").unwrap();
+        let short = Fixed::parse("0.10000000000000000000000000000").unwrap();
+        let difference = Fixed {
+            mantissa: long.mantissa - short.mantissa,
+            bits: long.bits,
+        };
+        assert!((difference.to_f64() / 1e-29 - 1.0).abs() < 1e-12);
+
+        assert_eq!(Fixed::from_f64(-1.25, 8).to_string(), "-1.25");
+        assert_eq!(Fixed::from_f64(3.0, 0).to_string(), "3");
+        assert_eq!(Fixed::from_f64(0.0, 4).to_string(), "0");
+        let deep = Fixed::parse("-0.000123456789012345678901234567").unwrap();
+        let exact = Fixed::parse(&deep.to_string())
+            .unwrap()
+            .with_bits(deep.bits);
+        assert_eq!(exact, deep);
+    }
+
+    #[test]
+    fn test_perturb_matches_mandel() {
+        let center = Complex::new(-0.75, 0.1);
+        let orbit = reference_orbit(&FixedComplex::from_f64(&center, 64), 500);
+
+        for dc in [Complex::new(0.0, 0.0), Complex::new(1e-3, -2e-3)].iter() {
+            let c = center + dc;
+            let expected = mandelbrot::iterate(&crate::fractal::Fractal::Mandelbrot, &c, 500);
+            assert_eq!(
+                perturb(&orbit, *dc, 0, Complex::new(0.0, 0.0), BAILOUT, 500).iterations,
+                expected.iterations
+            );
+        }
+    }
+
+    #[test]
+    fn test_precision() {
+        let mut transform = Transform::new((200, 300));
+        assert_eq!(Precision::select(&transform), Precision::F64);
+        assert!(Precision::f32_sufficient(&transform));
+        transform.zoom(1e4);
+        assert!(!Precision::f32_sufficient(&transform));
+        assert_eq!(Precision::select(&transform), Precision::F64);
+        transform.reset();
+
+        transform.center_at(&Complex::new(-1.5, 0.0));
+        transform.zoom(1e14);
+        assert_eq!(Precision::select(&transform), Precision::DoubleDouble);
+
+        transform.zoom(1e16);
+        assert_eq!(Precision::select(&transform), Precision::Bignum);
+    }
+
+    #[test]
+    fn test_reference_reuse() {
+        let mut transform = Transform::new((200, 100));
+        transform.center_at(&Complex::new(-0.743643887037151, 0.131825904205330));
+        transform.zoom(1e15);
+        let precision = Precision::select(&transform);
+        let reference = Reference::new(transform.origin(), &transform, 1000, precision);
+        let radius = transform.pos_to_delta(0, 0).norm();
+        assert_eq!(
+            reference.offset(&transform, 1000, precision, radius),
+            Some(Complex::new(0.0, 0.0))
+        );
+
+        // a pan within the view keeps the reference
+        transform.recenter(150, 50);
+        let offset = reference
+            .offset(&transform, 1000, precision, radius)
+            .unwrap();
+        assert!((offset + transform.pos_to_delta(50, 50)).norm() < 1e-6 * offset.norm());
+
+        // more iterations or a pan out of the view need a new reference
+        assert!(reference
+            .offset(&transform, 2000, precision, radius)
+            .is_none());
+        transform.recenter(199, 50);
+        assert!(reference
+            .offset(&transform, 1000, precision, radius)
+            .is_none());
+    }
+
+    #[test]
+    fn test_double_double_orbit() {
+        let center = Complex::new(-0.743643887037151, 0.131825904205330);
+        let c = FixedComplex::from_f64(&center, 200).add_f64(&Complex::new(3e-25, -1e-25), 200);
+        let bignum = reference_orbit(&c, 1000);
+        let double = reference_orbit_double(&c, 1000);
+
+        assert_eq!(double.len(), bignum.len());
+        for (a, b) in double.iter().zip(bignum.iter()) {
+            assert!((a - b).norm() <= 1e-15 * b.norm());
+        }
+    }
+
+    #[test]
+    fn test_series_skips_iterations() {
+        let center = Complex::new(-0.743643887037151, 0.131825904205330);
+        let orbit = reference_orbit(&FixedComplex::from_f64(&center, 120), 2000);
+        let series = Series::new(&orbit, 1e-10);
+        assert!(series.skip > 50);
+
+        for dc in [Complex::new(1e-10, 0.0), Complex::new(-3e-11, 7e-11)].iter() {
+            let dz = series.evaluate(*dc);
+            assert_eq!(
+                perturb(&orbit, *dc, series.skip, dz, BAILOUT, 2000).iterations,
+                perturb(&orbit, *dc, 0, Complex::new(0.0, 0.0), BAILOUT, 2000).iterations
+            );
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::export;
+use crate::mandelbrot;
+use crate::palette::{self, ColorScheme, RgbaBuffer};
+
+use num::complex::Complex;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Width and height of the window icon
+pub const ICON_SIZE: u32 = 64;
+
+/// Iterations of the icon, enough to show the bulbs
+const ICON_ITERATIONS: u32 = 40;
+
+/// Name of the icon and desktop entry files
+const APP_ID: &str = "mandelbrust";
+
+/// Renders the window icon, the whole set in the green scheme on a
+/// transparent background outside the escape circle
+pub fn icon() -> RgbaBuffer {
+    let mut icon = RgbaBuffer::new(ICON_SIZE, ICON_SIZE);
+    let scale = 3.0 / ICON_SIZE as f64;
+
+    for (i, pixel) in icon.data.chunks_mut(4).enumerate() {
+        let (x, y) = (i as u32 % ICON_SIZE, i as u32 / ICON_SIZE);
+        let c = Complex::new(
+            -2.05 + (x as f64 + 0.5) * scale,
+            -1.5 + (y as f64 + 0.5) * scale,
+        );
+        let n = mandelbrot::mandel(&c, ICON_ITERATIONS).iterations;
+        let color = palette::color(ColorScheme::Green, n, ICON_ITERATIONS);
+        let alpha = match c.norm() > 2.0 {
+            true => 0,
+            false => 255,
+        };
+        pixel.copy_from_slice(&[color.r, color.g, color.b, alpha]);
+    }
+
+    icon
+}
+
+/// Contents of the desktop entry starting the executable
+pub fn desktop_entry(exec: &Path, icon: &Path) -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=MandelbRust\n\
+         Comment=Explore the Mandelbrot set\n\
+         Exec={}\n\
+         Icon={}\n\
+         Terminal=false\n\
+         Categories=Graphics;Education;Science;\n",
+        exec_argument(exec),
+        escape_string(&icon.display().to_string())
+    )
+}
+
+/// Quotes the path as the program of the Exec key: `"`, `` ` ``, `$` and
+/// `\` get a backslash and `%` is doubled so it is not a field code. The
+/// string escapes apply on top, a `\` in the path ends up as `\\\\`.
+fn exec_argument(path: &Path) -> String {
+    let mut quoted = String::from("\"");
+    for c in path.display().to_string().chars() {
+        match c {
+            '"' | '`' | '$' | '\\' => quoted.extend(['\\', c]),
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    escape_string(&quoted)
+}
+
+/// Escapes backslashes and control characters in a string value of a
+/// desktop entry
+fn escape_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// User data directory, $XDG_DATA_HOME or ~/.local/share
+fn data_dir() -> Result<PathBuf, String> {
+    match (env::var_os("XDG_DATA_HOME"), env::var_os("HOME")) {
+        (Some(dir), _) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        (_, Some(home)) => Ok(Path::new(&home).join(".local/share")),
+        _ => Err("Neither XDG_DATA_HOME nor HOME is set".to_string()),
+    }
+}
+
+/// Writes the icon and a desktop entry for the running executable to the
+/// user data directory, so the application shows up in menus and docks.
+/// Returns the path of the desktop entry.
+pub fn install_desktop_entry() -> Result<PathBuf, String> {
+    let exec = env::current_exe().map_err(|e| format!("Failed to locate executable: {}", e))?;
+    let data = data_dir()?;
+
+    let icon_dir = data.join(format!("icons/hicolor/{0}x{0}/apps", ICON_SIZE));
+    let entry_dir = data.join("applications");
+    for dir in [&icon_dir, &entry_dir] {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    }
+
+    let icon_path = icon_dir.join(format!("{}.png", APP_ID));
+    let icon = icon();
+    export::write_png(&icon_path, icon.width, icon.height, &icon.data, &[])?;
+
+    let entry_path = entry_dir.join(format!("{}.desktop", APP_ID));
+    fs::write(&entry_path, desktop_entry(&exec, &icon_path))
+        .map_err(|e| format!("Failed to write {:?}: {}", entry_path, e))?;
+
+    Ok(entry_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_icon() {
+        let icon = icon();
+        assert_eq!(icon.data.len(), (ICON_SIZE * ICON_SIZE * 4) as usize);
+        // transparent corner, opaque black inside the main cardioid
+        assert_eq!(icon.data[3], 0);
+        let center = ((ICON_SIZE / 2 * ICON_SIZE + ICON_SIZE / 2) * 4) as usize;
+        assert_eq!(&icon.data[center..center + 4], &[0, 0, 0, 255]);
+
+        let entry = desktop_entry(Path::new("/opt/mandel brust"), Path::new("/icons/m.png"));
+        assert!(entry.starts_with("[Desktop Entry]\n"));
+        assert!(entry.contains("Exec=\"/opt/mandel brust\"\n"));
+        assert!(entry.contains("Icon=/icons/m.png\n"));
+
+        // quoted characters get a backslash, which is escaped as a string
+        let entry = desktop_entry(Path::new("/opt/a\"b`c$d\\e%f"), Path::new("/i\\m.png"));
+        assert!(entry.contains(r#"Exec="/opt/a\\"b\\`c\\$d\\\\e%%f""#));
+        assert!(entry.contains(r"Icon=/i\\m.png"));
+    }
+}
@@ -0,0 +1,113 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Relative rounding error of double-double arithmetic
+pub const EPSILON: f64 = f64::EPSILON * f64::EPSILON;
+
+/// Unevaluated sum hi + lo of two f64 with |lo| <= ulp(hi) / 2,
+/// about 106 bits of precision at a fraction of the bignum cost
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct DoubleDouble {
+    pub hi: f64,
+    pub lo: f64,
+}
+
+impl DoubleDouble {
+    pub fn new(hi: f64, lo: f64) -> Self {
+        let (hi, lo) = two_sum(hi, lo);
+        DoubleDouble { hi, lo }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    pub fn square(self) -> Self {
+        self * self
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> Self {
+        DoubleDouble { hi: value, lo: 0.0 }
+    }
+}
+
+/// Exact sum of two f64 as a rounded sum and its error
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let v = s - a;
+    (s, (a - (s - v)) + (b - v))
+}
+
+/// Exact product of two f64 as a rounded product and its error
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn add(self, d: DoubleDouble) -> DoubleDouble {
+        let (s, e) = two_sum(self.hi, d.hi);
+        let (t, f) = two_sum(self.lo, d.lo);
+        let (s, e) = two_sum(s, e + t);
+        DoubleDouble::new(s, e + f)
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn sub(self, d: DoubleDouble) -> DoubleDouble {
+        self + -d
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, d: DoubleDouble) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, d.hi);
+        DoubleDouble::new(p, e + self.hi * d.lo + self.lo * d.hi)
+    }
+}
+
+impl Mul<f64> for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, d: f64) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, d);
+        DoubleDouble::new(p, e + self.lo * d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precision() {
+        // 1 + 2^-80 is lost in f64 but not in double-double
+        let small = 2f64.powi(-80);
+        let x = DoubleDouble::from(1.0) + DoubleDouble::from(small);
+        assert_eq!((x - DoubleDouble::from(1.0)).to_f64(), small);
+
+        // (1 + 2^-40)^2 = 1 + 2^-39 + 2^-80
+        let y = DoubleDouble::from(1.0 + 2f64.powi(-40)).square();
+        assert_eq!(y.hi, 1.0 + 2f64.powi(-39));
+        assert_eq!(y.lo, small);
+        assert_eq!((y * 2.0).lo, 2.0 * small);
+    }
+}
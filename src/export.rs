@@ -0,0 +1,381 @@
+use crate::jpeg;
+
+use num::complex::Complex;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Largest block size of uncompressed deflate blocks
+const MAX_BLOCK: usize = 65535;
+
+/// Largest IDAT chunk written, longer bands are split over several chunks
+/// since PNG limits chunks to 2^31 - 1 bytes
+const MAX_IDAT: usize = 1 << 20;
+
+/// Returns a file name with the current time that is not taken yet, e.g.
+/// "mandelbrust_1571234567.png", a second export within the same second
+/// gets "mandelbrust_1571234567_2.png"
+pub fn timestamped_path(prefix: &str, extension: &str) -> Result<PathBuf, String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_secs();
+    let name = format!("{}_{}", prefix, timestamp);
+    Ok(free_path(Path::new(""), &name, extension))
+}
+
+/// First path of the name in the directory that does not exist, a
+/// counter from 2 on is appended to names already taken
+fn free_path(dir: &Path, name: &str, extension: &str) -> PathBuf {
+    (1..)
+        .map(|n| match n {
+            1 => dir.join(format!("{}.{}", name, extension)),
+            _ => dir.join(format!("{}_{}.{}", name, n, extension)),
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Writes the orbit of c, the format is selected by the file extension
+/// and is either CSV or JSON
+pub fn write_orbit(path: &Path, c: &Complex<f64>, orbit: &[Complex<f64>]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+    let mut out = BufWriter::new(file);
+    let json = path.extension().is_some_and(|e| e == "json");
+
+    let result = if json {
+        write_orbit_json(&mut out, c, orbit)
+    } else {
+        write_orbit_csv(&mut out, orbit)
+    };
+    result
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+fn write_orbit_csv<W: Write>(out: &mut W, orbit: &[Complex<f64>]) -> std::io::Result<()> {
+    writeln!(out, "iteration,re,im")?;
+    for (i, z) in orbit.iter().enumerate() {
+        writeln!(out, "{},{},{}", i, z.re, z.im)?;
+    }
+    Ok(())
+}
+
+fn write_orbit_json<W: Write>(
+    out: &mut W,
+    c: &Complex<f64>,
+    orbit: &[Complex<f64>],
+) -> std::io::Result<()> {
+    // non finite values are not valid JSON numbers
+    let number = |v: f64| match v.is_finite() {
+        true => format!("{:?}", v),
+        false => "null".to_string(),
+    };
+
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"c\": [{}, {}],", number(c.re), number(c.im))?;
+    writeln!(out, "  \"orbit\": [")?;
+    for (i, z) in orbit.iter().enumerate() {
+        let separator = if i + 1 < orbit.len() { "," } else { "" };
+        writeln!(out, "    [{}, {}]{}", number(z.re), number(z.im), separator)?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
+
+/// Writes RGBA pixel data as PNG or JPEG file, selected by the file extension.
+/// The quality from 1 to 100 only applies to JPEG. The metadata is stored as
+/// PNG text chunks or JPEG comments.
+pub fn write_image(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    quality: u8,
+    metadata: &[(&str, String)],
+) -> Result<(), String> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("png") => write_png(path, width, height, rgba, metadata),
+        Some("jpg") | Some("jpeg") => {
+            jpeg::write_jpeg(path, width, height, rgba, quality, metadata)
+        }
+        _ => Err(format!(
+            "Unsupported image format {:?}, expected png or jpg",
+            path
+        )),
+    }
+}
+
+/// Writes RGBA pixel data, 4 bytes per pixel, as a PNG file with text
+/// chunks of the metadata. The image data is stored without compression.
+pub fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    metadata: &[(&str, String)],
+) -> Result<(), String> {
+    let mut png = PngStream::create(path, width, height, metadata)?;
+    png.write_rows(rgba)?;
+    png.finish()
+}
+
+/// PNG file written in bands of rows, so images larger than the memory
+/// can be exported. Each band is written as IDAT chunks of at most
+/// MAX_IDAT bytes continuing one zlib stream of uncompressed deflate blocks.
+pub struct PngStream {
+    out: BufWriter<File>,
+    path: PathBuf,
+    width: u32,
+    rows_left: u32,
+    /// running Adler-32 sums of the uncompressed data
+    adler: (u32, u32),
+}
+
+impl PngStream {
+    /// Creates the file and writes the header and the metadata
+    pub fn create(
+        path: &Path,
+        width: u32,
+        height: u32,
+        metadata: &[(&str, String)],
+    ) -> Result<Self, String> {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        // 8 bit depth, color type RGBA, default compression, filter and no interlace
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let file = File::create(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+        let mut png = PngStream {
+            out: BufWriter::new(file),
+            path: path.to_path_buf(),
+            width,
+            rows_left: height,
+            adler: (1, 0),
+        };
+        png.write(|out| {
+            out.write_all(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a])?;
+            write_chunk(out, b"IHDR", &header)?;
+            for (keyword, text) in metadata {
+                let chunk = [keyword.as_bytes(), &[0], text.as_bytes()].concat();
+                write_chunk(out, b"tEXt", &chunk)?;
+            }
+            // zlib header of the stream continued by the bands
+            write_chunk(out, b"IDAT", &[0x78, 0x01])
+        })?;
+        Ok(png)
+    }
+
+    /// Appends whole rows of RGBA pixel data, 4 bytes per pixel
+    pub fn write_rows(&mut self, rgba: &[u8]) -> Result<(), String> {
+        let row_size = self.width as usize * 4;
+        let rows = rgba.len() / row_size.max(1);
+        if rgba.len() != row_size * rows || rows > self.rows_left as usize {
+            return Err(format!(
+                "Pixel data of {} bytes does not match the {} rows of width {} left",
+                rgba.len(),
+                self.rows_left,
+                self.width
+            ));
+        }
+        self.rows_left -= rows as u32;
+
+        // every row starts with filter type 0 (none)
+        let mut raw = Vec::with_capacity((row_size + 1) * rows);
+        for row in rgba.chunks(row_size) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+        self.adler = adler32_update(self.adler, &raw);
+        let data = deflate_stored(&raw, false);
+        self.write(|out| {
+            data.chunks(MAX_IDAT)
+                .try_for_each(|chunk| write_chunk(out, b"IDAT", chunk))
+        })
+    }
+
+    /// Ends the zlib stream and the file, all rows must have been written
+    pub fn finish(mut self) -> Result<(), String> {
+        if self.rows_left > 0 {
+            return Err(format!(
+                "{} rows of {:?} are missing",
+                self.rows_left, self.path
+            ));
+        }
+        let (a, b) = self.adler;
+        let mut end = deflate_stored(&[], true);
+        end.extend_from_slice(&((b << 16) | a).to_be_bytes());
+        self.write(|out| {
+            write_chunk(out, b"IDAT", &end)?;
+            write_chunk(out, b"IEND", &[])?;
+            out.flush()
+        })
+    }
+
+    fn write<F>(&mut self, write: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+    {
+        write(&mut self.out).map_err(|e| format!("Failed to write {:?}: {}", self.path, e))
+    }
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+    if data.len() > i32::MAX as usize {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("PNG chunk of {} bytes is too long", data.len()),
+        ));
+    }
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let crc = crc32(crc32(0xffff_ffff, kind), data) ^ 0xffff_ffff;
+    out.write_all(&crc.to_be_bytes())
+}
+
+/// Splits data into uncompressed deflate blocks, the last block ends
+/// the stream if last is set
+fn deflate_stored(data: &[u8], last: bool) -> Vec<u8> {
+    let blocks = data.len() / MAX_BLOCK + 1;
+    let mut out = Vec::with_capacity(data.len() + blocks * 5);
+
+    let mut chunks = data.chunks(MAX_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[last as u8, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let len = chunk.len() as u16;
+        out.push((last && chunks.peek().is_none()) as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |c, _| match c & 1 {
+            1 => 0xedb8_8320 ^ (c >> 1),
+            _ => c >> 1,
+        })
+    })
+}
+
+#[cfg(test)]
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = adler32_update((1, 0), data);
+    (b << 16) | a
+}
+
+/// Continues the two Adler-32 sums over data
+fn adler32_update(sums: (u32, u32), data: &[u8]) -> (u32, u32) {
+    data.iter().fold(sums, |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(0xffff_ffff, b"IEND") ^ 0xffff_ffff, 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_free_path() {
+        let dir = std::env::temp_dir();
+        let name = format!("mandelbrust_free_{}", std::process::id());
+        let first = free_path(&dir, &name, "png");
+        assert_eq!(first, dir.join(format!("{}.png", name)));
+        File::create(&first).unwrap();
+        let second = free_path(&dir, &name, "png");
+        assert_eq!(second, dir.join(format!("{}_2.png", name)));
+        std::fs::remove_file(first).unwrap();
+    }
+
+    #[test]
+    fn test_png_stream() {
+        let dir = std::env::temp_dir();
+        let (whole, banded) = (
+            dir.join("mandelbrust_whole.png"),
+            dir.join("mandelbrust_banded.png"),
+        );
+        let rgba: Vec<u8> = (0..5 * 7 * 4).map(|i| i as u8).collect();
+        let metadata = [("Title", "test".to_string())];
+        write_png(&whole, 5, 7, &rgba, &metadata).unwrap();
+
+        let mut png = PngStream::create(&banded, 5, 7, &metadata).unwrap();
+        assert!(png.write_rows(&rgba[..3]).is_err());
+        for band in rgba.chunks(3 * 5 * 4) {
+            png.write_rows(band).unwrap();
+        }
+        png.finish().unwrap();
+
+        // both files hold the same pixels, the bands only split the data
+        let decode = |path: &Path| -> Vec<u8> {
+            let file = std::fs::read(path).unwrap();
+            let mut data = Vec::new();
+            let mut pos = 8;
+            while pos < file.len() {
+                let len =
+                    u32::from_be_bytes([file[pos], file[pos + 1], file[pos + 2], file[pos + 3]])
+                        as usize;
+                if &file[pos + 4..pos + 8] == b"IDAT" {
+                    data.extend_from_slice(&file[pos + 8..pos + 8 + len]);
+                }
+                pos += len + 12;
+            }
+            data
+        };
+        let stored = |data: Vec<u8>| -> Vec<u8> {
+            // skips the zlib header, the block headers and the checksum
+            let mut pixels = Vec::new();
+            let mut pos = 2;
+            while pos + 4 < data.len() {
+                let len = u16::from_le_bytes([data[pos + 1], data[pos + 2]]) as usize;
+                pixels.extend_from_slice(&data[pos + 5..pos + 5 + len]);
+                pos += len + 5;
+            }
+            assert_eq!(data[data.len() - 4..], adler32(&pixels).to_be_bytes());
+            pixels
+        };
+        assert_eq!(stored(decode(&whole)), stored(decode(&banded)));
+        std::fs::remove_file(whole).unwrap();
+        std::fs::remove_file(banded).unwrap();
+    }
+
+    #[test]
+    fn test_idat_size() {
+        let path =
+            std::env::temp_dir().join(format!("mandelbrust_idat_{}.png", std::process::id()));
+        let (width, height) = (1024, 600);
+        let rgba = vec![7; width * height * 4];
+        write_png(&path, width as u32, height as u32, &rgba, &[]).unwrap();
+
+        // the band is longer than an IDAT chunk and split over three
+        let file = std::fs::read(&path).unwrap();
+        let mut sizes = Vec::new();
+        let mut pos = 8;
+        while pos < file.len() {
+            let len = u32::from_be_bytes([file[pos], file[pos + 1], file[pos + 2], file[pos + 3]]);
+            if &file[pos + 4..pos + 8] == b"IDAT" {
+                sizes.push(len as usize);
+            }
+            pos += len as usize + 12;
+        }
+        assert_eq!(sizes.len(), 5);
+        assert_eq!(sizes[1..3], [MAX_IDAT, MAX_IDAT]);
+        assert!(sizes[3] < MAX_IDAT);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,422 @@
+use crate::fractal::{Formula, Orbit};
+
+use num::complex::Complex;
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Compiled formula, calculates the next z from z and c
+type Function = Box<dyn Fn(Complex<f64>, Complex<f64>) -> Complex<f64> + Send + Sync>;
+
+/// Parsed formula syntax tree
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(Complex<f64>),
+    Z,
+    C,
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    Call(String, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+}
+
+/// Functions available in formulas
+const FUNCTIONS: [&str; 7] = ["sin", "cos", "exp", "log", "sqrt", "conj", "abs"];
+
+/// A user defined iteration formula like `z^3 + c*z + c`, compiled
+/// to a closure. Knows the variables `z` and `c`, the imaginary unit `i`,
+/// the operators `+ - * / ^`, parentheses and the functions
+/// sin, cos, exp, log, sqrt, conj and abs.
+pub struct Expression {
+    source: String,
+    function: Function,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.expression()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(format!("Unexpected {:?} in formula '{}'", token, source));
+        }
+
+        Ok(Expression {
+            source: source.to_string(),
+            function: compile(fold(expr)),
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn eval(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        (self.function)(z, c)
+    }
+}
+
+impl Formula for Expression {
+    fn step(&self, orbit: &Orbit, c: &Complex<f64>) -> Complex<f64> {
+        self.eval(orbit.z, *c)
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(&ch) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+        } else if ch.is_ascii_digit() || ch == '.' {
+            let mut number = String::new();
+            while let Some(&d) = chars.peek() {
+                if !(d.is_ascii_digit() || d == '.') {
+                    break;
+                }
+                number.push(d);
+                chars.next();
+            }
+            let value = number
+                .parse()
+                .map_err(|_| format!("Invalid number '{}' in formula", number))?;
+            tokens.push(Token::Number(value));
+        } else if ch.is_ascii_alphabetic() {
+            let mut ident = String::new();
+            while let Some(&a) = chars.peek() {
+                if !a.is_ascii_alphanumeric() {
+                    break;
+                }
+                ident.push(a);
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident.to_lowercase()));
+        } else if "+-*/^()".contains(ch) {
+            tokens.push(Token::Op(ch));
+            chars.next();
+        } else {
+            return Err(format!("Invalid character '{}' in formula", ch));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the tokens
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(*op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: char) -> Result<(), String> {
+        match self.peek_op() {
+            Some(o) if o == op => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(format!("Expected '{}' in formula", op)),
+        }
+    }
+
+    /// expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        while let Some(op) = self.peek_op() {
+            match op {
+                '+' | '-' => self.pos += 1,
+                _ => break,
+            }
+            let rhs = self.term()?;
+            lhs = match op {
+                '+' => Expr::Add(Box::new(lhs), Box::new(rhs)),
+                _ => Expr::Sub(Box::new(lhs), Box::new(rhs)),
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek_op() {
+            match op {
+                '*' | '/' => self.pos += 1,
+                _ => break,
+            }
+            let rhs = self.unary()?;
+            lhs = match op {
+                '*' => Expr::Mul(Box::new(lhs), Box::new(rhs)),
+                _ => Expr::Div(Box::new(lhs), Box::new(rhs)),
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// unary := '-' unary | power
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek_op() == Some('-') {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    /// power := atom ('^' unary)?
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.atom()?;
+        if self.peek_op() == Some('^') {
+            self.pos += 1;
+            return Ok(Expr::Pow(Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    /// atom := number | variable | function '(' expression ')' | '(' expression ')'
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+
+        match token {
+            Some(Token::Number(value)) => Ok(Expr::Number(Complex::new(value, 0.0))),
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "z" => Ok(Expr::Z),
+                "c" => Ok(Expr::C),
+                "i" => Ok(Expr::Number(Complex::new(0.0, 1.0))),
+                f if FUNCTIONS.contains(&f) => {
+                    self.expect('(')?;
+                    let arg = self.expression()?;
+                    self.expect(')')?;
+                    Ok(Expr::Call(ident, Box::new(arg)))
+                }
+                _ => Err(format!("Unknown name '{}' in formula", ident)),
+            },
+            Some(Token::Op('(')) => {
+                let expr = self.expression()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(token) => Err(format!("Unexpected {:?} in formula", token)),
+            None => Err("Unexpected end of formula".to_string()),
+        }
+    }
+}
+
+/// Returns the function with the given name
+fn function(name: &str) -> fn(Complex<f64>) -> Complex<f64> {
+    match name {
+        "sin" => |z| z.sin(),
+        "cos" => |z| z.cos(),
+        "exp" => |z| z.exp(),
+        "log" => |z| z.ln(),
+        "sqrt" => |z| z.sqrt(),
+        "conj" => |z| z.conj(),
+        _ => |z| Complex::new(z.norm(), 0.0),
+    }
+}
+
+/// Folds a binary operation if both sides are constant
+fn fold_binary(
+    a: Expr,
+    b: Expr,
+    f: fn(Complex<f64>, Complex<f64>) -> Complex<f64>,
+    e: fn(Box<Expr>, Box<Expr>) -> Expr,
+) -> Expr {
+    match (fold(a), fold(b)) {
+        (Expr::Number(a), Expr::Number(b)) => Expr::Number(f(a, b)),
+        (a, b) => e(Box::new(a), Box::new(b)),
+    }
+}
+
+/// Evaluates all constant sub expressions
+fn fold(expr: Expr) -> Expr {
+    match expr {
+        Expr::Neg(a) => match fold(*a) {
+            Expr::Number(a) => Expr::Number(-a),
+            a => Expr::Neg(Box::new(a)),
+        },
+        Expr::Add(a, b) => fold_binary(*a, *b, |a, b| a + b, Expr::Add),
+        Expr::Sub(a, b) => fold_binary(*a, *b, |a, b| a - b, Expr::Sub),
+        Expr::Mul(a, b) => fold_binary(*a, *b, |a, b| a * b, Expr::Mul),
+        Expr::Div(a, b) => fold_binary(*a, *b, |a, b| a / b, Expr::Div),
+        Expr::Pow(a, b) => fold_binary(*a, *b, |a, b| a.powc(b), Expr::Pow),
+        Expr::Call(name, a) => match fold(*a) {
+            Expr::Number(a) => Expr::Number(function(&name)(a)),
+            a => Expr::Call(name, Box::new(a)),
+        },
+        e => e,
+    }
+}
+
+/// Turns the syntax tree into nested closures
+fn compile(expr: Expr) -> Function {
+    match expr {
+        Expr::Number(n) => Box::new(move |_, _| n),
+        Expr::Z => Box::new(|z, _| z),
+        Expr::C => Box::new(|_, c| c),
+        Expr::Neg(a) => {
+            let a = compile(*a);
+            Box::new(move |z, c| -a(z, c))
+        }
+        Expr::Add(a, b) => {
+            let (a, b) = (compile(*a), compile(*b));
+            Box::new(move |z, c| a(z, c) + b(z, c))
+        }
+        Expr::Sub(a, b) => {
+            let (a, b) = (compile(*a), compile(*b));
+            Box::new(move |z, c| a(z, c) - b(z, c))
+        }
+        Expr::Mul(a, b) => {
+            let (a, b) = (compile(*a), compile(*b));
+            Box::new(move |z, c| a(z, c) * b(z, c))
+        }
+        Expr::Div(a, b) => {
+            let (a, b) = (compile(*a), compile(*b));
+            Box::new(move |z, c| a(z, c) / b(z, c))
+        }
+        Expr::Pow(a, b) => match *b {
+            // integer powers are much faster by repeated multiplication
+            Expr::Number(n) if n.im == 0.0 && n.re.fract() == 0.0 && n.re.abs() < 64.0 => {
+                let a = compile(*a);
+                let n = n.re as i32;
+                Box::new(move |z, c| a(z, c).powi(n))
+            }
+            b => {
+                let (a, b) = (compile(*a), compile(b));
+                Box::new(move |z, c| a(z, c).powc(b(z, c)))
+            }
+        },
+        Expr::Call(name, a) => {
+            let a = compile(*a);
+            let f = function(&name);
+            Box::new(move |z, c| f(a(z, c)))
+        }
+    }
+}
+
+/// Formula file edited in an external editor, parsed again whenever
+/// its content changes
+pub struct FormulaFile {
+    path: PathBuf,
+    content: String,
+}
+
+impl FormulaFile {
+    /// Writes the formula source to a new file in the directory, named
+    /// after the process so running instances don't share a file. Never
+    /// opens an existing file, which may be a link planted in a shared
+    /// directory, but moves on to the next free name.
+    pub fn create(dir: &Path, source: &str) -> Result<Self, String> {
+        for n in 0.. {
+            let name = format!("mandelbrust_formula_{}_{}.txt", std::process::id(), n);
+            let path = dir.join(name);
+            let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(format!("Failed to create {:?}: {}", path, e)),
+            };
+            file.write_all(source.as_bytes())
+                .map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+            return Ok(FormulaFile {
+                path,
+                content: source.to_string(),
+            });
+        }
+        unreachable!()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens the file in $EDITOR without waiting for the editor to close
+    pub fn edit(&self) -> Result<(), String> {
+        let editor = std::env::var("EDITOR").map_err(|_| "Set $EDITOR to edit formulas")?;
+        Command::new(&editor)
+            .arg(&self.path)
+            .spawn()
+            .map_err(|e| format!("Failed to start editor '{}': {}", editor, e))?;
+        Ok(())
+    }
+
+    /// Parses the file if it changed since the last call
+    pub fn poll(&mut self) -> Option<Result<Expression, String>> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        if content == self.content {
+            return None;
+        }
+        self.content = content;
+        Some(Expression::parse(self.content.trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        let z = Complex::new(0.5, -0.25);
+        let c = Complex::new(-0.75, 0.1);
+
+        let f = Expression::parse("z^3 + c*z + c").unwrap();
+        assert_eq!(f.eval(z, c), z * z * z + c * z + c);
+
+        let f = Expression::parse("-2*(z - i)^2/c").unwrap();
+        let i = Complex::new(0.0, 1.0);
+        assert_eq!(f.eval(z, c), -((z - i) * (z - i) * 2.0) / c);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Expression::parse("z^2 +").is_err());
+        assert!(Expression::parse("z^2 + x").is_err());
+        assert!(Expression::parse("(z^2 + c").is_err());
+        assert!(Expression::parse("z z").is_err());
+        assert!(Expression::parse("z # c").is_err());
+    }
+
+    #[test]
+    fn test_formula_file() {
+        let dir = std::env::temp_dir().join(format!("mandelbrust-formula-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // an existing file is left alone, the formula goes to a new one
+        let taken = dir.join(format!("mandelbrust_formula_{}_0.txt", std::process::id()));
+        fs::write(&taken, "keep").unwrap();
+        let mut file = FormulaFile::create(&dir, "z^2 + c").unwrap();
+        let path = file.path().to_path_buf();
+        assert_ne!(path, taken);
+        assert_eq!(fs::read_to_string(&taken).unwrap(), "keep");
+        assert_ne!(FormulaFile::create(&dir, "z^2 + c").unwrap().path(), path);
+        assert!(file.poll().is_none());
+
+        fs::write(&path, "z^3 + c\n").unwrap();
+        assert_eq!(file.poll().unwrap().unwrap().source(), "z^3 + c");
+        assert!(file.poll().is_none());
+
+        fs::write(&path, "z^").unwrap();
+        assert!(file.poll().unwrap().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,585 @@
+use crate::mandelbrot;
+use crate::trap::Trap;
+use crate::types::View;
+
+use num::complex::Complex;
+
+use std::sync::Arc;
+
+/// Constant weight of the previous z value in the Phoenix formula
+const PHOENIX_P: f64 = -0.5;
+
+/// Escape radius of the Magnet formulas
+const MAGNET_BAILOUT: f64 = 100.0;
+
+/// Distance to 1 where the Magnet formulas are considered converged
+const MAGNET_EPSILON: f64 = 1e-3;
+
+/// Relaxation factor of the Nova formula
+const NOVA_RELAXATION: f64 = 1.0;
+
+/// Escape radius of the Nova formula
+const NOVA_BAILOUT: f64 = 1000.0;
+
+/// Step size below which the Nova formula is considered converged
+const NOVA_EPSILON: f64 = 1e-6;
+
+/// Escape radius of the Collatz map, cos grows quickly away from the real axis
+const COLLATZ_BAILOUT: f64 = 1000.0;
+
+/// Escape radius of the logistic map
+const LAMBDA_BAILOUT: f64 = 100.0;
+
+/// Iteration state, keeps the current and the previous z value
+#[derive(Copy, Clone, Default)]
+pub struct Orbit {
+    pub z: Complex<f64>,
+    pub z_prev: Complex<f64>,
+    /// Number of steps taken
+    pub iteration: u32,
+}
+
+impl Orbit {
+    /// Creates an orbit starting at z
+    pub fn start(z: Complex<f64>) -> Self {
+        Orbit {
+            z,
+            ..Orbit::default()
+        }
+    }
+
+    /// Size of the last step
+    pub fn delta(&self) -> f64 {
+        (self.z - self.z_prev).norm()
+    }
+
+    /// Moves the orbit one step forward to z
+    pub fn advance(&mut self, z: Complex<f64>) {
+        self.z_prev = self.z;
+        self.z = z;
+        self.iteration += 1;
+    }
+}
+
+/// Result of iterating a single point
+#[derive(Copy, Clone, Default)]
+pub struct Outcome {
+    pub iterations: u32,
+    /// true if the orbit converged instead of escaping
+    pub converged: bool,
+    /// fractional iteration count for smooth coloring
+    pub smooth: f64,
+    /// last z of the orbit, zero if it did not escape
+    pub z: Complex<f64>,
+    /// closest approach of the orbit to the orbit trap, infinite without
+    /// a trap
+    pub trap: f64,
+    /// iteration at which |z| came closest to 0, for atom domain coloring
+    pub period: u32,
+    /// smallest |z| of the orbit, infinite if it was not recorded
+    pub min_norm: f64,
+}
+
+impl Outcome {
+    /// Outcome without a fractional part
+    pub fn new(iterations: u32, converged: bool) -> Self {
+        Outcome {
+            iterations,
+            converged,
+            smooth: iterations as f64,
+            z: Complex::new(0.0, 0.0),
+            trap: f64::INFINITY,
+            period: 0,
+            min_norm: f64::INFINITY,
+        }
+    }
+
+    /// Outcome of an orbit escaping to z, the normalized count
+    /// `n + 1 - log2(ln |z|)` is continuous across iteration bands
+    pub fn escaped(iterations: u32, z: Complex<f64>) -> Self {
+        let log_z = z.norm().ln();
+        let smooth = match log_z > 0.0 {
+            true => (iterations as f64 + 1.0 - log_z.log2()).max(0.0),
+            false => iterations as f64,
+        };
+        Outcome {
+            iterations,
+            converged: false,
+            smooth,
+            z,
+            trap: f64::INFINITY,
+            period: 0,
+            min_norm: f64::INFINITY,
+        }
+    }
+}
+
+/// A formula iterated for each point in the complex plane
+pub trait Formula {
+    /// Calculates the next z value from the orbit state and c
+    fn step(&self, orbit: &Orbit, c: &Complex<f64>) -> Complex<f64>;
+
+    /// The starting z value of the orbit
+    fn initial(&self, _c: &Complex<f64>) -> Complex<f64> {
+        Complex::new(0.0, 0.0)
+    }
+
+    /// Starting z value of a second orbit that has to stay bounded as
+    /// well, for families with two critical points
+    fn second_initial(&self, _c: &Complex<f64>) -> Option<Complex<f64>> {
+        None
+    }
+
+    /// The orbit escapes when |z| reaches this radius
+    fn bailout(&self) -> f64 {
+        2.0
+    }
+
+    /// Checks if the orbit has converged to a finite attractor
+    fn converged(&self, _orbit: &Orbit) -> bool {
+        false
+    }
+
+    /// Checks if c is known to be attracted by a cycle without iterating,
+    /// formulas without a valid shortcut always return false
+    fn interior_check(&self, _c: &Complex<f64>) -> bool {
+        false
+    }
+
+    /// Checks if the conjugate of c has the conjugate orbit of c, the
+    /// image is then symmetric about the real axis
+    fn symmetric(&self) -> bool {
+        false
+    }
+
+    /// Calculates the number of iterations for c to escape or converge
+    fn escape_time(&self, c: &Complex<f64>, max_iter: u32) -> Outcome {
+        mandelbrot::iterate(self, c, max_iter)
+    }
+}
+
+/// Formula shared between render threads
+pub type SharedFormula = Arc<dyn Formula + Send + Sync>;
+
+/// Defines the different fractal types
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Fractal {
+    Mandelbrot,
+    Phoenix,
+    Magnet1,
+    Magnet2,
+    BurningShip,
+    Nova,
+    /// experimental complex extension of the Collatz map
+    Collatz,
+    /// logistic map `lambda*z*(1 - z)`, plotted in the lambda plane
+    Lambda,
+}
+
+impl Fractal {
+    /// Returns the fractal type following this one, used for cycling
+    pub fn cycle(self) -> Self {
+        match self {
+            Fractal::Mandelbrot => Fractal::Phoenix,
+            Fractal::Phoenix => Fractal::Magnet1,
+            Fractal::Magnet1 => Fractal::Magnet2,
+            Fractal::Magnet2 => Fractal::BurningShip,
+            Fractal::BurningShip => Fractal::Nova,
+            Fractal::Nova => Fractal::Collatz,
+            Fractal::Collatz => Fractal::Lambda,
+            Fractal::Lambda => Fractal::Mandelbrot,
+        }
+    }
+
+    /// View of fractals that do not fit the default view
+    pub fn default_view(self) -> Option<View> {
+        match self {
+            // the lambda plane is symmetric around 1
+            Fractal::Lambda => Some(View {
+                center: Complex::new(1.0, 0.0),
+                height: 5.6,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Fractal {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().to_lowercase().as_str() {
+            "mandelbrot" => Ok(Fractal::Mandelbrot),
+            "phoenix" => Ok(Fractal::Phoenix),
+            "magnet1" => Ok(Fractal::Magnet1),
+            "magnet2" => Ok(Fractal::Magnet2),
+            "burningship" => Ok(Fractal::BurningShip),
+            "nova" => Ok(Fractal::Nova),
+            "collatz" => Ok(Fractal::Collatz),
+            "lambda" => Ok(Fractal::Lambda),
+            _ => Err(format!("Unknown fractal type '{}'", name)),
+        }
+    }
+}
+
+impl Formula for Fractal {
+    fn step(&self, orbit: &Orbit, c: &Complex<f64>) -> Complex<f64> {
+        match self {
+            Fractal::Mandelbrot => orbit.z * orbit.z + c,
+            Fractal::Phoenix => orbit.z * orbit.z + c + orbit.z_prev * PHOENIX_P,
+            Fractal::Magnet1 => {
+                let z = orbit.z;
+                ((z * z + c - 1.0) / (z * 2.0 + c - 2.0)).powi(2)
+            }
+            Fractal::Magnet2 => {
+                let z = orbit.z;
+                let c1 = c - 1.0;
+                let c2 = c - 2.0;
+                ((z.powi(3) + z * c1 * 3.0 + c1 * c2)
+                    / (z * z * 3.0 + z * c2 * 3.0 + c1 * c2 + 1.0))
+                    .powi(2)
+            }
+            Fractal::BurningShip => {
+                let z = Complex::new(orbit.z.re.abs(), orbit.z.im.abs());
+                z * z + c
+            }
+            Fractal::Nova => {
+                // relaxed Newton step for z^3 - 1 with added c
+                let z = orbit.z;
+                let z2 = z * z;
+                z - (z2 * z - 1.0) / (z2 * 3.0) * NOVA_RELAXATION + c
+            }
+            Fractal::Collatz => {
+                // interpolates n/2 for even and 3n+1 for odd integers
+                let z = orbit.z;
+                let cos = (z * std::f64::consts::PI).cos();
+                (z * 7.0 + 2.0 - (z * 5.0 + 2.0) * cos) / 4.0
+            }
+            Fractal::Lambda => c * orbit.z * (1.0 - orbit.z),
+        }
+    }
+
+    fn initial(&self, c: &Complex<f64>) -> Complex<f64> {
+        match self {
+            // the critical point of the Nova formula
+            Fractal::Nova => Complex::new(1.0, 0.0),
+            // the map has no parameter, every pixel is its own starting point
+            Fractal::Collatz => *c,
+            // the critical point of the logistic map
+            Fractal::Lambda => Complex::new(0.5, 0.0),
+            _ => Complex::new(0.0, 0.0),
+        }
+    }
+
+    fn interior_check(&self, c: &Complex<f64>) -> bool {
+        match self {
+            Fractal::Mandelbrot => mandelbrot::in_set(c),
+            // the lambda plane is conjugate to the Mandelbrot plane
+            Fractal::Lambda => mandelbrot::in_set(&(c / 2.0 - c * c / 4.0)),
+            _ => false,
+        }
+    }
+
+    /// Only the burning ship breaks the symmetry by folding z
+    fn symmetric(&self) -> bool {
+        *self != Fractal::BurningShip
+    }
+
+    fn bailout(&self) -> f64 {
+        match self {
+            Fractal::Magnet1 | Fractal::Magnet2 => MAGNET_BAILOUT,
+            Fractal::Nova => NOVA_BAILOUT,
+            Fractal::Collatz => COLLATZ_BAILOUT,
+            Fractal::Lambda => LAMBDA_BAILOUT,
+            _ => 2.0,
+        }
+    }
+
+    fn converged(&self, orbit: &Orbit) -> bool {
+        match self {
+            Fractal::Magnet1 | Fractal::Magnet2 => (orbit.z - 1.0).norm() < MAGNET_EPSILON,
+            Fractal::Nova => orbit.iteration > 0 && orbit.delta() < NOVA_EPSILON,
+            _ => false,
+        }
+    }
+
+    /// The Mandelbrot set uses its own faster path
+    fn escape_time(&self, c: &Complex<f64>, max_iter: u32) -> Outcome {
+        match self {
+            Fractal::Mandelbrot => mandelbrot::mandel(c, max_iter),
+            _ => mandelbrot::iterate(self, c, max_iter),
+        }
+    }
+}
+
+/// Hybrid fractal, alternates between formulas on a repeating schedule
+pub struct Hybrid {
+    schedule: Vec<Fractal>,
+}
+
+impl Hybrid {
+    pub fn new(schedule: Vec<Fractal>) -> Result<Self, String> {
+        if schedule.is_empty() {
+            return Err("Hybrid schedule must not be empty".to_string());
+        }
+        Ok(Hybrid { schedule })
+    }
+}
+
+/// Cubic family `z^3 + a*z + c`, the second parameter a selects a
+/// 2D slice through the 4D parameter space
+pub struct Cubic {
+    a: Complex<f64>,
+    /// critical point the orbit starts from
+    critical: Complex<f64>,
+}
+
+impl Cubic {
+    pub fn new(a: Complex<f64>) -> Self {
+        Cubic {
+            a,
+            critical: (-a / 3.0).sqrt(),
+        }
+    }
+}
+
+impl Formula for Cubic {
+    fn step(&self, orbit: &Orbit, c: &Complex<f64>) -> Complex<f64> {
+        orbit.z * orbit.z * orbit.z + self.a * orbit.z + c
+    }
+
+    fn initial(&self, _c: &Complex<f64>) -> Complex<f64> {
+        self.critical
+    }
+
+    /// The connectedness locus requires the orbits of both critical
+    /// points to stay bounded
+    fn second_initial(&self, _c: &Complex<f64>) -> Option<Complex<f64>> {
+        Some(-self.critical)
+    }
+
+    /// The escape radius grows with a
+    fn bailout(&self) -> f64 {
+        (2.0 + self.a.norm()).sqrt().max(2.0)
+    }
+}
+
+/// Moves the starting point of a formula towards the pixel, t = 0 keeps
+/// the Mandelbrot convention and t = 1 starts at c like a Julia set
+pub struct Morph {
+    formula: SharedFormula,
+    t: f64,
+}
+
+impl Morph {
+    pub fn new(formula: SharedFormula, t: f64) -> Self {
+        Morph { formula, t }
+    }
+}
+
+impl Formula for Morph {
+    fn step(&self, orbit: &Orbit, c: &Complex<f64>) -> Complex<f64> {
+        self.formula.step(orbit, c)
+    }
+
+    fn initial(&self, c: &Complex<f64>) -> Complex<f64> {
+        self.formula.initial(c) * (1.0 - self.t) + c * self.t
+    }
+
+    fn second_initial(&self, c: &Complex<f64>) -> Option<Complex<f64>> {
+        let z = self.formula.second_initial(c)?;
+        Some(z * (1.0 - self.t) + c * self.t)
+    }
+
+    fn bailout(&self) -> f64 {
+        self.formula.bailout()
+    }
+
+    fn converged(&self, orbit: &Orbit) -> bool {
+        self.formula.converged(orbit)
+    }
+}
+
+/// Julia set of a formula for a fixed parameter, the pixel is the
+/// starting point of the orbit
+pub struct Julia {
+    formula: SharedFormula,
+    c: Complex<f64>,
+}
+
+impl Julia {
+    pub fn new(formula: SharedFormula, c: Complex<f64>) -> Self {
+        Julia { formula, c }
+    }
+}
+
+impl Formula for Julia {
+    fn step(&self, orbit: &Orbit, _c: &Complex<f64>) -> Complex<f64> {
+        self.formula.step(orbit, &self.c)
+    }
+
+    fn initial(&self, c: &Complex<f64>) -> Complex<f64> {
+        *c
+    }
+
+    fn bailout(&self) -> f64 {
+        self.formula.bailout()
+    }
+
+    fn converged(&self, orbit: &Orbit) -> bool {
+        self.formula.converged(orbit)
+    }
+}
+
+/// Replaces the escape radius of a formula, larger radii make the
+/// smooth coloring more accurate
+pub struct Bailout {
+    formula: SharedFormula,
+    radius: f64,
+}
+
+impl Bailout {
+    pub fn new(formula: SharedFormula, radius: f64) -> Self {
+        Bailout { formula, radius }
+    }
+}
+
+impl Formula for Bailout {
+    fn step(&self, orbit: &Orbit, c: &Complex<f64>) -> Complex<f64> {
+        self.formula.step(orbit, c)
+    }
+
+    fn initial(&self, c: &Complex<f64>) -> Complex<f64> {
+        self.formula.initial(c)
+    }
+
+    fn second_initial(&self, c: &Complex<f64>) -> Option<Complex<f64>> {
+        self.formula.second_initial(c)
+    }
+
+    fn bailout(&self) -> f64 {
+        self.radius
+    }
+
+    fn converged(&self, orbit: &Orbit) -> bool {
+        self.formula.converged(orbit)
+    }
+
+    fn interior_check(&self, c: &Complex<f64>) -> bool {
+        self.formula.interior_check(c)
+    }
+
+    fn symmetric(&self) -> bool {
+        self.formula.symmetric()
+    }
+}
+
+/// Records the closest approach of each orbit of a formula to 0 and to
+/// the trap if there is one while iterating it
+pub struct Recorded {
+    formula: SharedFormula,
+    trap: Option<Trap>,
+}
+
+impl Recorded {
+    pub fn new(formula: SharedFormula, trap: Option<Trap>) -> Self {
+        Recorded { formula, trap }
+    }
+}
+
+impl Formula for Recorded {
+    fn step(&self, orbit: &Orbit, c: &Complex<f64>) -> Complex<f64> {
+        self.formula.step(orbit, c)
+    }
+
+    fn initial(&self, c: &Complex<f64>) -> Complex<f64> {
+        self.formula.initial(c)
+    }
+
+    fn second_initial(&self, c: &Complex<f64>) -> Option<Complex<f64>> {
+        self.formula.second_initial(c)
+    }
+
+    fn bailout(&self) -> f64 {
+        self.formula.bailout()
+    }
+
+    fn converged(&self, orbit: &Orbit) -> bool {
+        self.formula.converged(orbit)
+    }
+
+    /// The conjugate orbit has the same distances to 0 and to a trap on
+    /// the real axis
+    fn symmetric(&self) -> bool {
+        self.formula.symmetric() && self.trap.is_none_or(|trap| trap.center.im == 0.0)
+    }
+
+    fn escape_time(&self, c: &Complex<f64>, max_iter: u32) -> Outcome {
+        mandelbrot::iterate_recorded(self, c, max_iter, self.trap.as_ref())
+    }
+}
+
+/// The Mandelbrot set iterated in single precision for shallow views,
+/// orbits other than the escape time are still calculated in f64
+pub struct SinglePrecision {
+    radius: f64,
+}
+
+impl SinglePrecision {
+    pub fn new(radius: f64) -> Self {
+        SinglePrecision { radius }
+    }
+}
+
+impl Formula for SinglePrecision {
+    fn step(&self, orbit: &Orbit, c: &Complex<f64>) -> Complex<f64> {
+        orbit.z * orbit.z + c
+    }
+
+    fn bailout(&self) -> f64 {
+        self.radius
+    }
+
+    fn interior_check(&self, c: &Complex<f64>) -> bool {
+        mandelbrot::in_set(c)
+    }
+
+    fn symmetric(&self) -> bool {
+        true
+    }
+
+    fn escape_time(&self, c: &Complex<f64>, max_iter: u32) -> Outcome {
+        mandelbrot::mandel_f32(c, max_iter, self.radius)
+    }
+}
+
+/// Parses a comma separated list of fractal names, e.g. "mandelbrot,burningship"
+pub fn parse_schedule(names: &str) -> Result<Vec<Fractal>, String> {
+    let schedule = names
+        .split(',')
+        .map(|name| name.parse())
+        .collect::<Result<Vec<Fractal>, String>>()?;
+    Hybrid::new(schedule).map(|h| h.schedule)
+}
+
+impl Formula for Hybrid {
+    fn step(&self, orbit: &Orbit, c: &Complex<f64>) -> Complex<f64> {
+        let index = orbit.iteration as usize % self.schedule.len();
+        self.schedule[index].step(orbit, c)
+    }
+
+    fn initial(&self, c: &Complex<f64>) -> Complex<f64> {
+        self.schedule[0].initial(c)
+    }
+
+    fn bailout(&self) -> f64 {
+        self.schedule
+            .iter()
+            .map(|f| f.bailout())
+            .fold(0.0, f64::max)
+    }
+
+    fn converged(&self, orbit: &Orbit) -> bool {
+        self.schedule.iter().any(|f| f.converged(orbit))
+    }
+}
@@ -0,0 +1,422 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Position of each coefficient in the 8x8 block, in the order they are stored
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Luminance quantization table of the JPEG standard, quality 50
+const LUMA_QUANT: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// Chrominance quantization table of the JPEG standard, quality 50
+const CHROMA_QUANT: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+// Huffman tables of the JPEG standard, number of codes per length and the coded values
+const DC_LUMA_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_CHROMA_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const AC_LUMA_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const AC_LUMA_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+const AC_CHROMA_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const AC_CHROMA_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+/// Huffman code and code length for each value
+struct HuffmanTable {
+    codes: [(u16, u8); 256],
+}
+
+impl HuffmanTable {
+    fn new(bits: &[u8; 16], values: &[u8]) -> Self {
+        let mut codes = [(0, 0); 256];
+        let mut code = 0u16;
+        let mut values = values.iter();
+        for (length, &count) in bits.iter().enumerate() {
+            for _ in 0..count {
+                let value = *values.next().expect("Too few Huffman values");
+                codes[value as usize] = (code, length as u8 + 1);
+                code += 1;
+            }
+            code <<= 1;
+        }
+        HuffmanTable { codes }
+    }
+}
+
+/// Tables and prediction state of one color component
+struct Component {
+    quant: [u16; 64],
+    dc: HuffmanTable,
+    ac: HuffmanTable,
+    previous_dc: i32,
+}
+
+/// Writes bits most significant first, with the byte stuffing of JPEG
+struct BitWriter<W: Write> {
+    out: W,
+    buffer: u32,
+    count: u32,
+}
+
+impl<W: Write> BitWriter<W> {
+    fn write(&mut self, bits: u16, length: u8) -> std::io::Result<()> {
+        self.buffer = (self.buffer << length) | (bits as u32 & ((1 << length) - 1));
+        self.count += length as u32;
+        while self.count >= 8 {
+            let byte = (self.buffer >> (self.count - 8)) as u8;
+            self.out.write_all(&[byte])?;
+            if byte == 0xff {
+                self.out.write_all(&[0])?;
+            }
+            self.count -= 8;
+        }
+        Ok(())
+    }
+
+    /// Pads the last byte with ones
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.count {
+            0 => Ok(()),
+            n => self.write(0x7f, 8 - n as u8),
+        }
+    }
+}
+
+/// Writes RGBA pixel data, 4 bytes per pixel, as a baseline JPEG file.
+/// Quality ranges from 1 to 100, alpha is ignored, metadata is written as comments.
+pub fn write_jpeg(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    quality: u8,
+    metadata: &[(&str, String)],
+) -> Result<(), String> {
+    if rgba.len() != width as usize * height as usize * 4 {
+        return Err(format!(
+            "Pixel data of {} bytes does not match image size {}x{}",
+            rgba.len(),
+            width,
+            height
+        ));
+    }
+    if width > 65535 || height > 65535 {
+        return Err(format!(
+            "Image size {}x{} is too large for JPEG",
+            width, height
+        ));
+    }
+
+    let file = File::create(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+    let mut out = BufWriter::new(file);
+    encode(&mut out, width, height, rgba, quality, metadata)
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+fn encode<W: Write>(
+    out: &mut W,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    quality: u8,
+    metadata: &[(&str, String)],
+) -> std::io::Result<()> {
+    let mut components = [
+        Component {
+            quant: scale_quant(&LUMA_QUANT, quality),
+            dc: HuffmanTable::new(&DC_LUMA_BITS, &DC_VALUES),
+            ac: HuffmanTable::new(&AC_LUMA_BITS, &AC_LUMA_VALUES),
+            previous_dc: 0,
+        },
+        Component {
+            quant: scale_quant(&CHROMA_QUANT, quality),
+            dc: HuffmanTable::new(&DC_CHROMA_BITS, &DC_VALUES),
+            ac: HuffmanTable::new(&AC_CHROMA_BITS, &AC_CHROMA_VALUES),
+            previous_dc: 0,
+        },
+        Component {
+            quant: scale_quant(&CHROMA_QUANT, quality),
+            dc: HuffmanTable::new(&DC_CHROMA_BITS, &DC_VALUES),
+            ac: HuffmanTable::new(&AC_CHROMA_BITS, &AC_CHROMA_VALUES),
+            previous_dc: 0,
+        },
+    ];
+
+    out.write_all(&[0xff, 0xd8])?;
+    write_segment(
+        out,
+        0xe0,
+        &[b'J', b'F', b'I', b'F', 0, 1, 1, 0, 0, 1, 0, 1, 0, 0],
+    )?;
+    for (keyword, text) in metadata {
+        write_segment(out, 0xfe, format!("{}: {}", keyword, text).as_bytes())?;
+    }
+
+    let mut tables = Vec::with_capacity(130);
+    for (id, component) in components.iter().take(2).enumerate() {
+        tables.push(id as u8);
+        tables.extend(ZIGZAG.iter().map(|&i| component.quant[i] as u8));
+    }
+    write_segment(out, 0xdb, &tables)?;
+
+    let mut frame = Vec::with_capacity(15);
+    frame.push(8);
+    frame.extend_from_slice(&(height as u16).to_be_bytes());
+    frame.extend_from_slice(&(width as u16).to_be_bytes());
+    // Y, Cb and Cr without subsampling, the chroma channels share table 1
+    frame.extend_from_slice(&[3, 1, 0x11, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    write_segment(out, 0xc0, &frame)?;
+
+    let mut huffman = Vec::new();
+    for (class_id, bits, values) in [
+        (0x00, &DC_LUMA_BITS, &DC_VALUES[..]),
+        (0x10, &AC_LUMA_BITS, &AC_LUMA_VALUES[..]),
+        (0x01, &DC_CHROMA_BITS, &DC_VALUES[..]),
+        (0x11, &AC_CHROMA_BITS, &AC_CHROMA_VALUES[..]),
+    ] {
+        huffman.push(class_id);
+        huffman.extend_from_slice(bits);
+        huffman.extend_from_slice(values);
+    }
+    write_segment(out, 0xc4, &huffman)?;
+
+    write_segment(out, 0xda, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0])?;
+
+    let cosines = dct_cosines();
+    let mut bits = BitWriter {
+        out: &mut *out,
+        buffer: 0,
+        count: 0,
+    };
+    let mut pixels = [[0.0; 3]; 64];
+    for block_y in (0..height).step_by(8) {
+        for block_x in (0..width).step_by(8) {
+            // edge blocks repeat the last row and column
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                let x = (block_x + i as u32 % 8).min(width - 1) as usize;
+                let y = (block_y + i as u32 / 8).min(height - 1) as usize;
+                let p = &rgba[(y * width as usize + x) * 4..];
+                let (r, g, b) = (p[0] as f64, p[1] as f64, p[2] as f64);
+                // YCbCr, shifted to be centered around 0
+                *pixel = [
+                    0.299 * r + 0.587 * g + 0.114 * b - 128.0,
+                    -0.168_736 * r - 0.331_264 * g + 0.5 * b,
+                    0.5 * r - 0.418_688 * g - 0.081_312 * b,
+                ];
+            }
+            for (channel, component) in components.iter_mut().enumerate() {
+                let mut block = [0.0; 64];
+                for (sample, pixel) in block.iter_mut().zip(pixels.iter()) {
+                    *sample = pixel[channel];
+                }
+                encode_block(&mut bits, component, &dct(&block, &cosines))?;
+            }
+        }
+    }
+    bits.flush()?;
+
+    out.write_all(&[0xff, 0xd9])
+}
+
+fn write_segment<W: Write>(out: &mut W, marker: u8, data: &[u8]) -> std::io::Result<()> {
+    out.write_all(&[0xff, marker])?;
+    out.write_all(&(data.len() as u16 + 2).to_be_bytes())?;
+    out.write_all(data)
+}
+
+/// Scales a quantization table of quality 50 like the IJG library
+fn scale_quant(table: &[u16; 64], quality: u8) -> [u16; 64] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = match quality < 50 {
+        true => 5000 / quality,
+        false => 200 - quality * 2,
+    };
+
+    let mut scaled = [0; 64];
+    for (s, &q) in scaled.iter_mut().zip(table.iter()) {
+        *s = ((q as u32 * scale + 50) / 100).clamp(1, 255) as u16;
+    }
+    scaled
+}
+
+/// Cosine factors of the 8 point DCT, including the normalization
+fn dct_cosines() -> [[f64; 8]; 8] {
+    let mut cosines = [[0.0; 8]; 8];
+    for (u, row) in cosines.iter_mut().enumerate() {
+        let norm = if u == 0 { 0.5 / 2f64.sqrt() } else { 0.5 };
+        for (x, c) in row.iter_mut().enumerate() {
+            *c = norm * ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / 16.0).cos();
+        }
+    }
+    cosines
+}
+
+/// Two dimensional DCT of an 8x8 block in row order
+fn dct(block: &[f64; 64], cosines: &[[f64; 8]; 8]) -> [f64; 64] {
+    let mut rows = [0.0; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..8).map(|x| cosines[u][x] * block[y * 8 + x]).sum();
+        }
+    }
+
+    let mut coefficients = [0.0; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coefficients[v * 8 + u] = (0..8).map(|y| cosines[v][y] * rows[y * 8 + u]).sum();
+        }
+    }
+    coefficients
+}
+
+/// Number of bits of the value and its bit pattern, negative values
+/// are stored as the one's complement
+fn magnitude(value: i32) -> (u16, u8) {
+    let length = 32 - value.unsigned_abs().leading_zeros();
+    let bits = match value < 0 {
+        true => value - 1,
+        false => value,
+    };
+    ((bits & ((1 << length) - 1)) as u16, length as u8)
+}
+
+fn encode_block<W: Write>(
+    out: &mut BitWriter<W>,
+    component: &mut Component,
+    coefficients: &[f64; 64],
+) -> std::io::Result<()> {
+    let mut quantized = [0; 64];
+    for (i, &index) in ZIGZAG.iter().enumerate() {
+        quantized[i] = (coefficients[index] / component.quant[index] as f64).round() as i32;
+    }
+
+    let (bits, length) = magnitude(quantized[0] - component.previous_dc);
+    component.previous_dc = quantized[0];
+    let (code, code_length) = component.dc.codes[length as usize];
+    out.write(code, code_length)?;
+    out.write(bits, length)?;
+
+    let mut zeros = 0;
+    for &value in quantized[1..].iter() {
+        if value == 0 {
+            zeros += 1;
+            continue;
+        }
+        while zeros >= 16 {
+            let (code, code_length) = component.ac.codes[0xf0];
+            out.write(code, code_length)?;
+            zeros -= 16;
+        }
+        let (bits, length) = magnitude(value);
+        let (code, code_length) = component.ac.codes[(zeros << 4) | length as usize];
+        out.write(code, code_length)?;
+        out.write(bits, length)?;
+        zeros = 0;
+    }
+    if zeros > 0 {
+        let (code, code_length) = component.ac.codes[0x00];
+        out.write(code, code_length)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magnitude() {
+        assert_eq!(magnitude(0), (0, 0));
+        assert_eq!(magnitude(5), (0b101, 3));
+        assert_eq!(magnitude(-5), (0b010, 3));
+        assert_eq!(scale_quant(&LUMA_QUANT, 50), LUMA_QUANT);
+    }
+
+    #[test]
+    fn test_stuffing() {
+        let mut out = Vec::new();
+        let mut bits = BitWriter {
+            out: &mut out,
+            buffer: 0,
+            count: 0,
+        };
+        bits.write(0xff, 8).unwrap();
+        bits.write(0b0, 1).unwrap();
+        bits.flush().unwrap();
+        // a single one bit is padded to 0xff, which is stuffed as well
+        bits.write(0b1, 1).unwrap();
+        bits.flush().unwrap();
+        assert_eq!(out, [0xff, 0x00, 0x7f, 0xff, 0x00]);
+    }
+
+    #[test]
+    fn test_encode() {
+        let gray = vec![128; 8 * 8 * 4];
+        let mut out = Vec::new();
+        encode(&mut out, 8, 8, &gray, 50, &[("Title", "gray".to_string())]).unwrap();
+        assert_eq!(&out[..2], &[0xff, 0xd8]);
+        assert_eq!(&out[out.len() - 2..], &[0xff, 0xd9]);
+
+        // the segments up to the scan, each with its length
+        let mut markers = Vec::new();
+        let mut pos = 2;
+        loop {
+            assert_eq!(out[pos], 0xff);
+            let length = u16::from_be_bytes([out[pos + 2], out[pos + 3]]) as usize;
+            markers.push(out[pos + 1]);
+            if out[pos + 1] == 0xc0 {
+                // precision, height and width
+                assert_eq!(&out[pos + 4..pos + 9], &[8, 0, 8, 0, 8]);
+            }
+            pos += 2 + length;
+            if markers.last() == Some(&0xda) {
+                break;
+            }
+        }
+        assert_eq!(markers, [0xe0, 0xfe, 0xdb, 0xc0, 0xc4, 0xda]);
+        // the metadata follows the JFIF header as a comment
+        assert_eq!(&out[20..35], b"\xff\xfe\x00\x0dTitle: gray");
+
+        // all coefficients of the gray block are 0: the luma DC code 00
+        // and end of block 1010, the chroma DC and end of block codes 00
+        assert_eq!(&out[pos..], &[0b0010_1000, 0b0000_0011, 0xff, 0xd9]);
+    }
+}
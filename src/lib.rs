@@ -0,0 +1,28 @@
+extern crate num;
+extern crate sdl2;
+
+pub mod app;
+pub mod backend;
+pub mod cache;
+pub mod deep;
+pub mod desktop;
+pub mod double;
+pub mod export;
+pub mod formula;
+pub mod fractal;
+pub mod jpeg;
+pub mod lyapunov;
+pub mod macros;
+pub mod mandelbrot;
+pub mod options;
+pub mod pacing;
+pub mod palette;
+pub mod power;
+pub mod quaternion;
+pub mod share;
+pub mod stats;
+pub mod timing;
+pub mod trap;
+pub mod types;
+pub mod viewport;
+pub mod workers;
@@ -0,0 +1,118 @@
+use crate::mandelbrot::{self, RenderContext};
+use crate::timing;
+use crate::types::MandelImage;
+use crate::types::Transform;
+
+use num::complex::Complex;
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Number of iterations to settle the logistic map before measuring
+const WARMUP: u32 = 50;
+
+/// Sequence of the classic "Zircon Zity" image
+pub const DEFAULT_SEQUENCE: &str = "BBBBBBAAAAAA";
+
+/// Center of the "Zircon Zity" region, a along the real and b along the imaginary axis
+pub fn default_center() -> Complex<f64> {
+    Complex::new(3.7, 2.95)
+}
+
+/// Parses a sequence of 'A' and 'B' characters,
+/// each entry is true where the 'B' parameter should be used
+pub fn parse_sequence(sequence: &str) -> Result<Vec<bool>, String> {
+    if sequence.is_empty() {
+        return Err("Lyapunov sequence must not be empty".to_string());
+    }
+
+    sequence
+        .chars()
+        .map(|ch| match ch.to_ascii_uppercase() {
+            'A' => Ok(false),
+            'B' => Ok(true),
+            _ => Err(format!("Invalid character '{}' in Lyapunov sequence", ch)),
+        })
+        .collect()
+}
+
+/// Calculates the Lyapunov exponent of the logistic map where the
+/// growth rate alternates between a and b according to the sequence.
+/// Negative values are stable, positive values are chaotic.
+pub fn exponent(a: f64, b: f64, sequence: &[bool], iterations: u32) -> f64 {
+    let rate = |n: u32| match sequence[n as usize % sequence.len()] {
+        false => a,
+        true => b,
+    };
+
+    let mut x = 0.5;
+    for n in 0..WARMUP {
+        x = rate(n) * x * (1.0 - x);
+    }
+
+    let mut sum = 0.0;
+    for n in WARMUP..(WARMUP + iterations) {
+        let r = rate(n);
+        x = r * x * (1.0 - x);
+        sum += (r * (1.0 - 2.0 * x)).abs().max(f64::MIN_POSITIVE).ln();
+    }
+
+    sum / iterations as f64
+}
+
+/// multithreaded Lyapunov image generation, stores the exponent as pixel value
+pub fn generate_image_thread(
+    context: &mut RenderContext,
+    transform: &Transform,
+    sequence: &Arc<Vec<bool>>,
+    image: &mut MandelImage,
+) {
+    let start = SystemTime::now();
+
+    let trans = transform.clone();
+    let sequence = sequence.clone();
+    let iterations = image.max_iterations;
+
+    let thread_count = mandelbrot::generate_tiles(
+        &mut context.values,
+        image.width,
+        image.height,
+        move |x, y| {
+            let z = trans.pos_to_complex(x, y);
+            exponent(z.re, z.im, &sequence, iterations)
+        },
+        |tile, values| image.set_values(tile, values),
+    );
+
+    timing::record(
+        "generate",
+        start,
+        format_args!(
+            "Generated Lyapunov image with {} threads and {} iterations",
+            thread_count, iterations
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sequence() {
+        assert_eq!(parse_sequence("AbBa"), Ok(vec![false, true, true, false]));
+        assert!(parse_sequence("").is_err());
+        assert!(parse_sequence("ABC").is_err());
+    }
+
+    #[test]
+    fn test_exponent() {
+        let sequence = parse_sequence("AB").unwrap();
+
+        // chaotic region gives a positive exponent
+        assert!(exponent(3.9, 3.9, &sequence, 1000) > 0.0);
+
+        // stable fixed point gives a negative exponent
+        assert!(exponent(2.5, 2.5, &sequence, 1000) < 0.0);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::app::MandelEvent;
+use crate::options;
+
+/// Actions run by a function key, e.g. "F5=scheme rainbow; iterations 5000; zoom 4x; export"
+#[derive(Clone)]
+pub struct Macro {
+    /// name of the function key, F1 to F12
+    pub key: String,
+    pub actions: Vec<MandelEvent>,
+}
+
+/// Checks if a key name is a function key that can run a macro
+pub fn is_macro_key(name: &str) -> bool {
+    name.strip_prefix('F')
+        .and_then(|n| n.parse::<u32>().ok())
+        .is_some_and(|n| (1..=12).contains(&n))
+}
+
+/// Parses a key binding of the form "<key>=<action>; <action>; ..."
+pub fn parse_macro(value: &str) -> Result<Macro, String> {
+    let (key, actions) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid macro '{}', expected <key>=<actions>", value))?;
+    let key = key.trim().to_uppercase();
+    if !is_macro_key(&key) {
+        return Err(format!("Invalid macro key '{}', expected F1 to F12", key));
+    }
+
+    let actions = actions
+        .split(';')
+        .filter(|action| !action.trim().is_empty())
+        .map(parse_action)
+        .collect::<Result<Vec<_>, _>>()?;
+    if actions.is_empty() {
+        return Err(format!("Macro '{}' has no actions", key));
+    }
+
+    Ok(Macro { key, actions })
+}
+
+/// Parses a single action of a macro into the event of its key
+fn parse_action(action: &str) -> Result<MandelEvent, String> {
+    let mut words = action.split_whitespace();
+    let name = words.next().unwrap_or_default().to_lowercase();
+    let argument = words.next();
+    if words.next().is_some() {
+        return Err(format!("Too many arguments in action '{}'", action.trim()));
+    }
+
+    let event = match (name.as_str(), argument) {
+        ("scheme", Some(scheme)) => MandelEvent::Scheme(scheme.parse()?),
+        ("iterations", Some(n)) => MandelEvent::SetIterations(options::validate_iterations(
+            n.parse().map_err(|_| format!("Invalid number '{}'", n))?,
+        )?),
+        ("zoom", Some(factor)) => {
+            MandelEvent::Zoom(options::parse_zoom(factor.trim_end_matches('x'))?)
+        }
+        ("depth", Some(zoom)) => MandelEvent::ZoomTo(options::parse_zoom(zoom)?),
+        ("reset", None) => MandelEvent::Zoom(0.0),
+        ("histogram", None) => MandelEvent::ToggleHistogram,
+        ("smooth", None) => MandelEvent::ToggleSmooth,
+        ("export", None) => MandelEvent::Export,
+        _ => return Err(format!("Unknown action '{}'", action.trim())),
+    };
+
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_macro() {
+        let m = parse_macro("f5=scheme rainbow; iterations 5000; zoom 4x; export").unwrap();
+        assert_eq!(m.key, "F5");
+        assert_eq!(m.actions.len(), 4);
+        assert!(matches!(m.actions[1], MandelEvent::SetIterations(5000)));
+        assert!(matches!(m.actions[2], MandelEvent::Zoom(z) if z == 4.0));
+
+        assert!(parse_macro("F13=export").is_err());
+        assert!(parse_macro("F1=").is_err());
+        assert!(parse_macro("F1=zoom").is_err());
+        assert!(parse_macro("F1=scheme purple").is_err());
+        assert!(parse_macro("F1=export now").is_err());
+        assert!(parse_macro("export").is_err());
+    }
+}
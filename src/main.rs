@@ -1,73 +1,108 @@
 extern crate num;
 extern crate sdl2;
 
-mod mandelbrot;
-mod palette;
-mod types;
-
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::clipboard::ClipboardUtil;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
 use sdl2::mouse::MouseButton;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::{Point, Rect};
+use sdl2::surface::Surface;
 use sdl2::video::Window;
 
 use std::thread;
 use std::time::SystemTime;
 
-use palette::ColorScheme;
-use types::MandelImage;
-use types::MandelPixel;
-use types::Transform;
-
-/// Keeps the draw settings
-struct DrawSettings {
-    run: bool,
-    update_image: bool,
-    update_texture: bool,
-    use_histogram: bool,
-    show_colors: bool,
-    color_scheme: ColorScheme,
-}
+use mandelbrust::app::{App, MandelEvent, MORPH_STEP};
+use mandelbrust::options::Options;
+use mandelbrust::pacing::FramePacing;
+use mandelbrust::palette::{ColorScheme, RgbaBuffer};
+use mandelbrust::share::Session;
+use mandelbrust::types::PixelRect;
+use mandelbrust::viewport::Viewport;
+use mandelbrust::{desktop, macros, mandelbrot, options, timing, workers};
 
-impl DrawSettings {
-    fn new() -> Self {
-        DrawSettings {
-            run: true,
-            update_image: true,
-            update_texture: true,
-            use_histogram: false,
-            show_colors: false,
-            color_scheme: ColorScheme::Green,
-        }
-    }
-}
+/// Distance the quaternion slice and the cubic parameter move per key press
+const SLICE_STEP: f64 = 0.05;
+
+/// Fraction of the palette it is rotated by per key press
+const PALETTE_SHIFT: f64 = 1.0 / 32.0;
+
+/// Factor the palette repeats change by per key press
+const PALETTE_SCALE: f64 = 1.25;
+
+/// Radius of the magnifier loupe in window pixels
+const LOUPE_RADIUS: i32 = 120;
+
+/// Magnification of the loupe relative to the image on screen
+const LOUPE_ZOOM: f64 = 4.0;
+
+/// Sleep after each frame in milliseconds
+const FRAME_SLEEP: u64 = 50;
+
+/// Sleep after idle frames on battery power in milliseconds
+const POWER_SAVING_SLEEP: u64 = 200;
 
 /// Owns SDL objects
 struct Sdl {
     canvas: sdl2::render::Canvas<Window>,
     event_pump: sdl2::EventPump,
+    clipboard: ClipboardUtil,
     texture_creator: sdl2::render::TextureCreator<sdl2::video::WindowContext>,
 }
 
-fn setup_sdl(width: u32, height: u32) -> Result<Sdl, String> {
+/// Everything drawn on top of the fractal texture, compared between
+/// frames so an idle window is not drawn again
+#[derive(Copy, Clone, PartialEq)]
+struct Overlays {
+    split: Option<i32>,
+    julia_grid: bool,
+    exclusion: Option<PixelRect>,
+    /// cursor position while the loupe is shown
+    loupe: Option<(i32, i32)>,
+}
+
+fn setup_sdl(window_size: (u32, u32), texture_size: (u32, u32)) -> Result<Sdl, String> {
+    let (width, height) = texture_size;
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
 
-    let window = video_subsystem
-        .window("MandelbRust", width, height)
+    let mut window = video_subsystem
+        .window("MandelbRust", window_size.0, window_size.1)
         .position_centered()
         .opengl()
         .build()
         .map_err(|e| e.to_string())?;
 
+    let mut icon = desktop::icon();
+    let pitch = icon.pitch() as u32;
+    let surface = Surface::from_data(
+        &mut icon.data,
+        icon.width,
+        icon.height,
+        pitch,
+        PixelFormatEnum::RGBA32,
+    )?;
+    window.set_icon(surface);
+
     let mut canvas = window
         .into_canvas()
         .present_vsync()
         .build()
         .map_err(|e| e.to_string())?;
 
+    let info = canvas.info();
+    if (info.max_texture_width > 0 && width > info.max_texture_width)
+        || (info.max_texture_height > 0 && height > info.max_texture_height)
+    {
+        return Err(format!(
+            "Image size {}x{} exceeds the texture limit {}x{} of the renderer",
+            width, height, info.max_texture_width, info.max_texture_height
+        ));
+    }
+
     let event_pump = sdl_context.event_pump()?;
+    let clipboard = video_subsystem.clipboard();
     let texture_creator = canvas.texture_creator();
 
     canvas.set_draw_color(Color::RGB(0, 0, 0));
@@ -80,24 +115,16 @@ fn setup_sdl(width: u32, height: u32) -> Result<Sdl, String> {
     Ok(Sdl {
         canvas,
         event_pump,
+        clipboard,
         texture_creator,
     })
 }
 
-/// Defined the different user events
-enum MandelEvent {
-    Idle,
-    Quit,
-    Zoom(f64),
-    Scheme(ColorScheme),
-    Center(i32, i32),
-    ChangeIterations(f64),
-    ToggleHistogram,
-    ToggleColorschemes,
-    ShowInfo(i32, i32),
-}
+fn get_event(event_pump: &mut sdl2::EventPump, clipboard: &ClipboardUtil) -> MandelEvent {
+    // only the last position of a drag is of interest
+    let mut drag = None;
+    let mut exposed = false;
 
-fn get_event(event_pump: &mut sdl2::EventPump) -> MandelEvent {
     for event in event_pump.poll_iter() {
         match event {
             Event::Quit { .. }
@@ -129,12 +156,237 @@ fn get_event(event_pump: &mut sdl2::EventPump) -> MandelEvent {
             } => {
                 return MandelEvent::ToggleHistogram;
             }
+            Event::KeyDown {
+                keycode: Some(Keycode::G),
+                ..
+            } => {
+                return MandelEvent::ToggleSmooth;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::B),
+                ..
+            } => {
+                return MandelEvent::TogglePowerSaving;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::S),
+                ..
+            } => {
+                return MandelEvent::ToggleSplit;
+            }
             Event::KeyDown {
                 keycode: Some(Keycode::C),
                 ..
             } => {
                 return MandelEvent::ToggleColorschemes;
             }
+            Event::KeyDown {
+                keycode: Some(Keycode::F),
+                ..
+            } => {
+                return MandelEvent::NextFractal;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::L),
+                ..
+            } => {
+                return MandelEvent::ToggleLyapunov;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::U),
+                ..
+            } => {
+                return MandelEvent::ToggleFormula;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::V),
+                ..
+            } => {
+                return MandelEvent::EditFormula;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Y),
+                ..
+            } => {
+                return MandelEvent::ToggleHybrid;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Insert),
+                ..
+            } => {
+                return MandelEvent::AppendHybrid;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Backspace),
+                ..
+            } => {
+                return MandelEvent::RemoveHybrid;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::E),
+                ..
+            } => {
+                return MandelEvent::Export;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::O),
+                ..
+            } => {
+                return MandelEvent::ToggleOrbitExport;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Q),
+                ..
+            } => {
+                return MandelEvent::ToggleQuaternion;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::I),
+                ..
+            } => {
+                return MandelEvent::ToggleInverse;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::X),
+                ..
+            } => {
+                return MandelEvent::ToggleExponential;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::K),
+                ..
+            } => {
+                return MandelEvent::ToggleCubic;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::M),
+                ..
+            } => {
+                return MandelEvent::Morph(MORPH_STEP);
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::N),
+                ..
+            } => {
+                return MandelEvent::Morph(-MORPH_STEP);
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::T),
+                ..
+            } => {
+                return MandelEvent::ShowStats;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::R),
+                ..
+            } => {
+                return MandelEvent::NextBailout;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::W),
+                ..
+            } => {
+                return MandelEvent::ToggleDistance;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::P),
+                ..
+            } => {
+                return MandelEvent::ToggleInterior;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Tab),
+                ..
+            } => {
+                return MandelEvent::NextTrap;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Left),
+                ..
+            } => {
+                return MandelEvent::MoveTrap(-1, 0);
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Right),
+                ..
+            } => {
+                return MandelEvent::MoveTrap(1, 0);
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Up),
+                ..
+            } => {
+                return MandelEvent::MoveTrap(0, 1);
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Down),
+                ..
+            } => {
+                return MandelEvent::MoveTrap(0, -1);
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::D),
+                ..
+            } => {
+                return MandelEvent::NextShading;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Backslash),
+                keymod,
+                ..
+            } => {
+                return match keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                    true => MandelEvent::ShiftPalette(-PALETTE_SHIFT),
+                    false => MandelEvent::ShiftPalette(PALETTE_SHIFT),
+                };
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Quote),
+                keymod,
+                ..
+            } => {
+                return match keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                    true => MandelEvent::ScalePalette(1.0 / PALETTE_SCALE),
+                    false => MandelEvent::ScalePalette(PALETTE_SCALE),
+                };
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::LeftBracket),
+                ..
+            } => {
+                return MandelEvent::MoveSlice(-SLICE_STEP, 0.0);
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::RightBracket),
+                ..
+            } => {
+                return MandelEvent::MoveSlice(SLICE_STEP, 0.0);
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Comma),
+                ..
+            } => {
+                return MandelEvent::MoveSlice(0.0, -SLICE_STEP);
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Period),
+                ..
+            } => {
+                return MandelEvent::MoveSlice(0.0, SLICE_STEP);
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Z),
+                ..
+            } => {
+                // magnification pasted from published coordinates
+                match clipboard
+                    .clipboard_text()
+                    .and_then(|text| options::parse_zoom(&text))
+                {
+                    Ok(zoom) => return MandelEvent::ZoomTo(zoom),
+                    Err(e) => println!("Zoom from clipboard: {}", e),
+                }
+            }
             Event::KeyDown {
                 keycode: Some(Keycode::PageUp),
                 ..
@@ -147,6 +399,12 @@ fn get_event(event_pump: &mut sdl2::EventPump) -> MandelEvent {
             } => {
                 return MandelEvent::ChangeIterations(0.5);
             }
+            Event::KeyDown {
+                keycode: Some(Keycode::A),
+                ..
+            } => {
+                return MandelEvent::ToggleAutoIterations;
+            }
             Event::KeyDown {
                 keycode: Some(Keycode::Num1),
                 ..
@@ -171,6 +429,66 @@ fn get_event(event_pump: &mut sdl2::EventPump) -> MandelEvent {
             } => {
                 return MandelEvent::Scheme(ColorScheme::Rainbow);
             }
+            Event::KeyDown {
+                keycode: Some(Keycode::Num5),
+                ..
+            } => {
+                return MandelEvent::NextPalette;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Num6),
+                ..
+            } => {
+                return MandelEvent::ToggleBinary;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Num7),
+                ..
+            } => {
+                return MandelEvent::ToggleAngle;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Num8),
+                ..
+            } => {
+                return MandelEvent::TogglePotential;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Slash),
+                ..
+            } => {
+                return MandelEvent::ToggleAtomDomain;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Num9),
+                ..
+            } => {
+                return MandelEvent::ToggleFollow;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Num0),
+                ..
+            } => {
+                return MandelEvent::NextStrategy;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Semicolon),
+                ..
+            } => {
+                return MandelEvent::NextBackend;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Home),
+                ..
+            } => {
+                return MandelEvent::ToggleJuliaMap;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::End),
+                ..
+            } => {
+                return MandelEvent::ToggleZoomIterations;
+            }
             Event::MouseButtonDown {
                 x,
                 y,
@@ -187,170 +505,298 @@ fn get_event(event_pump: &mut sdl2::EventPump) -> MandelEvent {
             } => {
                 return MandelEvent::ShowInfo(x, y);
             }
+            Event::MouseButtonDown {
+                x,
+                y,
+                mouse_btn: MouseButton::Middle,
+                ..
+            } => {
+                return MandelEvent::SelectStart(x, y);
+            }
+            Event::MouseButtonUp {
+                x,
+                y,
+                mouse_btn: MouseButton::Middle,
+                ..
+            } => {
+                return MandelEvent::SelectEnd(x, y);
+            }
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } if macros::is_macro_key(&keycode.name()) => {
+                return MandelEvent::Macro(keycode.name());
+            }
+            Event::MouseMotion { x, mousestate, .. } if mousestate.left() => {
+                drag = Some(x);
+            }
+            Event::Window {
+                win_event:
+                    WindowEvent::Exposed | WindowEvent::SizeChanged(..) | WindowEvent::Restored,
+                ..
+            } => {
+                exposed = true;
+            }
             _ => {}
         }
     }
 
-    MandelEvent::Idle
+    match (drag, exposed) {
+        (Some(x), _) => MandelEvent::Drag(x),
+        (None, true) => MandelEvent::Redraw,
+        (None, false) => MandelEvent::Idle,
+    }
+}
+
+/// Maps mouse positions of an event from window to image coordinates
+fn map_event(e: MandelEvent, view: &Viewport, window_size: (u32, u32)) -> MandelEvent {
+    let map = |x, y| view.window_to_viewport(window_size, x, y);
+    match e {
+        MandelEvent::Center(x, y) => {
+            let (x, y) = map(x, y);
+            MandelEvent::Center(x, y)
+        }
+        MandelEvent::ShowInfo(x, y) => {
+            let (x, y) = map(x, y);
+            MandelEvent::ShowInfo(x, y)
+        }
+        MandelEvent::SelectStart(x, y) => {
+            let (x, y) = map(x, y);
+            MandelEvent::SelectStart(x, y)
+        }
+        MandelEvent::SelectEnd(x, y) => {
+            let (x, y) = map(x, y);
+            MandelEvent::SelectEnd(x, y)
+        }
+        MandelEvent::Drag(x) => MandelEvent::Drag(map(x, 0).0),
+        e => e,
+    }
 }
 
 pub fn main() -> Result<(), String> {
-    let mut image = MandelImage::new(1000, 800, 150);
-    let mut transform = Transform::new((image.width, image.height));
-    let mut settings = DrawSettings::new();
-    let mut sdl = setup_sdl(image.width, image.height)?;
+    let options = Options::parse(std::env::args().skip(1))?;
+    if options.install_desktop_entry {
+        let path = desktop::install_desktop_entry()?;
+        println!("Installed desktop entry {:?}", path);
+        return Ok(());
+    }
+    let window = options.window;
+    let frame_diagnostics = options.frame_diagnostics;
+    timing::set_logging(options.log_timings);
+    workers::set_pinning(options.pin_threads);
+    let session = Session::open(options.host.as_deref(), options.join.as_deref())?;
+    let mut app = App::new(options);
+    app.set_session(session);
+    let (width, height) = app.image_size();
+    let view = Viewport::new(width, height);
+    let window_size = window.unwrap_or((width, height));
+    let mut sdl = setup_sdl(window_size, (width, height))?;
 
     let mut mandel_texture = sdl
         .texture_creator
-        .create_texture_target(
-            sdl.texture_creator.default_pixel_format(),
-            image.width,
-            image.height,
-        )
+        .create_texture_streaming(PixelFormatEnum::RGBA32, width, height)
         .expect("Failed to create mandel texture");
-    let mut color_texture = sdl
-        .texture_creator
-        .create_texture_target(
-            sdl.texture_creator.default_pixel_format(),
-            image.width,
-            image.height,
-        )
-        .expect("Failed to create color texture");
-
-    draw_color_texture(&mut sdl.canvas, &mut color_texture);
-
-    while settings.run {
-        let e = get_event(&mut sdl.event_pump);
-        match e {
-            MandelEvent::Quit => settings.run = false,
-            MandelEvent::Zoom(factor) => {
-                if factor > 0.0 {
-                    transform.zoom(factor);
-                } else {
-                    transform.reset();
-                    image.max_iterations = 150;
-                }
-                settings.update_image = true;
-            }
-            MandelEvent::Center(x, y) => {
-                transform.center_at(&transform.pos_to_complex(x, y));
-                settings.update_image = true;
-            }
-            MandelEvent::Scheme(scheme) => {
-                settings.color_scheme = scheme;
-                settings.update_texture = true;
-            }
-            MandelEvent::ToggleHistogram => {
-                settings.use_histogram = !settings.use_histogram;
-                settings.update_texture = true;
-            }
-            MandelEvent::ToggleColorschemes => {
-                settings.show_colors = !settings.show_colors;
-            }
-            MandelEvent::ChangeIterations(factor) => {
-                image.max_iterations = (image.max_iterations as f64 * factor).round() as u32;
-                settings.update_image = true;
+
+    let refresh_rate = sdl
+        .canvas
+        .window()
+        .display_mode()
+        .map_or(0, |mode| mode.refresh_rate);
+    let mut pacing = FramePacing::new(refresh_rate);
+    let mut title = String::new();
+    let mut drawn: Option<Overlays> = None;
+
+    while app.running() {
+        pacing.start();
+        let event = get_event(&mut sdl.event_pump, &sdl.clipboard);
+        let input = !matches!(event, MandelEvent::Idle);
+        app.handle_event(map_event(event, &view, window_size));
+        pacing.stage("events");
+        let new_frame = app.tick();
+        pacing.stage("render");
+        if new_frame {
+            draw_texture(&mut mandel_texture, app.frame())?;
+        }
+        let status = app.status();
+        if status != title {
+            sdl.canvas
+                .window_mut()
+                .set_title(&format!("MandelbRust - {}", status))
+                .map_err(|e| e.to_string())?;
+            title = status;
+        }
+        pacing.stage("upload");
+
+        let settings = app.settings();
+        // the loupe is shown while the key is held
+        let loupe = sdl
+            .event_pump
+            .keyboard_state()
+            .is_scancode_pressed(Scancode::J)
+            .then(|| {
+                let mouse = sdl.event_pump.mouse_state();
+                (mouse.x(), mouse.y())
+            });
+        let overlays = match settings.show_colors {
+            true => Overlays {
+                split: None,
+                julia_grid: false,
+                exclusion: None,
+                loupe,
+            },
+            false => Overlays {
+                split: settings.split_position,
+                julia_grid: settings.julia_map,
+                exclusion: settings.exclusion,
+                loupe,
+            },
+        };
+        // an idle frame with unchanged overlays leaves the window as it is
+        if !new_frame && !input && drawn == Some(overlays) {
+            thread::sleep(std::time::Duration::from_millis(idle_sleep(&app)));
+            continue;
+        }
+        drawn = Some(overlays);
+
+        let letterbox = view.letterbox(window_size);
+        sdl.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        sdl.canvas.clear();
+        sdl.canvas.copy(&mandel_texture, None, Some(letterbox))?;
+        if let Some(split) = overlays.split {
+            let (x, _) = view.viewport_to_window(window_size, split, 0);
+            sdl.canvas.set_draw_color(Color::RGB(255, 255, 255));
+            sdl.canvas.draw_line(
+                Point::new(x, letterbox.top()),
+                Point::new(x, letterbox.bottom()),
+            )?;
+        }
+        // composite the Julia thumbnails with a grid between the cells
+        if overlays.julia_grid {
+            let (width, height) = (width as i32, height as i32);
+            let cell = mandelbrot::JULIA_CELL as usize;
+            sdl.canvas.set_draw_color(Color::RGB(96, 96, 96));
+            for x in (cell as i32..width).step_by(cell) {
+                let top = view.viewport_to_window(window_size, x, 0);
+                let bottom = view.viewport_to_window(window_size, x, height);
+                sdl.canvas.draw_line(top, bottom)?;
             }
-            MandelEvent::ShowInfo(x, y) => {
-                let z = transform.pos_to_complex(x, y);
-                println!(
-                    "Comples: [{}, {}i], iterations: {}",
-                    z.re,
-                    z.im,
-                    image.iterations(x, y)
-                );
+            for y in (cell as i32..height).step_by(cell) {
+                let left = view.viewport_to_window(window_size, 0, y);
+                let right = view.viewport_to_window(window_size, width, y);
+                sdl.canvas.draw_line(left, right)?;
             }
-            _ => {}
         }
-
-        if settings.update_image {
-            mandelbrot::generate_image_thread(&transform, &mut image);
-            mandelbrot::equalize_image(&mut image);
-
-            settings.update_image = false;
-            settings.update_texture = true;
+        if let Some(rect) = overlays.exclusion {
+            let (left, top) = view.viewport_to_window(window_size, rect.left, rect.top);
+            let (right, bottom) = view.viewport_to_window(window_size, rect.right, rect.bottom);
+            sdl.canvas.set_draw_color(Color::RGB(255, 255, 255));
+            sdl.canvas.draw_rect(Rect::new(
+                left,
+                top,
+                (right - left) as u32,
+                (bottom - top) as u32,
+            ))?;
         }
-
-        if settings.update_texture {
-            // select color function
-            // TODO: use array instead of function
-            let color: Box<dyn Fn(&MandelPixel) -> Color> = match settings.use_histogram {
-                true => Box::new(|pix| {
-                    palette::color(
-                        settings.color_scheme,
-                        pix.iterations_equalized,
-                        image.max_iterations,
-                    )
-                }),
-                false => Box::new(|pix| {
-                    palette::color(settings.color_scheme, pix.iterations, image.max_iterations)
-                }),
-            };
-            draw_texture(&mut sdl.canvas, &mut mandel_texture, &image, color);
-            settings.update_texture = false;
+        if let Some(cursor) = overlays.loupe {
+            draw_loupe(&mut sdl.canvas, &mandel_texture, &view, window_size, cursor)?;
         }
-
-        let texture = match settings.show_colors {
-            true => &color_texture,
-            false => &mandel_texture,
-        };
-        sdl.canvas.copy(texture, None, None)?;
+        pacing.stage("draw");
         sdl.canvas.present();
+        pacing.stage("present");
+        if let (Some(missed), true) = (pacing.finish(), frame_diagnostics) {
+            println!(
+                "Missed frame: {:?}, slowest stage {}: {:?}",
+                missed.total, missed.stage, missed.stage_time
+            );
+        }
 
-        thread::sleep(std::time::Duration::from_millis(50));
+        let sleep = match new_frame {
+            true => FRAME_SLEEP,
+            false => idle_sleep(&app),
+        };
+        thread::sleep(std::time::Duration::from_millis(sleep));
     }
 
     Ok(())
 }
 
-fn draw_texture<F>(
-    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
-    texture: &mut sdl2::render::Texture<'_>,
-    image: &MandelImage,
-    color: F,
-) where
-    F: Fn(&MandelPixel) -> Color,
-{
-    let start = SystemTime::now();
+/// Wait for events after a frame without a new image, on battery power
+/// idle frames wait longer
+fn idle_sleep(app: &App) -> u64 {
+    // a running render is checked every frame to show it soon
+    match app.power_saving() && !app.rendering() {
+        true => POWER_SAVING_SLEEP,
+        false => FRAME_SLEEP,
+    }
+}
 
-    canvas
-        .with_texture_canvas(texture, |texture_canvas| {
-            image.iter().for_each(|pix| {
-                texture_canvas.set_draw_color(color(&pix));
-                texture_canvas
-                    .draw_point(Point::new(pix.x, pix.y))
-                    .expect("Failed to draw pixel");
-            });
+/// Draws a circle around the cursor magnified from the texture, the
+/// pixels are enlarged instead of recomputed
+fn draw_loupe(
+    canvas: &mut sdl2::render::Canvas<Window>,
+    texture: &sdl2::render::Texture<'_>,
+    view: &Viewport,
+    window_size: (u32, u32),
+    cursor: (i32, i32),
+) -> Result<(), String> {
+    let letterbox = view.letterbox(window_size);
+    let ratio = letterbox.width() as f64 / view.width as f64;
+    // window pixels per image pixel within the loupe
+    let scale = LOUPE_ZOOM * ratio;
+    let center_x = (cursor.0 - letterbox.x()) as f64 / ratio;
+    let center_y = (cursor.1 - letterbox.y()) as f64 / ratio;
+
+    // one texture row per window row, clipped to the circle
+    for dy in -LOUPE_RADIUS..=LOUPE_RADIUS {
+        let y = (center_y + dy as f64 / scale).floor() as i32;
+        if y < 0 || y >= view.height as i32 {
+            continue;
+        }
+        let half = ((LOUPE_RADIUS * LOUPE_RADIUS - dy * dy) as f64).sqrt();
+        let x0 = ((center_x - half / scale).floor() as i32).max(0);
+        let x1 = ((center_x + half / scale).ceil() as i32).min(view.width as i32);
+        if x1 <= x0 {
+            continue;
+        }
+
+        let window_x = cursor.0 + ((x0 as f64 - center_x) * scale).round() as i32;
+        let width = ((x1 - x0) as f64 * scale).round() as u32;
+        canvas.set_clip_rect(Rect::new(
+            cursor.0 - half as i32,
+            cursor.1 + dy,
+            (2.0 * half) as u32 + 1,
+            1,
+        ));
+        canvas.copy(
+            texture,
+            Rect::new(x0, y, (x1 - x0) as u32, 1),
+            Rect::new(window_x, cursor.1 + dy, width, 1),
+        )?;
+    }
+    canvas.set_clip_rect(None);
+
+    let ring: Vec<Point> = (0..360)
+        .map(|degree| {
+            let angle = (degree as f64).to_radians();
+            Point::new(
+                cursor.0 + (LOUPE_RADIUS as f64 * angle.cos()).round() as i32,
+                cursor.1 + (LOUPE_RADIUS as f64 * angle.sin()).round() as i32,
+            )
         })
-        .expect("Failed to draw texture");
-    println!("Texture drawn in: {:?}", start.elapsed().unwrap());
+        .collect();
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    canvas.draw_points(ring.as_slice())
 }
 
-fn draw_color_texture(
-    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+fn draw_texture(
     texture: &mut sdl2::render::Texture<'_>,
-) {
+    buffer: &RgbaBuffer,
+) -> Result<(), String> {
     let start = SystemTime::now();
 
-    let (width, height) = canvas.output_size().unwrap();
-    let bar_height = height / 4;
-    let draw_rect =
-        |can: &mut sdl2::render::Canvas<sdl2::video::Window>, x: u32, y: u32, s: ColorScheme| {
-            can.set_draw_color(palette::color(s, x, width));
-            can.draw_rect(Rect::new(x as i32, y as i32, 1, bar_height))
-                .expect("Failed to draw pixel");
-        };
-
-    canvas
-        .with_texture_canvas(texture, |mut texture_canvas| {
-            for x in 0..width {
-                draw_rect(&mut texture_canvas, x, 0, ColorScheme::Green);
-                draw_rect(&mut texture_canvas, x, bar_height, ColorScheme::Redish);
-                draw_rect(&mut texture_canvas, x, bar_height * 2, ColorScheme::Blue);
-                draw_rect(&mut texture_canvas, x, bar_height * 3, ColorScheme::Rainbow);
-            }
-        })
-        .expect("Failed to draw texture");
-
-    println!("Color texture drawn in: {:?}", start.elapsed().unwrap());
+    texture
+        .update(None, &buffer.data, buffer.pitch())
+        .map_err(|e| e.to_string())?;
+    timing::record("texture", start, format_args!("Texture drawn"));
+    Ok(())
 }
@@ -1,124 +1,1642 @@
+use crate::backend::LANES;
+use crate::deep::Reference;
+use crate::fractal::{Formula, Julia, Orbit, Outcome, SharedFormula};
+use crate::timing;
+use crate::trap::Trap;
 use crate::types::MandelImage;
+use crate::types::OrbitStats;
+use crate::types::PixelRect;
 use crate::types::Transform;
+use crate::workers;
 
 use num::complex::Complex;
+use num::Float;
 
+use std::cell::RefCell;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::thread;
+#[cfg(test)]
+use std::time::Duration;
 use std::time::SystemTime;
 
+/// Size of the sample grid used for estimating the iteration count
+const SAMPLE_GRID: (u32, u32) = (80, 60);
+
+/// Iteration cap used while sampling
+const SAMPLE_MAX_ITERATIONS: u32 = 50_000;
+
+/// Length of the first periodicity checking window
+const PERIODICITY_WINDOW: u32 = 8;
+
+/// Squared distance below which an orbit is considered periodic
+const PERIODICITY_TOLERANCE: f64 = 1e-24;
+
+/// Lower bound for estimated iteration counts
+const MIN_ITERATIONS: u32 = 50;
+
+/// Escape radius of the distance estimate, larger radii make it more accurate
+const DISTANCE_BAILOUT: f64 = 1000.0;
+
+/// Longest cycle searched for by the interior distance estimate
+const MAX_PERIOD: u32 = 1024;
+
+/// Squared distance of a returning orbit to count as a cycle in the
+/// single precision kernel, a few units in the last place
+const PERIODICITY_TOLERANCE_F32: f32 = 1e-12;
+
+/// Squared distance of a returning orbit to count as a cycle
+const PERIOD_TOLERANCE: f64 = 1e-12;
+
+/// Newton steps refining the point of the cycle
+const NEWTON_STEPS: u32 = 8;
+
+/// Largest multiplier of a cycle found by Newton's method to count as
+/// attracting, leaves room for the error of the refined cycle
+const MAX_MULTIPLIER: f64 = 0.99;
+
+/// Components of period 3 and 4 as period, nucleus in the upper half plane
+/// and the radius of a disc around the nucleus covering the component
+const BULBS: [(u32, f64, f64, f64); 6] = [
+    (3, -0.122561, 0.744862, 0.12),
+    (3, -1.754878, 0.0, 0.04),
+    (4, 0.282271, 0.530061, 0.07),
+    (4, -1.310703, 0.0, 0.08),
+    (4, -0.156520, 1.032247, 0.02),
+    (4, -1.940799, 0.0, 0.005),
+];
+
+/// Rectangles with fewer pixels are calculated without tracing their border
+const MIN_TRACE_AREA: usize = 64;
+
+/// Spacing of the coarse grid calculated first by solid guessing
+const GUESS_STEP: usize = 8;
+
+/// Width and height of the Julia thumbnails of the Julia map in pixels
+pub const JULIA_CELL: u32 = 64;
+
+/// Visible height of a Julia set, centered at 0
+pub const JULIA_VIEW_HEIGHT: f64 = 3.2;
+
+/// Limits of the render threads to save memory or power
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Limits {
+    /// rows rendered at once, the whole image if None
+    pub band_rows: Option<u32>,
+    /// width and height of the tiles taken by the render threads
+    pub tile: u32,
+    /// number of row blocks rendered in parallel by the workers
+    pub threads: i32,
+    /// size of the pixel blocks sharing one calculated value
+    pub block: u32,
+    /// how the rows of a thread are calculated
+    pub strategy: Strategy,
+    /// tiles are stored in tile order whatever thread finishes first
+    pub deterministic: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            band_rows: None,
+            tile: TILE_SIZE,
+            threads: workers::global().size() as i32,
+            block: 1,
+            strategy: Strategy::BruteForce,
+            deterministic: false,
+        }
+    }
+}
+
+/// Order in which the pixels of the image are calculated
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Strategy {
+    /// every pixel
+    BruteForce,
+    /// Mariani-Silver: rectangles with a border of a single value are
+    /// filled without calculating their inside
+    BorderTracing,
+    /// successive refinement of a coarse grid, cells with equal corners
+    /// are filled and the others are subdivided
+    SolidGuessing,
+}
+
+impl Strategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::BruteForce => "brute",
+            Strategy::BorderTracing => "border",
+            Strategy::SolidGuessing => "guess",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Strategy::BruteForce => Strategy::BorderTracing,
+            Strategy::BorderTracing => Strategy::SolidGuessing,
+            Strategy::SolidGuessing => Strategy::BruteForce,
+        }
+    }
+}
+
+impl std::str::FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "brute" => Ok(Strategy::BruteForce),
+            "border" => Ok(Strategy::BorderTracing),
+            "guess" => Ok(Strategy::SolidGuessing),
+            _ => Err(format!(
+                "Unknown renderer '{}', expected brute, border or guess",
+                name
+            )),
+        }
+    }
+}
+
+/// Pixel values compared by border tracing
+pub trait Fill {
+    /// True if both values color the same, so areas between them can be filled
+    fn same(&self, other: &Self) -> bool;
+}
+
+/// Escaped pixels differ in their fractional iterations and last z, so
+/// only areas inside the set or converged to an attractor are filled
+impl Fill for Outcome {
+    fn same(&self, other: &Self) -> bool {
+        self.iterations == other.iterations
+            && self.converged == other.converged
+            && self.smooth == other.smooth
+            && self.z == other.z
+            && self.trap == other.trap
+            && self.period == other.period
+            && self.min_norm == other.min_norm
+    }
+}
+
+impl Fill for f64 {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// Groups of values are the same if all their values are
+impl<T: Fill, const N: usize> Fill for [T; N] {
+    fn same(&self, other: &Self) -> bool {
+        self.iter().zip(other.iter()).all(|(a, b)| a.same(b))
+    }
+}
+
+/// Orbits of different pixels are never the same
+impl Fill for OrbitStats {
+    fn same(&self, _other: &Self) -> bool {
+        false
+    }
+}
+
+impl Limits {
+    /// Limits for small devices, the image is rendered in bands of a few
+    /// rows per thread so the scratch buffers stay small
+    pub fn low_memory(self) -> Self {
+        Limits {
+            band_rows: Some(self.threads as u32 * LOW_MEMORY_ROWS),
+            ..self
+        }
+    }
+
+    /// Limits on battery power, fewer threads calculate blocks of pixels
+    pub fn power_saving(self) -> Self {
+        Limits {
+            threads: self.threads.min(POWER_SAVING_THREADS),
+            block: POWER_SAVING_BLOCK,
+            ..self
+        }
+    }
+}
+
+/// Failure injected into the render thread of a tile by tests
+#[cfg(test)]
+#[derive(Copy, Clone, Debug)]
+pub enum Fault {
+    /// delays the tile by some milliseconds
+    Slow(u64),
+    /// the thread finishes without sending the tile
+    Drop,
+    /// the thread panics before sending the tile
+    Panic,
+}
+
+/// Token shared with the render threads to abort a render, the threads
+/// check it before each tile and finish the tiles they started
+#[derive(Clone, Debug, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears the token for the next render
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Buffers of the tiles, handed out to render threads and returned
+/// when they are done
+pub struct BufferPool<T> {
+    buffers: Vec<Vec<T>>,
+    pub limits: Limits,
+    /// aborts the tiles of this pool not yet started
+    pub cancel: Cancel,
+    /// receives every finished tile
+    pub progress: Option<mpsc::Sender<PixelRect>>,
+    /// keeps the samples of a pass with blocks for the next full pass
+    pub refine: bool,
+    coarse: Option<Coarse<T>>,
+    /// failures of the render threads by tile index
+    #[cfg(test)]
+    pub faults: Vec<Option<Fault>>,
+}
+
+impl<T> Default for BufferPool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> BufferPool<T> {
+    pub fn new() -> Self {
+        BufferPool {
+            buffers: Vec::new(),
+            limits: Limits::default(),
+            cancel: Cancel::default(),
+            progress: None,
+            refine: false,
+            coarse: None,
+            #[cfg(test)]
+            faults: Vec::new(),
+        }
+    }
+
+    /// Takes the buffer of a tile, emptied and with room for len entries
+    pub fn take(&mut self, index: usize, len: usize) -> Vec<T> {
+        if self.buffers.len() <= index {
+            self.buffers.resize_with(index + 1, Vec::new);
+        }
+        let mut buffer = std::mem::take(&mut self.buffers[index]);
+        buffer.clear();
+        buffer.reserve(len);
+        buffer
+    }
+
+    /// Returns the buffer of a tile for reuse in the next frame
+    pub fn put(&mut self, index: usize, buffer: Vec<T>) {
+        self.buffers[index] = buffer;
+    }
+
+    /// Reports a stored tile, a closed receiver is ignored
+    fn finished(&self, tile: &PixelRect) {
+        if let Some(progress) = &self.progress {
+            let _ = progress.send(*tile);
+        }
+    }
+
+    /// Keeps the samples of coarse passes or drops the kept ones
+    pub fn set_refine(&mut self, refine: bool) {
+        self.refine = refine;
+        if !refine {
+            self.coarse = None;
+        }
+    }
+}
+
+/// Samples of a pass with blocks, one per block on the grid of the
+/// block size, for the full pass of the same size to reuse
+struct Coarse<T> {
+    block: i32,
+    width: u32,
+    height: u32,
+    columns: i32,
+    samples: Vec<Option<T>>,
+}
+
+impl<T: Clone> Coarse<T> {
+    fn new(block: u32, width: u32, height: u32) -> Self {
+        let block = block as i32;
+        let columns = (width as i32 + block - 1) / block;
+        let rows = (height as i32 + block - 1) / block;
+        Coarse {
+            block,
+            width,
+            height,
+            columns,
+            samples: vec![None; (columns * rows) as usize],
+        }
+    }
+
+    /// Keeps the calculated values of a tile on the grid, the blocks of a
+    /// tile start at its left edge
+    fn keep(&mut self, tile: &PixelRect, values: &[T]) {
+        if tile.left % self.block != 0 {
+            return;
+        }
+        let (width, block) = (tile.width() as i32, self.block);
+        for y in (tile.top..tile.bottom).filter(|y| y % block == 0) {
+            for x in (tile.left..tile.right).step_by(block as usize) {
+                let i = ((y - tile.top) * width + x - tile.left) as usize;
+                let sample = (y / block * self.columns + x / block) as usize;
+                self.samples[sample] = Some(values[i].clone());
+            }
+        }
+    }
+
+    fn get(&self, x: i32, y: i32) -> Option<&T> {
+        match x % self.block == 0 && y % self.block == 0 {
+            true => {
+                self.samples[(y / self.block * self.columns + x / self.block) as usize].as_ref()
+            }
+            false => None,
+        }
+    }
+}
+
+/// Reusable scratch buffers of the render path, kept between frames
+/// so rendering an image does not allocate
+pub struct RenderContext {
+    pub outcomes: BufferPool<Outcome>,
+    pub values: BufferPool<f64>,
+    /// distances and interior distances of the distance pass
+    pub distances: BufferPool<[f64; 2]>,
+    pub stats: BufferPool<OrbitStats>,
+    /// outcomes of the lane groups of the SIMD backend
+    pub lanes: BufferPool<[Outcome; LANES]>,
+    samples: Vec<(u32, bool)>,
+    boundary: Vec<u32>,
+    iteration_counts: Vec<i32>,
+    /// iteration counts of each equalization thread
+    thread_counts: Vec<Vec<i32>>,
+    cumulative_distribution: Vec<i32>,
+    adjusted: Vec<u32>,
+    /// reference orbit of the last deep zoom render
+    pub reference: Option<Reference>,
+}
+
+impl Default for RenderContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderContext {
+    pub fn new() -> Self {
+        let mut context = RenderContext {
+            outcomes: BufferPool::new(),
+            values: BufferPool::new(),
+            distances: BufferPool::new(),
+            stats: BufferPool::new(),
+            lanes: BufferPool::new(),
+            samples: Vec::new(),
+            boundary: Vec::new(),
+            iteration_counts: Vec::new(),
+            thread_counts: Vec::new(),
+            cumulative_distribution: Vec::new(),
+            adjusted: Vec::new(),
+            reference: None,
+        };
+        // one token cancels the passes of all pools
+        context.values.cancel = context.outcomes.cancel.clone();
+        context.distances.cancel = context.outcomes.cancel.clone();
+        context.stats.cancel = context.outcomes.cancel.clone();
+        context.lanes.cancel = context.outcomes.cancel.clone();
+        context
+    }
+
+    /// Token aborting the render using this context
+    pub fn cancel(&self) -> Cancel {
+        self.outcomes.cancel.clone()
+    }
+
+    /// Keeps the samples of coarse iteration and value passes for the
+    /// full passes after them, false drops the kept samples
+    pub fn set_refine(&mut self, refine: bool) {
+        self.outcomes.set_refine(refine);
+        self.values.set_refine(refine);
+    }
+
+    /// Sends the tiles of the iteration and value passes to progress
+    pub fn set_progress(&mut self, progress: Option<mpsc::Sender<PixelRect>>) {
+        self.outcomes.progress = progress.clone();
+        self.lanes.progress = progress.clone();
+        self.distances.progress = progress.clone();
+        self.values.progress = progress;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.outcomes.limits = limits;
+        // tiles of lane groups cover as many pixels as the outcome tiles
+        self.lanes.limits = Limits {
+            tile: (limits.tile / LANES as u32).max(1),
+            ..limits
+        };
+        // values like Lyapunov exponents have no connected level sets to fill
+        self.values.limits = Limits {
+            strategy: Strategy::BruteForce,
+            ..limits
+        };
+        self.distances.limits = self.values.limits;
+        self.stats.limits = self.values.limits;
+    }
+}
+
+/// Scratch of the tile strategies, each render thread keeps its own
+/// between renders so calculating a tile does not allocate
+#[derive(Default)]
+struct Scratch {
+    /// pixels of the tile already calculated or filled
+    done: Vec<bool>,
+    /// rectangles left to trace as left, top, right and bottom
+    rectangles: Vec<(usize, usize, usize, usize)>,
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::new(Scratch::default());
+}
+
+/// Clears the vector and fills it with len copies of value, only
+/// allocates when the vector has grown beyond its previous size
+fn refill<T: Clone>(v: &mut Vec<T>, len: usize, value: T) {
+    v.clear();
+    v.resize(len, value);
+}
+
 /// Calculates the number of iterations for a given complex number
 /// to "escape" the Mandelbrot set
-pub fn mandel(c: &Complex<f64>, max_iter: u32) -> u32 {
-    if in_set(&c) {
-        max_iter
-    } else {
-        let f = |z| z * z + c;
-        let mut iter = 0;
-        let mut next = Complex::new(0.0, 0.0);
+pub fn mandel(c: &Complex<f64>, max_iter: u32) -> Outcome {
+    if in_set(c) {
+        return Outcome::new(max_iter, false);
+    }
+    match escape_loop(c.re, c.im, max_iter, 4.0, PERIODICITY_TOLERANCE) {
+        Some((iter, re, im)) => Outcome::escaped(iter, Complex::new(re, im)),
+        None => Outcome::new(max_iter, false),
+    }
+}
+
+/// Same as `mandel` in single precision with the given escape radius,
+/// only for views where f32 still tells the pixels apart
+pub fn mandel_f32(c: &Complex<f64>, max_iter: u32, bailout: f64) -> Outcome {
+    if in_set(c) {
+        return Outcome::new(max_iter, false);
+    }
+    let (re, im, bailout_sqr) = (c.re as f32, c.im as f32, bailout.powi(2) as f32);
+    match escape_loop(re, im, max_iter, bailout_sqr, PERIODICITY_TOLERANCE_F32) {
+        Some((iter, re, im)) => Outcome::escaped(iter, Complex::new(re as f64, im as f64)),
+        None => Outcome::new(max_iter, false),
+    }
+}
+
+/// Iterates `z * z + c` until |z|^2 reaches bailout_sqr, returns the
+/// escape count and the last z, None if the orbit stays bounded
+fn escape_loop<T: Float>(
+    c_re: T,
+    c_im: T,
+    max_iter: u32,
+    bailout_sqr: T,
+    tolerance: T,
+) -> Option<(u32, T, T)> {
+    // z = re + im*i, the squares are reused for the next step and the
+    // escape test, which gives the same values as `z * z + c`
+    let zero = T::zero();
+    let (mut re, mut im) = (zero, zero);
+    let (mut re2, mut im2) = (zero, zero);
+    let mut iter = 0;
+
+    // Brent's cycle detection, z is compared with a saved value that
+    // is replaced after windows of doubling length
+    let (mut saved_re, mut saved_im) = (zero, zero);
+    let mut window = PERIODICITY_WINDOW;
+    let mut steps = 0;
+
+    while re2 + im2 < bailout_sqr && iter < max_iter {
+        im = (re + re) * im + c_im;
+        re = re2 - im2 + c_re;
+        re2 = re * re;
+        im2 = im * im;
+        iter += 1;
+
+        let (delta_re, delta_im) = (re - saved_re, im - saved_im);
+        if delta_re * delta_re + delta_im * delta_im < tolerance {
+            return None;
+        }
+        steps += 1;
+        if steps == window {
+            (saved_re, saved_im) = (re, im);
+            steps = 0;
+            window *= 2;
+        }
+    }
+
+    match iter < max_iter {
+        true => Some((iter, re, im)),
+        false => None,
+    }
+}
+
+/// Calculates the number of iterations for a given complex number
+/// to "escape" or converge using any formula
+pub fn iterate<F: Formula + ?Sized>(formula: &F, c: &Complex<f64>, max_iter: u32) -> Outcome {
+    if formula.interior_check(c) {
+        return Outcome::new(max_iter, false);
+    }
+    let first = iterate_visit(formula, c, max_iter, |_| {});
+    second_orbit(formula, c, max_iter, first)
+}
+
+/// With two critical orbits the faster escape wins, the outcome of the
+/// first orbit is given
+fn second_orbit<F: Formula + ?Sized>(
+    formula: &F,
+    c: &Complex<f64>,
+    max_iter: u32,
+    first: Outcome,
+) -> Outcome {
+    match formula.second_initial(c) {
+        Some(start) => {
+            let second = iterate_from(formula, start, c, max_iter, |_| {});
+            match first.iterations <= second.iterations {
+                true => first,
+                false => Outcome {
+                    trap: first.trap,
+                    period: first.period,
+                    min_norm: first.min_norm,
+                    ..second
+                },
+            }
+        }
+        None => first,
+    }
+}
+
+/// Returns all z values of the orbit of c, starting with the initial value
+pub fn orbit<F: Formula + ?Sized>(
+    formula: &F,
+    c: &Complex<f64>,
+    max_iter: u32,
+) -> Vec<Complex<f64>> {
+    let mut points = Vec::new();
+    iterate_visit(formula, c, max_iter, |orbit| points.push(orbit.z));
+    points
+}
+
+/// Iterates c like `iterate` and calls visit for every orbit state
+fn iterate_visit<F, V>(formula: &F, c: &Complex<f64>, max_iter: u32, visit: V) -> Outcome
+where
+    F: Formula + ?Sized,
+    V: FnMut(&Orbit),
+{
+    iterate_from(formula, formula.initial(c), c, max_iter, visit)
+}
 
-        while next.norm() < 2.0 && iter < max_iter {
-            next = f(next);
-            iter += 1;
+/// Iterates the orbit of c starting at z
+fn iterate_from<F, V>(
+    formula: &F,
+    z: Complex<f64>,
+    c: &Complex<f64>,
+    max_iter: u32,
+    mut visit: V,
+) -> Outcome
+where
+    F: Formula + ?Sized,
+    V: FnMut(&Orbit),
+{
+    let bailout_sqr = formula.bailout().powi(2);
+    let mut orbit = Orbit::start(z);
+    let mut outcome = Outcome::default();
+    visit(&orbit);
+
+    while orbit.z.norm_sqr() < bailout_sqr && outcome.iterations < max_iter {
+        if formula.converged(&orbit) {
+            outcome.converged = true;
+            break;
         }
+        orbit.advance(formula.step(&orbit, c));
+        outcome.iterations += 1;
+        visit(&orbit);
+    }
 
-        iter
+    match outcome.converged || outcome.iterations == max_iter {
+        true => Outcome::new(outcome.iterations, outcome.converged),
+        false => Outcome::escaped(outcome.iterations, orbit.z),
     }
 }
 
+/// Iterates c like `iterate` and records the closest approach of the
+/// orbit to 0 and to the trap, the starting point is left out as it is
+/// the same for most pixels
+pub fn iterate_recorded<F: Formula + ?Sized>(
+    formula: &F,
+    c: &Complex<f64>,
+    max_iter: u32,
+    trap: Option<&Trap>,
+) -> Outcome {
+    let (mut closest, mut min_norm, mut period) = (f64::INFINITY, f64::INFINITY, 0);
+    let first = iterate_visit(formula, c, max_iter, |orbit| {
+        if orbit.iteration == 0 {
+            return;
+        }
+        let norm = orbit.z.norm();
+        if norm < min_norm {
+            min_norm = norm;
+            period = orbit.iteration;
+        }
+        if let Some(trap) = trap {
+            closest = closest.min(trap.distance(orbit.z));
+        }
+    });
+    let first = Outcome {
+        trap: closest,
+        period,
+        min_norm,
+        ..first
+    };
+    second_orbit(formula, c, max_iter, first)
+}
+
 /// Checks if z is definitely within the Mandelbort set
-/// according to wikipedia
-fn in_set(z: &Complex<f64>) -> bool {
+/// according to wikipedia, or in a component of period 3 or 4
+pub fn in_set(z: &Complex<f64>) -> bool {
     let p = ((z.re - 0.25).powi(2) + z.im.powi(2)).sqrt();
 
-    if z.re <= (p - 2.0 * p.powi(2) + 0.25) {
-        return true;
-    } else if ((z.re + 1.0).powi(2) + z.im.powi(2)) <= 0.0625 {
-        return true;
+    z.re <= (p - 2.0 * p.powi(2) + 0.25)
+        || ((z.re + 1.0).powi(2) + z.im.powi(2)) <= 0.0625
+        || in_bulb(z)
+}
+
+/// Checks if c is in one of the components of period 3 or 4, these have no
+/// closed form, so the cycle is refined with Newton's method starting at
+/// the critical point. An attracting cycle means c is inside the set.
+fn in_bulb(c: &Complex<f64>) -> bool {
+    // the components are symmetric about the real axis
+    let upper = Complex::new(c.re, c.im.abs());
+    BULBS.iter().any(|&(period, re, im, radius)| {
+        (upper - Complex::new(re, im)).norm_sqr() < radius * radius && attracting_cycle(c, period)
+    })
+}
+
+/// Checks if c has an attracting cycle of the period, or a divisor of it
+fn attracting_cycle(c: &Complex<f64>, period: u32) -> bool {
+    let mut z0 = Complex::new(0.0, 0.0);
+    for _ in 0..NEWTON_STEPS {
+        let (z, dz) = cycle(c, z0, period);
+        z0 -= (z - z0) / (dz - 1.0);
     }
+    let (z, dz) = cycle(c, z0, period);
+    (z - z0).norm_sqr() < PERIOD_TOLERANCE && dz.norm() < MAX_MULTIPLIER
+}
 
-    false
+/// Iterates z period times, returns f^p(z) and its derivative by z
+fn cycle(c: &Complex<f64>, z: Complex<f64>, period: u32) -> (Complex<f64>, Complex<f64>) {
+    let (mut z, mut dz) = (z, Complex::new(1.0, 0.0));
+    for _ in 0..period {
+        dz = z * dz * 2.0;
+        z = z * z + c;
+    }
+    (z, dz)
 }
 
 /// single threaded image generation
-pub fn _generate_image(transform: &Transform, image: &mut MandelImage) {
+pub fn _generate_image(transform: &Transform, formula: &dyn Formula, image: &mut MandelImage) {
     let start = SystemTime::now();
     let max_iter = image.max_iterations;
 
-    image
-        .iter_mut()
-        .for_each(|p| p.iterations = mandel(&transform.pos_to_complex(p.x, p.y), max_iter));
+    for i in 0..image.len() {
+        let (x, y) = image.position(i);
+        let outcome = formula.escape_time(&transform.pos_to_complex(x, y), max_iter);
+        image.iterations.set(i, outcome.iterations);
+        image.converged[i] = outcome.converged;
+    }
 
-    println!(
-        "Generated image with max iter {} in: {:?}",
-        image.max_iterations,
-        start.elapsed().unwrap()
+    timing::record(
+        "generate",
+        start,
+        format_args!("Generated image with max iter {}", image.max_iterations),
     );
 }
 
-/// multithreaded image generation
-pub fn generate_image_thread(transform: &Transform, image: &mut MandelImage) {
-    let start = SystemTime::now();
+/// Width and height of the tiles the render threads take from the queue
+pub const TILE_SIZE: u32 = 64;
+
+/// Rows per thread and band in low memory mode
+const LOW_MEMORY_ROWS: u32 = 4;
+
+/// Render threads on battery power
+const POWER_SAVING_THREADS: i32 = 4;
+
+/// Pixel block size on battery power, one value for 2x2 pixels
+const POWER_SAVING_BLOCK: u32 = 2;
 
+/// Calculates a value for every pixel on the render workers, the threads
+/// take tiles from a queue and every finished tile is handed to store.
+/// The limits of the pool set the number of threads and the tile size,
+/// stream the image band by band and share one value between the pixels
+/// of a block. A pool refining a coarse pass keeps its samples and the
+/// next full pass of the same size reuses them. A canceled render stops
+/// after the tiles in progress and leaves the others unchanged. Returns
+/// the number of threads used.
+pub fn generate_tiles<T, F, S>(
+    pool: &mut BufferPool<T>,
+    width: u32,
+    height: u32,
+    pixel: F,
+    mut store: S,
+) -> i32
+where
+    T: Clone + Fill + Send + Sync + 'static,
+    F: Fn(i32, i32) -> T + Send + Sync + 'static,
+    S: FnMut(&PixelRect, &[T]),
+{
+    let block = pool.limits.block.max(1);
+    let coarse = match pool.coarse.take() {
+        Some(coarse) if block == 1 && (coarse.width, coarse.height) == (width, height) => {
+            Some(coarse)
+        }
+        _ => None,
+    };
+    let pixel = Arc::new(
+        move |x, y| match coarse.as_ref().and_then(|c| c.get(x, y)) {
+            Some(sample) => sample.clone(),
+            None => pixel(x, y),
+        },
+    );
+    let mut kept = match pool.refine && block > 1 {
+        true => Some(Coarse::new(block, width, height)),
+        false => None,
+    };
+    let mut store = |tile: &PixelRect, values: &[T]| {
+        if let Some(kept) = &mut kept {
+            kept.keep(tile, values);
+        }
+        store(tile, values);
+    };
+    let band_rows = pool.limits.band_rows.unwrap_or(height).max(1) as i32;
+
+    let mut start = 0;
+    while start < height as i32 && !pool.cancel.is_canceled() {
+        let band = start..(start + band_rows).min(height as i32);
+        generate_band(pool, width, band.clone(), &pixel, &mut store);
+        start = band.end;
+    }
+    pool.coarse = kept;
+
+    pool.limits.threads
+}
+
+fn generate_band<T, F, S>(
+    pool: &mut BufferPool<T>,
+    width: u32,
+    band: Range<i32>,
+    pixel: &Arc<F>,
+    store: &mut S,
+) where
+    T: Clone + Fill + Send + 'static,
+    F: Fn(i32, i32) -> T + Send + Sync + 'static,
+    S: FnMut(&PixelRect, &[T]),
+{
     let (tx, rx) = mpsc::channel();
-    let trans = Arc::new(transform.clone());
-    let width = image.width;
-    let height = image.height;
-    let max_iter = image.max_iterations;
 
-    let thread_count: i32 = 12;
-    let rows_per_thread = height as i32 / thread_count;
-    for t in 0..thread_count {
-        let rows = if t < (thread_count - 1) {
-            (rows_per_thread * t)..(rows_per_thread * (t + 1))
-        } else {
-            (rows_per_thread * t)..height as i32
-        };
-        let trans_clone = trans.clone();
+    let limits = pool.limits;
+    let tiles: Vec<PixelRect> = PixelRect::new((0, band.start), (width as i32, band.end))
+        .tiles(limits.tile)
+        .collect();
+    let tile_count = tiles.len() as i32;
+
+    // threads take tiles from the queue until it is empty, so threads
+    // with fast tiles take over the rest instead of waiting for slow ones
+    let queue: Vec<_> = tiles
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, tile)| (i, *tile, pool.take(i, tile.len())))
+        .collect();
+    let queue = Arc::new(Mutex::new(queue));
+
+    let thread_count = limits.threads.max(1).min(tile_count);
+    let workers = workers::global();
+    #[cfg(test)]
+    let faults = Arc::new(pool.faults.clone());
+    for _ in 0..thread_count {
+        let queue = queue.clone();
+        let pixel = pixel.clone();
         let tx = tx.clone();
-        thread::spawn(move || {
-            let mut iterations = Vec::with_capacity((width * height) as usize);
-
-            for y in rows.clone() {
-                for x in 0..width {
-                    iterations.push(mandel(
-                        &trans_clone.pos_to_complex(x as i32, y as i32),
-                        max_iter,
-                    ));
-                }
+        let cancel = pool.cancel.clone();
+        #[cfg(test)]
+        let faults = faults.clone();
+        workers.execute(move || loop {
+            if cancel.is_canceled() {
+                return;
+            }
+            let next = queue.lock().unwrap().pop();
+            let (i, tile, mut buffer) = match next {
+                Some(tile) => tile,
+                None => return,
+            };
+            fill_tile(&*pixel, &tile, limits, &mut buffer);
+
+            #[cfg(test)]
+            match faults.get(i).copied().flatten() {
+                Some(Fault::Slow(millis)) => thread::sleep(Duration::from_millis(millis)),
+                Some(Fault::Drop) => return,
+                Some(Fault::Panic) => panic!("Injected panic in render thread"),
+                None => (),
             }
 
-            tx.send((rows, iterations)).unwrap();
+            // the receiver is gone if an earlier render panicked
+            let _ = tx.send((i, tile, buffer));
         });
     }
+    // receiving ends when all threads are done, also if some failed
+    drop(tx);
 
-    for _ in 0..thread_count {
-        let (rows, iterations) = rx.recv().unwrap();
-        println!("Got rows {:?}", rows);
-        image.set_iterations(rows, &iterations);
+    // each tile is stored as soon as its thread signals it is done, in
+    // deterministic mode tiles finished early wait for the tiles before
+    let mut done = vec![false; tiles.len()];
+    let mut held: Vec<Option<(PixelRect, Vec<T>)>> = Vec::new();
+    if limits.deterministic {
+        held.resize_with(tiles.len(), || None);
+    }
+    let mut next = 0;
+    for (i, tile, buffer) in rx {
+        done[i] = true;
+        if !limits.deterministic {
+            store_tile(pool, store, i, &tile, buffer);
+            continue;
+        }
+        held[i] = Some((tile, buffer));
+        while let Some((tile, buffer)) = held.get_mut(next).and_then(Option::take) {
+            store_tile(pool, store, next, &tile, buffer);
+            next += 1;
+        }
     }
 
-    println!(
-        "Generated image with {} threads and max iterations {} in: {:?}",
-        thread_count,
-        max_iter,
-        start.elapsed().unwrap()
+    if pool.cancel.is_canceled() {
+        return;
+    }
+
+    // tiles of threads that panicked or lost their results, tiles held
+    // back behind them are stored in order
+    for (i, tile) in tiles.iter().enumerate() {
+        if let Some((tile, buffer)) = held.get_mut(i).and_then(Option::take) {
+            store_tile(pool, store, i, &tile, buffer);
+            continue;
+        }
+        if done[i] {
+            continue;
+        }
+        println!(
+            "Tile {:?} lost by a render thread, rendering it again",
+            tile
+        );
+        let mut buffer = pool.take(i, tile.len());
+        fill_tile(&**pixel, tile, limits, &mut buffer);
+        store_tile(pool, store, i, tile, buffer);
+    }
+}
+
+/// Stores a finished tile, reports it and returns its buffer to the pool
+fn store_tile<T, S>(
+    pool: &mut BufferPool<T>,
+    store: &mut S,
+    i: usize,
+    tile: &PixelRect,
+    buffer: Vec<T>,
+) where
+    S: FnMut(&PixelRect, &[T]),
+{
+    store(tile, &buffer);
+    pool.finished(tile);
+    pool.put(i, buffer);
+}
+
+/// Calculates the pixels of the tile into the buffer, row by row
+fn fill_tile<T, F>(pixel: &F, tile: &PixelRect, limits: Limits, buffer: &mut Vec<T>)
+where
+    T: Clone + Fill,
+    F: Fn(i32, i32) -> T,
+{
+    let left = tile.left;
+    let rows = tile.top..tile.bottom;
+    fill_rows(
+        &|x, y| pixel(x + left, y),
+        tile.width(),
+        limits,
+        rows,
+        buffer,
     );
 }
 
+/// Calculates the pixels of the rows into the buffer
+fn fill_rows<T, F>(pixel: &F, width: u32, limits: Limits, rows: Range<i32>, buffer: &mut Vec<T>)
+where
+    T: Clone + Fill,
+    F: Fn(i32, i32) -> T,
+{
+    let block = limits.block.max(1) as i32;
+    match limits.strategy {
+        Strategy::BorderTracing if block == 1 => {
+            return SCRATCH
+                .with(|scratch| trace_rows(pixel, width, rows, buffer, &mut scratch.borrow_mut()))
+        }
+        Strategy::SolidGuessing if block == 1 => {
+            return SCRATCH
+                .with(|scratch| guess_rows(pixel, width, rows, buffer, &mut scratch.borrow_mut()))
+        }
+        _ => (),
+    }
+
+    for y in rows.clone() {
+        // rows and columns within a block repeat the first one
+        if y % block != 0 && y != rows.start {
+            buffer.extend_from_within(buffer.len() - width as usize..);
+            continue;
+        }
+        for x in 0..width as i32 {
+            let value = match x % block {
+                0 => pixel(x, y),
+                _ => buffer[buffer.len() - 1].clone(),
+            };
+            buffer.push(value);
+        }
+    }
+}
+
+/// Calculates the rows by border tracing: the border of a rectangle is
+/// calculated and if it has a single value, the inside is filled with it,
+/// otherwise the rectangle is split in four. Filled pixels copy the whole
+/// value of the border, which is only the same on all of it when the
+/// pixels have no fractional part, see `Fill`.
+fn trace_rows<T, F>(
+    pixel: &F,
+    width: u32,
+    rows: Range<i32>,
+    buffer: &mut Vec<T>,
+    scratch: &mut Scratch,
+) where
+    T: Clone + Fill,
+    F: Fn(i32, i32) -> T,
+{
+    let (w, h) = (width as usize, rows.len());
+    if w == 0 || h == 0 {
+        return;
+    }
+    buffer.resize(w * h, pixel(0, rows.start));
+    let Scratch { done, rectangles } = scratch;
+    refill(done, w * h, false);
+    done[0] = true;
+
+    let mut calculate = |buffer: &mut Vec<T>, x: usize, y: usize| {
+        let index = y * w + x;
+        if !done[index] {
+            buffer[index] = pixel(x as i32, rows.start + y as i32);
+            done[index] = true;
+        }
+        index
+    };
+
+    // rectangles as left, top, right and bottom, right and bottom excluded
+    rectangles.clear();
+    rectangles.push((0, 0, w, h));
+    while let Some((left, top, right, bottom)) = rectangles.pop() {
+        let first = calculate(buffer, left, top);
+        let mut uniform = true;
+        for x in left..right {
+            for y in [top, bottom - 1] {
+                let index = calculate(buffer, x, y);
+                uniform &= buffer[index].same(&buffer[first]);
+            }
+        }
+        for y in top..bottom {
+            for x in [left, right - 1] {
+                let index = calculate(buffer, x, y);
+                uniform &= buffer[index].same(&buffer[first]);
+            }
+        }
+
+        let (inner_width, inner_height) = (right - left, bottom - top);
+        if inner_width <= 2 || inner_height <= 2 {
+            continue;
+        }
+        if uniform {
+            let value = buffer[first].clone();
+            for y in top + 1..bottom - 1 {
+                buffer[y * w + left + 1..y * w + right - 1].fill(value.clone());
+            }
+        } else if inner_width * inner_height <= MIN_TRACE_AREA {
+            for y in top + 1..bottom - 1 {
+                for x in left + 1..right - 1 {
+                    calculate(buffer, x, y);
+                }
+            }
+        } else {
+            // the halves share the middle row and column
+            let (middle_x, middle_y) = ((left + right) / 2, (top + bottom) / 2);
+            rectangles.push((left, top, middle_x + 1, middle_y + 1));
+            rectangles.push((middle_x, top, right, middle_y + 1));
+            rectangles.push((left, middle_y, middle_x + 1, bottom));
+            rectangles.push((middle_x, middle_y, right, bottom));
+        }
+    }
+}
+
+/// Parts of the image outside the known rectangle, the rows above and
+/// below it and the columns left and right of it
+fn exposed_strips(width: u32, height: u32, known: &PixelRect) -> Vec<PixelRect> {
+    let (width, height) = (width as i32, height as i32);
+    vec![
+        PixelRect::new((0, 0), (width, known.top)),
+        PixelRect::new((0, known.bottom), (width, height)),
+        PixelRect::new((0, known.top), (known.left, known.bottom)),
+        PixelRect::new((known.right, known.top), (width, known.bottom)),
+    ]
+    .into_iter()
+    .filter(|strip| !strip.is_empty())
+    .collect()
+}
+
+/// multithreaded image generation, each pixel blends the given number of
+/// jittered samples if there is more than one. Pixels in the known
+/// rectangle are kept, e.g. when they were copied from the previous view.
+pub fn generate_image_thread(
+    context: &mut RenderContext,
+    transform: &Transform,
+    formula: &SharedFormula,
+    samples: u32,
+    known: Option<&PixelRect>,
+    image: &mut MandelImage,
+) {
+    let start = SystemTime::now();
+
+    let trans = transform.clone();
+    let max_iter = image.max_iterations;
+    let formula = formula.clone();
+    let axis = match (known, formula.symmetric()) {
+        (None, true) => transform.mirror_axis(),
+        _ => None,
+    };
+    let pixel = Arc::new(move |x, y| match samples > 1 {
+        true => jittered_escape_time(&*formula, &trans, x, y, max_iter, samples),
+        false => formula.escape_time(&trans.pos_to_complex(x, y), max_iter),
+    });
+
+    if let Some(known) = known {
+        let mut thread_count = 0;
+        for strip in exposed_strips(image.width, image.height, known) {
+            let pixel = pixel.clone();
+            thread_count = generate_tiles(
+                &mut context.outcomes,
+                strip.width(),
+                strip.height(),
+                move |x, y| pixel(x + strip.left, y + strip.top),
+                |tile, outcomes| {
+                    image.set_iterations(&tile.offset(strip.left, strip.top), outcomes)
+                },
+            );
+        }
+        timing::record(
+            "generate",
+            start,
+            format_args!(
+                "Generated image reusing {} pixels with {} threads and max iterations {}",
+                known.len(),
+                thread_count,
+                max_iter
+            ),
+        );
+        return;
+    }
+
+    let computed = match axis {
+        Some(axis) => symmetric_rows(image.height, axis),
+        None => 0..image.height as i32,
+    };
+    let first = computed.start;
+    let thread_count = generate_tiles(
+        &mut context.outcomes,
+        image.width,
+        computed.len() as u32,
+        move |x, y| pixel(x, y + first),
+        |tile, outcomes| image.set_iterations(&tile.offset(0, first), outcomes),
+    );
+    if let Some(axis) = axis {
+        image.mirror_rows(computed.clone(), axis);
+        println!(
+            "Mirrored {} rows about the real axis",
+            image.height as usize - computed.len()
+        );
+    }
+
+    timing::record(
+        "generate",
+        start,
+        format_args!(
+            "Generated image with {} threads and max iterations {}",
+            thread_count, max_iter
+        ),
+    );
+}
+
+/// Offset of a jittered sample from its pixel center, both coordinates
+/// in [-0.5, 0.5). The offsets are a hash of the sample, so they are the
+/// same in every render and the image does not flicker.
+fn jitter(x: i32, y: i32, sample: u32) -> (f64, f64) {
+    // the splitmix64 finalizer of the pixel and sample index
+    let mut h = ((x as u32 as u64) << 32) | y as u32 as u64;
+    h = h.wrapping_add((sample as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^= h >> 31;
+    let unit = |bits: u64| (bits & 0xffff_ffff) as f64 / (1u64 << 32) as f64 - 0.5;
+    (unit(h), unit(h >> 32))
+}
+
+/// Escape time of a pixel blended from samples at jittered positions
+/// within it, which breaks up the banding of tight spirals. The counts of
+/// the escaped samples are averaged unless most samples stay bounded.
+pub fn jittered_escape_time<F: Formula + ?Sized>(
+    formula: &F,
+    transform: &Transform,
+    x: i32,
+    y: i32,
+    max_iter: u32,
+    samples: u32,
+) -> Outcome {
+    let mut escaped: Option<Outcome> = None;
+    let mut bounded: Option<Outcome> = None;
+    let (mut count, mut iterations, mut smooth) = (0, 0u64, 0.0);
+
+    for sample in 0..samples {
+        let (dx, dy) = jitter(x, y, sample);
+        let c = transform.subpixel_to_complex(x as f64 + dx, y as f64 + dy);
+        let outcome = formula.escape_time(&c, max_iter);
+        if outcome.converged || outcome.iterations >= max_iter {
+            bounded.get_or_insert(outcome);
+            continue;
+        }
+        count += 1;
+        iterations += outcome.iterations as u64;
+        smooth += outcome.smooth;
+        escaped.get_or_insert(outcome);
+    }
+
+    match (escaped, bounded) {
+        (_, Some(bounded)) if 2 * count < samples => bounded,
+        (Some(first), _) => Outcome {
+            iterations: (iterations as f64 / count as f64).round() as u32,
+            smooth: smooth / count as f64,
+            ..first
+        },
+        _ => Outcome::new(max_iter, false),
+    }
+}
+
+/// Rows to calculate when rows y and axis - y are mirror images, the
+/// side of the real axis with more rows, the other side is mirrored
+pub fn symmetric_rows(height: u32, axis: i32) -> Range<i32> {
+    let height = height as i32;
+    match axis >= height {
+        true => 0..(axis / 2 + 1).min(height),
+        false => (axis + 1).div_euclid(2).max(0)..height,
+    }
+}
+
+/// Calculates the rows by solid guessing: a coarse grid is calculated
+/// first, then each cell with four equal corners has its border checked
+/// and is filled if the whole border is the same, the other cells are
+/// refined at half the spacing down to single pixels. Checking the border
+/// keeps details inside a cell with equal corners, like border tracing.
+fn guess_rows<T, F>(
+    pixel: &F,
+    width: u32,
+    rows: Range<i32>,
+    buffer: &mut Vec<T>,
+    scratch: &mut Scratch,
+) where
+    T: Clone + Fill,
+    F: Fn(i32, i32) -> T,
+{
+    let (w, h) = (width as usize, rows.len());
+    if w == 0 || h == 0 {
+        return;
+    }
+    buffer.resize(w * h, pixel(0, rows.start));
+    let done = &mut scratch.done;
+    refill(done, w * h, false);
+    done[0] = true;
+
+    let calculate = |buffer: &mut Vec<T>, done: &mut [bool], x: usize, y: usize| {
+        let index = y * w + x;
+        if !done[index] {
+            buffer[index] = pixel(x as i32, rows.start + y as i32);
+            done[index] = true;
+        }
+    };
+
+    // grid lines at multiples of the step and at the last row and column
+    let grid = |len: usize, step: usize| (0..len - 1).step_by(step).chain([len - 1]);
+    for y in grid(h, GUESS_STEP) {
+        for x in grid(w, GUESS_STEP) {
+            calculate(buffer, done, x, y);
+        }
+    }
+
+    let mut step = GUESS_STEP;
+    while step > 1 {
+        let half = step / 2;
+        for top in (0..h - 1).step_by(step) {
+            for left in (0..w - 1).step_by(step) {
+                let (right, bottom) = ((left + step).min(w - 1), (top + step).min(h - 1));
+                let corner = buffer[top * w + left].clone();
+                let uniform = [(right, top), (left, bottom), (right, bottom)]
+                    .iter()
+                    .all(|&(x, y)| buffer[y * w + x].same(&corner));
+                let mut border = (left..=right)
+                    .flat_map(|x| [(x, top), (x, bottom)])
+                    .chain((top..=bottom).flat_map(|y| [(left, y), (right, y)]));
+                let uniform = uniform
+                    && border.all(|(x, y)| {
+                        calculate(buffer, done, x, y);
+                        buffer[y * w + x].same(&corner)
+                    });
+
+                if uniform {
+                    let value = corner;
+                    for y in top..=bottom {
+                        for x in left..=right {
+                            if !std::mem::replace(&mut done[y * w + x], true) {
+                                buffer[y * w + x] = value.clone();
+                            }
+                        }
+                    }
+                } else {
+                    let (middle_x, middle_y) = ((left + half).min(right), (top + half).min(bottom));
+                    for (x, y) in [
+                        (middle_x, top),
+                        (left, middle_y),
+                        (middle_x, middle_y),
+                        (right, middle_y),
+                        (middle_x, bottom),
+                    ] {
+                        calculate(buffer, done, x, y);
+                    }
+                }
+            }
+        }
+        step = half;
+    }
+}
+
+/// Parameter of the Julia thumbnail under a pixel of the Julia map,
+/// the point at the center of its cell
+pub fn julia_map_parameter(transform: &Transform, x: i32, y: i32) -> Complex<f64> {
+    let cell = JULIA_CELL as i32;
+    let center = |v: i32| v.div_euclid(cell) * cell + cell / 2;
+    transform.pos_to_complex(center(x), center(y))
+}
+
+/// Renders a grid of Julia thumbnails, each cell shows the Julia set of
+/// the formula for the parameter at the center of the cell
+pub fn julia_map_thread(
+    context: &mut RenderContext,
+    transform: &Transform,
+    formula: &SharedFormula,
+    image: &mut MandelImage,
+) {
+    let start = SystemTime::now();
+
+    let trans = transform.clone();
+    let formula = formula.clone();
+    let max_iter = image.max_iterations;
+    let cell = JULIA_CELL as i32;
+    let scale = JULIA_VIEW_HEIGHT / JULIA_CELL as f64;
+
+    generate_tiles(
+        &mut context.outcomes,
+        image.width,
+        image.height,
+        move |x, y| {
+            let c = julia_map_parameter(&trans, x, y);
+            let local =
+                |v: i32| (v.rem_euclid(cell) as f64 + 0.5) * scale - JULIA_VIEW_HEIGHT / 2.0;
+            let z = Complex::new(local(x), -local(y));
+            Julia::new(formula.clone(), c).escape_time(&z, max_iter)
+        },
+        |tile, outcomes| image.set_iterations(tile, outcomes),
+    );
+
+    timing::record("generate", start, format_args!("Generated Julia map"));
+}
+
+/// Estimates the distance of c to the Mandelbrot set from the derivative
+/// of the orbit, 0 for points that do not escape
+pub fn distance(c: &Complex<f64>, max_iter: u32) -> f64 {
+    let mut z = Complex::new(0.0, 0.0);
+    let mut dz = Complex::new(0.0, 0.0);
+
+    for _ in 0..max_iter {
+        dz = z * dz * 2.0 + 1.0;
+        z = z * z + c;
+        if z.norm_sqr() > DISTANCE_BAILOUT * DISTANCE_BAILOUT {
+            let r = z.norm();
+            return r * r.ln() / dz.norm();
+        }
+    }
+
+    0.0
+}
+
+/// Estimates the distance of c inside the Mandelbrot set to the boundary
+/// from the attracting cycle its orbit settles on, 0 if no cycle is found
+pub fn interior_distance(c: &Complex<f64>, max_iter: u32) -> f64 {
+    let f = |z: Complex<f64>| z * z + c;
+
+    // let the orbit settle onto its cycle
+    let mut z = Complex::new(0.0, 0.0);
+    for _ in 0..max_iter {
+        z = f(z);
+        if z.norm_sqr() > 4.0 {
+            return 0.0;
+        }
+    }
+
+    let settled = z;
+    let mut period = 0;
+    for p in 1..=MAX_PERIOD {
+        z = f(z);
+        if (z - settled).norm_sqr() < PERIOD_TOLERANCE {
+            period = p;
+            break;
+        }
+    }
+    if period == 0 {
+        return 0.0;
+    }
+
+    // refine the point of the cycle with Newton's method on f^p(z) - z
+    let mut z0 = settled;
+    for _ in 0..NEWTON_STEPS {
+        let (z, dz) = cycle(c, z0, period);
+        z0 -= (z - z0) / (dz - 1.0);
+    }
+
+    // derivatives of f^p at the cycle
+    let mut z = z0;
+    let mut dz = Complex::new(1.0, 0.0);
+    let mut dc = Complex::new(0.0, 0.0);
+    let mut dzdz = Complex::new(0.0, 0.0);
+    let mut dzdc = Complex::new(0.0, 0.0);
+    for _ in 0..period {
+        dzdc = (dz * dc + z * dzdc) * 2.0;
+        dzdz = (dz * dz + z * dzdz) * 2.0;
+        dc = z * dc * 2.0 + 1.0;
+        dz = z * dz * 2.0;
+        z = f(z);
+    }
+    if dz.norm_sqr() >= 1.0 {
+        return 0.0;
+    }
+
+    (1.0 - dz.norm_sqr()) / (dzdc + dzdz * dc / (-dz + 1.0)).norm()
+}
+
+/// multithreaded distance estimation, stores the distance to the
+/// Mandelbrot set in pixels. Points inside the set get the interior
+/// distance if requested.
+pub fn distance_image_thread(
+    context: &mut RenderContext,
+    transform: &Transform,
+    interior: bool,
+    image: &mut MandelImage,
+) {
+    let start = SystemTime::now();
+
+    let trans = transform.clone();
+    let max_iter = image.max_iterations;
+
+    let thread_count = generate_tiles(
+        &mut context.distances,
+        image.width,
+        image.height,
+        move |x, y| {
+            let c = trans.pos_to_complex(x, y);
+            let d = distance(&c, max_iter);
+            match d == 0.0 && interior {
+                true => [0.0, interior_distance(&c, max_iter) * trans.scale()],
+                false => [d * trans.scale(), 0.0],
+            }
+        },
+        |tile, distances| image.set_distances(tile, distances),
+    );
+
+    timing::record(
+        "distance",
+        start,
+        format_args!("Estimated distances with {} threads", thread_count),
+    );
+}
+
+/// Iterates c and records the statistics of its orbit, the derivative
+/// follows z^2 + c and is only kept if requested for that formula
+pub fn orbit_stats<F: Formula + ?Sized>(
+    formula: &F,
+    c: &Complex<f64>,
+    max_iter: u32,
+    trap: Option<&Trap>,
+    derivative: bool,
+) -> OrbitStats {
+    let mut stats = OrbitStats::default();
+    let mut dz = Complex::new(0.0, 0.0);
+    iterate_visit(formula, c, max_iter, |orbit| {
+        if orbit.iteration == 0 {
+            return;
+        }
+        dz = orbit.z_prev * dz * 2.0 + 1.0;
+        let norm = orbit.z.norm();
+        if norm < stats.min_norm {
+            stats.min_norm = norm;
+            stats.min_iteration = orbit.iteration;
+        }
+        if let Some(trap) = trap {
+            stats.trap = stats.trap.min(trap.distance(orbit.z));
+        }
+        stats.z = orbit.z;
+    });
+    if derivative {
+        stats.derivative = Some(dz);
+    }
+    stats
+}
+
+/// multithreaded orbit statistics, stored in the extended buffer of the
+/// image, this includes the trap distances if a trap is given
+pub fn stats_image_thread(
+    context: &mut RenderContext,
+    transform: &Transform,
+    formula: &SharedFormula,
+    trap: Option<Trap>,
+    derivative: bool,
+    image: &mut MandelImage,
+) {
+    let start = SystemTime::now();
+
+    let trans = transform.clone();
+    let formula = formula.clone();
+    let max_iter = image.max_iterations;
+
+    let thread_count = generate_tiles(
+        &mut context.stats,
+        image.width,
+        image.height,
+        move |x, y| {
+            let c = trans.pos_to_complex(x, y);
+            orbit_stats(&*formula, &c, max_iter, trap.as_ref(), derivative)
+        },
+        |tile, stats| image.set_stats(tile, stats),
+    );
+
+    timing::record(
+        "stats",
+        start,
+        format_args!("Recorded orbit statistics with {} threads", thread_count),
+    );
+}
+
+/// Estimates the maximum iterations needed for the current view by
+/// sampling a sparse grid. Samples that escape next to samples that do
+/// not escape are boundary adjacent, the returned count lets less than
+/// 1% of those hit the cap.
+pub fn estimate_iterations(
+    context: &mut RenderContext,
+    transform: &Transform,
+    formula: &dyn Formula,
+    image: &MandelImage,
+) -> u32 {
+    let start = SystemTime::now();
+    let (grid_width, grid_height) = SAMPLE_GRID;
+
+    let samples = &mut context.samples;
+    samples.clear();
+    for gy in 0..grid_height {
+        for gx in 0..grid_width {
+            let x = (gx * image.width / grid_width) as i32;
+            let y = (gy * image.height / grid_height) as i32;
+            let outcome =
+                formula.escape_time(&transform.pos_to_complex(x, y), SAMPLE_MAX_ITERATIONS);
+            let escaped = !outcome.converged && outcome.iterations < SAMPLE_MAX_ITERATIONS;
+            samples.push((outcome.iterations, escaped));
+        }
+    }
+
+    let escaped = |gx: i32, gy: i32| {
+        if gx < 0 || gy < 0 || gx >= grid_width as i32 || gy >= grid_height as i32 {
+            true
+        } else {
+            samples[(gx + gy * grid_width as i32) as usize].1
+        }
+    };
+
+    let boundary = &mut context.boundary;
+    boundary.clear();
+    for gy in 0..grid_height as i32 {
+        for gx in 0..grid_width as i32 {
+            let (iterations, is_escaped) = samples[(gx + gy * grid_width as i32) as usize];
+            let next_to_set = !escaped(gx - 1, gy)
+                || !escaped(gx + 1, gy)
+                || !escaped(gx, gy - 1)
+                || !escaped(gx, gy + 1);
+            if is_escaped && next_to_set {
+                boundary.push(iterations);
+            }
+        }
+    }
+
+    let estimate = if boundary.is_empty() {
+        image.max_iterations
+    } else {
+        boundary.sort_unstable();
+        let index = (boundary.len() * 99 / 100).min(boundary.len() - 1);
+        (boundary[index] + 1).max(MIN_ITERATIONS)
+    };
+
+    timing::record(
+        "estimate",
+        start,
+        format_args!(
+            "Estimated max iterations {} from {} boundary samples",
+            estimate,
+            boundary.len()
+        ),
+    );
+
+    estimate
+}
+
 /// histogram equalization
-pub fn equalize_image(image: &mut MandelImage) {
+pub fn equalize_image(
+    context: &mut RenderContext,
+    image: &mut MandelImage,
+    exclusion: Option<&PixelRect>,
+) {
     let start = SystemTime::now();
 
-    // count each iteration count
+    // count each iteration count, pixels in the exclusion zone are
+    // equalized with the statistics of the rest of the image
     let size: usize = (image.max_iterations + 1) as usize;
-    let mut iteration_counts = vec![0; size];
-    image
-        .iter()
-        .for_each(|p| iteration_counts[p.iterations as usize] += 1);
+    // each thread counts a block of rows, the counts are added up after
+    let threads = workers::global().size();
+    let rows = (image.height as usize).div_ceil(threads).max(1);
+    let chunk = (rows * image.width as usize).max(1);
+    let used = image.len().div_ceil(chunk);
+    context.thread_counts.resize_with(threads, Vec::new);
+    let image_ref = &*image;
+    thread::scope(|scope| {
+        let len = image_ref.len();
+        for (first, counts) in (0..len).step_by(chunk).zip(&mut context.thread_counts) {
+            let pixels = first..(first + chunk).min(len);
+            refill(counts, size, 0);
+            scope.spawn(move || match exclusion {
+                Some(rect) => {
+                    for i in pixels {
+                        let (x, y) = image_ref.position(i);
+                        if !rect.contains(x, y) {
+                            counts[image_ref.iterations.get(i) as usize] += 1;
+                        }
+                    }
+                }
+                None => {
+                    for i in pixels {
+                        counts[image_ref.iterations.get(i) as usize] += 1;
+                    }
+                }
+            });
+        }
+    });
+    let iteration_counts = &mut context.iteration_counts;
+    refill(iteration_counts, size, 0);
+    for counts in &context.thread_counts[..used] {
+        for (total, count) in iteration_counts.iter_mut().zip(counts) {
+            *total += count;
+        }
+    }
 
-    let mut cumulative_distribution = vec![0; size];
+    let cumulative_distribution = &mut context.cumulative_distribution;
+    refill(cumulative_distribution, size, 0);
 
     // TODO: use iter
     // skip 'image.max_iterations' value (in set) in equalization
@@ -133,7 +1651,8 @@ pub fn equalize_image(image: &mut MandelImage) {
         .iter()
         .take(image.max_iterations as usize)
         .sum();
-    let mut adjusted = vec![0; size];
+    let adjusted = &mut context.adjusted;
+    refill(adjusted, size, 0);
     let nominator = sum - cumulative_distribution[0];
     let hist = |n: u32| {
         if n == image.max_iterations {
@@ -146,8 +1665,8 @@ pub fn equalize_image(image: &mut MandelImage) {
         }
     };
 
-    for i in 0..size {
-        adjusted[i] = hist(i as u32);
+    for (i, a) in adjusted.iter_mut().enumerate() {
+        *a = hist(i as u32);
     }
 
     // let mut i = 0;
@@ -159,10 +1678,648 @@ pub fn equalize_image(image: &mut MandelImage) {
     //     i += 1;
     // }
 
-    // set adjusted iterations
-    image
-        .iter_mut()
-        .for_each(|p| p.iterations_equalized = adjusted[p.iterations as usize]);
+    apply_equalization(context, image);
+
+    timing::record("equalize", start, format_args!("Equalized image"));
+}
+
+/// Sets the equalized iterations with the table of the last equalized
+/// image, which needs the same max iterations
+pub fn apply_equalization(context: &RenderContext, image: &mut MandelImage) {
+    let threads = workers::global().size();
+    let rows = (image.height as usize).div_ceil(threads).max(1);
+    let chunk = (rows * image.width as usize).max(1);
+    let adjusted = &context.adjusted;
+    let iterations = &image.iterations;
+    let equalized = image.iterations_equalized.chunks_mut(chunk);
+    thread::scope(|scope| {
+        for (first, equalized) in (0..).step_by(chunk).zip(equalized) {
+            scope.spawn(move || {
+                for (i, equalized) in (first..).zip(equalized) {
+                    *equalized = adjusted[iterations.get(i) as usize];
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::{Bailout, Cubic, Fractal, Morph, Recorded};
+    use crate::trap::TrapShape;
+    use crate::types::Projection;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    impl Fill for (i32, i32) {
+        fn same(&self, other: &Self) -> bool {
+            self == other
+        }
+    }
+
+    #[test]
+    fn test_iterate_matches_mandel() {
+        let points = [
+            Complex::new(0.3, 0.5),
+            Complex::new(-0.75, 0.1),
+            Complex::new(-1.8, 0.0),
+            Complex::new(1.0, 1.0),
+        ];
+
+        for c in points.iter() {
+            let fast = mandel(c, 500);
+            let outcome = iterate(&Fractal::Mandelbrot, c, 500);
+            assert_eq!(fast.iterations, outcome.iterations);
+            assert_eq!(fast.smooth, outcome.smooth);
+            assert_eq!(fast.z, outcome.z);
+        }
+    }
+
+    #[test]
+    fn test_wrapped_cubic() {
+        let cubic: SharedFormula = Arc::new(Cubic::new(Complex::new(0.5, 0.2)));
+        let bailout = Bailout::new(cubic.clone(), cubic.bailout());
+        let morph = Morph::new(cubic.clone(), 0.0);
+        let mut second_escapes = false;
+        for i in 0..400 {
+            let c = Complex::new((i % 20) as f64 * 0.1 - 1.0, (i / 20) as f64 * 0.1 - 1.0);
+            let outcome = cubic.escape_time(&c, 200);
+            second_escapes |=
+                outcome.iterations < iterate_visit(&*cubic, &c, 200, |_| {}).iterations;
+            // wrappers keep iterating both critical orbits
+            assert_eq!(bailout.escape_time(&c, 200).iterations, outcome.iterations);
+            assert_eq!(morph.escape_time(&c, 200).iterations, outcome.iterations);
+        }
+        assert!(second_escapes);
+    }
+
+    #[test]
+    fn test_interior_distance() {
+        assert_eq!(interior_distance(&Complex::new(1.0, 0.0), 1000), 0.0);
+
+        // the estimate is within a factor of 4 of the true distance
+        let near_cusp = interior_distance(&Complex::new(0.24, 0.0), 1000);
+        assert!(near_cusp > 0.01 / 4.0 && near_cusp < 0.01 * 4.0);
+        let bulb = interior_distance(&Complex::new(-1.1, 0.0), 1000);
+        assert!(bulb > 0.15 / 4.0 && bulb < 0.15 * 4.0);
+    }
+
+    #[test]
+    fn test_bulbs() {
+        for &(period, re, im, radius) in BULBS.iter() {
+            let nucleus = Complex::new(re, -im);
+            assert!(in_set(&nucleus));
+            let mut inside = 0;
+            for i in -20..=20 {
+                for j in -20..=20 {
+                    let c = nucleus + Complex::new(i as f64, j as f64) * radius / 20.0;
+                    if in_bulb(&c) {
+                        inside += 1;
+                        let n = iterate_visit(&Fractal::Mandelbrot, &c, 20_000, |_| {});
+                        assert_eq!(n.iterations, 20_000, "{} in period {}", c, period);
+                    }
+                }
+            }
+            assert!(inside > 10, "{} points in period {}", inside, period);
+        }
+    }
+
+    #[test]
+    fn test_single_precision() {
+        let transform = Transform::new((120, 90));
+        let mut differ = 0;
+        for y in 0..90 {
+            for x in 0..120 {
+                let c = transform.pos_to_complex(x, y);
+                let (double, single) = (mandel(&c, 500), mandel_f32(&c, 500, 2.0));
+                if double.iterations != single.iterations {
+                    differ += 1;
+                }
+            }
+        }
+        assert!(differ < 120 * 90 / 100, "{} escape counts differ", differ);
+    }
+
+    #[test]
+    fn test_jitter() {
+        for sample in 0..8 {
+            let (dx, dy) = jitter(-3, 7, sample);
+            assert!((-0.5..0.5).contains(&dx) && (-0.5..0.5).contains(&dy));
+            assert_eq!(jitter(-3, 7, sample), (dx, dy));
+            assert_ne!(jitter(-3, 7, sample + 1), (dx, dy));
+        }
+
+        let transform = Transform::new((40, 30));
+        assert_eq!(
+            transform.subpixel_to_complex(12.0, 5.0),
+            transform.pos_to_complex(12, 5)
+        );
+        let formula = Fractal::Mandelbrot;
+        // inside the main cardioid all samples stay bounded
+        let inside = jittered_escape_time(&formula, &transform, 22, 15, 100, 3);
+        assert_eq!(inside.iterations, 100);
+        // far outside the blend lies between the samples
+        let outside = jittered_escape_time(&formula, &transform, 0, 0, 100, 3);
+        let samples: Vec<f64> = (0..3)
+            .map(|sample| {
+                let (dx, dy) = jitter(0, 0, sample);
+                let c = transform.subpixel_to_complex(dx, dy);
+                mandel(&c, 100).smooth
+            })
+            .collect();
+        let (min, max) = samples
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(a, b), &s| (a.min(s), b.max(s)));
+        assert!(outside.smooth >= min && outside.smooth <= max);
+    }
+
+    #[test]
+    fn test_periodicity() {
+        // the period 5 bulb is not covered by the interior check
+        let bulb = Complex::new(0.379513, 0.334932);
+        assert!(!in_set(&bulb));
+        assert_eq!(mandel(&bulb, 100_000).iterations, 100_000);
+
+        for i in -30..30 {
+            for j in -30..30 {
+                let c = Complex::new(i as f64 / 15.0 - 0.5, j as f64 / 15.0);
+                assert_eq!(
+                    mandel(&c, 2000).iterations,
+                    iterate(&Fractal::Mandelbrot, &c, 2000).iterations
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_interior_check() {
+        for &fractal in [Fractal::Mandelbrot, Fractal::Lambda].iter() {
+            let mut interior = 0;
+            for i in -40..40 {
+                for j in -40..40 {
+                    let c = Complex::new(i as f64 / 20.0 + 1.0, j as f64 / 20.0);
+                    let c = match fractal {
+                        Fractal::Lambda => c * 2.0,
+                        _ => c - 1.5,
+                    };
+                    if fractal.interior_check(&c) {
+                        interior += 1;
+                        assert_eq!(iterate_visit(&fractal, &c, 2000, |_| {}).iterations, 2000);
+                    }
+                }
+            }
+            assert!(interior > 100);
+        }
+    }
+
+    /// Inside on the left half plane, outside points escape twice as
+    /// fast below the real axis
+    struct HalfPlane;
+
+    impl Formula for HalfPlane {
+        fn step(&self, orbit: &Orbit, c: &Complex<f64>) -> Complex<f64> {
+            match (c.re > 0.0, c.im > 0.0) {
+                (false, _) => orbit.z,
+                (true, true) => orbit.z + 0.01,
+                (true, false) => orbit.z + 0.02,
+            }
+        }
+
+        fn interior_check(&self, c: &Complex<f64>) -> bool {
+            c.re <= 0.0
+        }
+    }
+
+    #[test]
+    fn test_estimate_iterations() {
+        let slow = HalfPlane
+            .escape_time(&Complex::new(1.0, 1.0), 1000)
+            .iterations;
+        let fast = HalfPlane
+            .escape_time(&Complex::new(1.0, -1.0), 1000)
+            .iterations;
+        assert!(fast > MIN_ITERATIONS && fast < slow);
 
-    println!("Equalized image in: {:?}", start.elapsed().unwrap());
+        // the boundary samples next to the imaginary axis escape after
+        // either count, the estimate covers the slower ones
+        let mut context = RenderContext::new();
+        let mut transform = Transform::new((80, 60));
+        let image = MandelImage::new(80, 60, 100);
+        let estimate = estimate_iterations(&mut context, &transform, &HalfPlane, &image);
+        assert_eq!(estimate, slow + 1);
+
+        // without points in the set the iterations are kept
+        transform.center_at(&Complex::new(10.0, 0.0));
+        let estimate = estimate_iterations(&mut context, &transform, &HalfPlane, &image);
+        assert_eq!(estimate, 100);
+    }
+
+    #[test]
+    fn test_equalize_exclusion() {
+        let mut image = MandelImage::new(10, 10, 10);
+        for i in 0..image.len() {
+            let n = if image.position(i).0 < 5 { 1 } else { 5 };
+            image.iterations.set(i, n);
+        }
+        let left = PixelRect::new((0, 0), (5, 10));
+
+        let mut context = RenderContext::new();
+        equalize_image(&mut context, &mut image, None);
+        assert_eq!(image.iterations_equalized[0], 5);
+        equalize_image(&mut context, &mut image, Some(&left));
+        assert_eq!(image.iterations_equalized[0], 0);
+        assert_eq!(image.iterations_equalized[99], 9);
+    }
+
+    #[test]
+    fn test_bands() {
+        let count = |pool: &mut BufferPool<(i32, i32)>| {
+            let mut pixels = Vec::new();
+            generate_tiles(
+                pool,
+                7,
+                50,
+                |x, y| (x, y),
+                |_, buffer| pixels.extend_from_slice(buffer),
+            );
+            pixels.sort();
+            pixels
+        };
+
+        let mut banded = BufferPool::new();
+        banded.limits.band_rows = Some(13);
+        banded.limits.tile = 4;
+        banded.limits.threads = 5;
+        let pixels = count(&mut banded);
+        assert_eq!(pixels, count(&mut BufferPool::new()));
+        assert_eq!(pixels.len(), 7 * 50);
+        assert_eq!(pixels.last(), Some(&(6, 49)));
+
+        let mut blocks = BufferPool::new();
+        blocks.limits.block = 2;
+        blocks.limits.threads = 1;
+        let pixels = count(&mut blocks);
+        assert_eq!(pixels.len(), 7 * 50);
+        assert_eq!(pixels.last(), Some(&(6, 48)));
+    }
+
+    #[test]
+    fn test_refine() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let count = |pool: &mut BufferPool<(i32, i32)>| {
+            let counter = calls.clone();
+            let mut pixels = Vec::new();
+            generate_tiles(
+                pool,
+                21,
+                30,
+                move |x, y| {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    (x, y)
+                },
+                |_, buffer| pixels.extend_from_slice(buffer),
+            );
+            pixels.sort();
+            (calls.swap(0, Ordering::Relaxed), pixels)
+        };
+        let (_, exact) = count(&mut BufferPool::new());
+
+        let mut pool = BufferPool::new();
+        pool.limits.tile = 8;
+        pool.limits.block = 4;
+        pool.set_refine(true);
+        assert_eq!(count(&mut pool).0, 6 * 8);
+        // the full pass only calculates the pixels between the samples
+        pool.limits.block = 1;
+        assert_eq!(count(&mut pool), (21 * 30 - 6 * 8, exact.clone()));
+        assert_eq!(count(&mut pool).0, 21 * 30);
+
+        pool.limits.block = 4;
+        count(&mut pool);
+        pool.set_refine(false);
+        pool.limits.block = 1;
+        assert_eq!(count(&mut pool), (21 * 30, exact));
+    }
+
+    #[test]
+    fn test_faults() {
+        let render = |pool: &mut BufferPool<(i32, i32)>| {
+            let mut pixels = Vec::new();
+            let mut order = Vec::new();
+            generate_tiles(
+                pool,
+                5,
+                40,
+                |x, y| (x, y),
+                |tile, buffer| {
+                    assert_eq!(buffer.len(), tile.len());
+                    order.push(tile.top);
+                    pixels.extend_from_slice(buffer);
+                },
+            );
+            pixels.sort();
+            (pixels, order)
+        };
+
+        let mut clean = BufferPool::new();
+        clean.limits.threads = 4;
+        clean.limits.tile = 8;
+        let (expected, _) = render(&mut clean);
+
+        // all tiles arrive, lost tiles last as they are rendered again on
+        // the caller, the others in the order their threads finish them
+        let mut faulty = BufferPool::new();
+        faulty.limits.threads = 4;
+        faulty.limits.tile = 8;
+        faulty.faults = vec![Some(Fault::Slow(10)), Some(Fault::Drop), Some(Fault::Panic)];
+        let (pixels, mut order) = render(&mut faulty);
+        assert_eq!(pixels, expected);
+        assert_eq!(order[3..], [8, 16]);
+        order[..3].sort();
+        assert_eq!(order[..3], [0, 24, 32]);
+
+        // in deterministic mode slow and lost tiles do not change the order
+        faulty.limits.deterministic = true;
+        let (pixels, order) = render(&mut faulty);
+        assert_eq!(pixels, expected);
+        assert_eq!(order, vec![0, 8, 16, 24, 32]);
+        faulty.limits.deterministic = false;
+
+        // the pool stays usable after failures, also in bands
+        faulty.limits.band_rows = Some(6);
+        assert_eq!(render(&mut faulty).0, expected);
+        faulty.faults.clear();
+        assert_eq!(render(&mut faulty).0, expected);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut pool = BufferPool::new();
+        pool.limits.threads = 1;
+        pool.limits.tile = 8;
+        let render = |pool: &mut BufferPool<f64>, cancel_row: i32| {
+            let cancel = pool.cancel.clone();
+            let mut tiles = Vec::new();
+            generate_tiles(
+                pool,
+                5,
+                40,
+                move |_, y| {
+                    // the view changes while this row is calculated
+                    if y == cancel_row {
+                        cancel.cancel();
+                    }
+                    y as f64
+                },
+                |tile, _| tiles.push(tile.top),
+            );
+            tiles
+        };
+
+        // the started tile is finished, the others are skipped
+        assert_eq!(render(&mut pool, 12), vec![0, 8]);
+        assert_eq!(render(&mut pool, 40), vec![]);
+
+        pool.cancel.reset();
+        pool.limits.band_rows = Some(16);
+        assert_eq!(render(&mut pool, 40), vec![0, 8, 16, 24, 32]);
+        pool.cancel.reset();
+        assert_eq!(render(&mut pool, 20), vec![0, 8, 16]);
+    }
+
+    #[test]
+    fn test_strategies() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let render = |strategy| {
+            let mut pool = BufferPool::new();
+            pool.limits.threads = 3;
+            pool.limits.strategy = strategy;
+            let mut image = vec![Outcome::default(); 120 * 90];
+            let counter = calls.clone();
+            counter.store(0, Ordering::Relaxed);
+            generate_tiles(
+                &mut pool,
+                120,
+                90,
+                move |x, y| {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    let c = Complex::new(x as f64 / 120.0 - 0.9, y as f64 / 120.0 - 0.375);
+                    mandel(&c, 200)
+                },
+                |tile, outcomes| {
+                    let width = tile.width() as usize;
+                    for (y, row) in (tile.top..tile.bottom).zip(outcomes.chunks(width)) {
+                        let start = y as usize * 120 + tile.left as usize;
+                        image[start..start + row.len()].copy_from_slice(row);
+                    }
+                },
+            );
+            let values: Vec<_> = image
+                .iter()
+                .map(|o| (o.iterations, o.converged, o.smooth, o.z))
+                .collect();
+            (values, calls.load(Ordering::Relaxed))
+        };
+
+        let (brute, brute_calls) = render(Strategy::BruteForce);
+        // the view is mostly inside the main cardioid
+        let (traced, traced_calls) = render(Strategy::BorderTracing);
+        assert_eq!(brute_calls, 120 * 90);
+        assert!(traced_calls < brute_calls / 2);
+        assert_eq!(traced, brute);
+
+        // escaped pixels keep their own fractional iterations
+        let outside =
+            |x: i32, y: i32| mandel(&Complex::new(x as f64 / 40.0 + 1.0, y as f64 / 40.0), 100);
+        let tracing = Limits {
+            strategy: Strategy::BorderTracing,
+            ..Limits::default()
+        };
+        let (mut exact, mut filled) = (Vec::new(), Vec::new());
+        fill_rows(&outside, 32, Limits::default(), 0..32, &mut exact);
+        fill_rows(&outside, 32, tracing, 0..32, &mut filled);
+        assert!(exact.iter().zip(&filled).all(|(a, b)| a.smooth == b.smooth));
+
+        let (guessed, guessed_calls) = render(Strategy::SolidGuessing);
+        assert!(guessed_calls < brute_calls / 2);
+        assert_eq!(guessed, brute);
+    }
+
+    #[test]
+    fn test_scratch_reuse() {
+        // each thread keeps the mask of the strategies for the next tiles
+        let pixel =
+            |x: i32, y: i32| mandel(&Complex::new(x as f64 / 40.0 - 0.9, y as f64 / 40.0), 100);
+        let limits = Limits {
+            strategy: Strategy::BorderTracing,
+            ..Limits::default()
+        };
+        let mut buffer = Vec::new();
+        fill_rows(&pixel, 32, limits, 0..32, &mut buffer);
+        let mask = SCRATCH.with(|scratch| scratch.borrow().done.as_ptr());
+        buffer.clear();
+        fill_rows(&pixel, 16, limits, 0..16, &mut buffer);
+        assert_eq!(SCRATCH.with(|scratch| scratch.borrow().done.as_ptr()), mask);
+    }
+
+    #[test]
+    fn test_distance() {
+        // the set reaches 0.25 on the positive and -2 on the negative axis
+        for &(x, expected) in [(1.0, 0.75), (-2.5, 0.5)].iter() {
+            let d = distance(&Complex::new(x, 0.0), 100);
+            assert!(d > expected / 2.0 && d < expected * 2.0, "{} at {}", d, x);
+        }
+        assert_eq!(distance(&Complex::new(-1.0, 0.0), 100), 0.0);
+        // the period 2 disk of radius 0.25 is centered at -1
+        let d = interior_distance(&Complex::new(-1.0, 0.0), 100);
+        assert!((d - 0.25).abs() < 1e-9, "{}", d);
+        assert_eq!(interior_distance(&Complex::new(1.0, 0.0), 100), 0.0);
+
+        let transform = Transform::new((16, 12));
+        let mut image = MandelImage::new(16, 12, 100);
+        assert!(image.distance.is_empty() && image.interior.is_empty());
+        distance_image_thread(&mut RenderContext::new(), &transform, true, &mut image);
+        let mut inside = false;
+        for (x, y) in (0..16).flat_map(|x| (0..12).map(move |y| (x, y))) {
+            let c = transform.pos_to_complex(x, y);
+            let d = distance(&c, 100);
+            let interior = match d == 0.0 {
+                true => interior_distance(&c, 100),
+                false => 0.0,
+            };
+            inside |= interior > 0.0;
+            let i = image.index(x, y);
+            assert_eq!(image.distance[i], d * transform.scale());
+            assert_eq!(image.interior[i], interior * transform.scale());
+        }
+        assert!(inside);
+    }
+
+    #[test]
+    fn test_orbit_stats() {
+        let trap = Trap::new(TrapShape::Point, Complex::new(0.0, 0.0));
+        let stats = orbit_stats(
+            &Fractal::Mandelbrot,
+            &Complex::new(1.0, 0.0),
+            100,
+            Some(&trap),
+            true,
+        );
+        // the orbit 1, 2 reaches the escape radius
+        assert_eq!((stats.min_norm, stats.min_iteration), (1.0, 1));
+        assert_eq!(stats.z, Complex::new(2.0, 0.0));
+        assert_eq!(stats.derivative, Some(Complex::new(3.0, 0.0)));
+        assert_eq!(stats.trap, 1.0);
+
+        // the orbit -1, 0, -1, ... returns to 0
+        let stats = orbit_stats(
+            &Fractal::Mandelbrot,
+            &Complex::new(-1.0, 0.0),
+            100,
+            None,
+            false,
+        );
+        assert_eq!((stats.min_norm, stats.min_iteration), (0.0, 2));
+        assert_eq!((stats.derivative, stats.trap), (None, f64::INFINITY));
+
+        let transform = Transform::new((16, 12));
+        let formula: SharedFormula = Arc::new(Fractal::Mandelbrot);
+        let mut image = MandelImage::new(16, 12, 50);
+        assert!(image.stats(3, 4).is_none());
+        let mut context = RenderContext::new();
+        stats_image_thread(
+            &mut context,
+            &transform,
+            &formula,
+            Some(trap),
+            true,
+            &mut image,
+        );
+        let c = transform.pos_to_complex(3, 4);
+        let expected = orbit_stats(&Fractal::Mandelbrot, &c, 50, Some(&trap), true);
+        let stats = image.stats(3, 4).unwrap();
+        assert_eq!((stats.min_norm, stats.z), (expected.min_norm, expected.z));
+
+        // the trap distances and atom domains are recorded while iterating
+        // the escape times
+        let recorded: SharedFormula = Arc::new(Recorded::new(formula.clone(), Some(trap)));
+        generate_image_thread(&mut context, &transform, &recorded, 1, None, &mut image);
+        let i = image.index(3, 4);
+        assert_eq!(image.trap[i], expected.trap);
+        assert_eq!(image.period[i], expected.min_iteration);
+        assert_eq!(image.min_norm[i], expected.min_norm);
+        assert_eq!(
+            image.iterations(3, 4),
+            formula.escape_time(&c, 50).iterations
+        );
+    }
+
+    #[test]
+    fn test_symmetry() {
+        assert_eq!(symmetric_rows(48, 48), 0..25);
+        assert_eq!(symmetric_rows(48, 36), 18..48);
+        assert_eq!(symmetric_rows(48, 37), 19..48);
+        assert_eq!(symmetric_rows(48, -3), 0..48);
+        assert_eq!(symmetric_rows(48, 100), 0..48);
+
+        let mut transform = Transform::new((64, 48));
+        assert_eq!(transform.mirror_axis(), Some(48));
+        let formula: SharedFormula = Arc::new(Fractal::Mandelbrot);
+        let mut image = MandelImage::new(64, 48, 100);
+        generate_image_thread(
+            &mut RenderContext::new(),
+            &transform,
+            &formula,
+            1,
+            None,
+            &mut image,
+        );
+        for i in 0..image.len() {
+            let (x, y) = image.position(i);
+            let outcome = mandel(&transform.pos_to_complex(x, y), 100);
+            assert_eq!(image.iterations.get(i), outcome.iterations);
+            assert_eq!(image.z[i], outcome.z);
+        }
+
+        transform.recenter(32, 30);
+        assert_eq!(transform.mirror_axis(), Some(36));
+        transform.set_projection(Projection::Exponential);
+        assert_eq!(transform.mirror_axis(), None);
+    }
+
+    #[test]
+    fn test_pan() {
+        let formula: SharedFormula = Arc::new(Fractal::Mandelbrot);
+        let render = |transform: &Transform, known: Option<&PixelRect>, image: &mut MandelImage| {
+            generate_image_thread(
+                &mut RenderContext::new(),
+                transform,
+                &formula,
+                1,
+                known,
+                image,
+            )
+        };
+        let previous = Transform::new((64, 48));
+        let mut old = MandelImage::new(64, 48, 100);
+        render(&previous, None, &mut old);
+
+        let mut transform = previous.clone();
+        transform.recenter(42, 17);
+        assert_eq!(transform.pan_offset(&previous), Some((10, -7)));
+        transform.zoom(2.0);
+        assert_eq!(transform.pan_offset(&previous), None);
+        transform.zoom(0.5);
+
+        let mut image = MandelImage::new(64, 48, 100);
+        let known = image.copy_shifted(&old, 10, -7);
+        assert_eq!(known, PixelRect::new((0, 7), (54, 48)));
+        assert_eq!(exposed_strips(64, 48, &known).len(), 2);
+        render(&transform, Some(&known), &mut image);
+
+        let mut full = MandelImage::new(64, 48, 100);
+        render(&transform, None, &mut full);
+        assert_eq!(image.iterations, full.iterations);
+    }
 }
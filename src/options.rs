@@ -0,0 +1,424 @@
+use crate::backend::Backend;
+use crate::deep::{Fixed, FixedComplex};
+use crate::formula::Expression;
+use crate::fractal::{self, Fractal};
+use crate::lyapunov;
+use crate::macros::{self, Macro};
+use crate::mandelbrot::Strategy;
+use crate::palette::{self, ColorScheme};
+use crate::types::{View, DEFAULT_VIEW};
+
+use std::path::Path;
+use std::sync::Arc;
+
+/// Largest supported image width and height, common texture size limit
+pub const MAX_SIZE: u32 = 16384;
+
+/// Largest width and height of exports rendered in bands, only a band of
+/// rows is in memory at a time
+pub const MAX_BANDED_SIZE: u32 = 1 << 20;
+
+/// Most jittered samples per pixel, beyond this supersampling is cheaper
+const MAX_JITTER_SAMPLES: u32 = 16;
+
+/// Command line options
+#[derive(Clone)]
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    pub iterations: u32,
+    /// Image center in the complex plane with all given digits, default view if None
+    pub center: Option<FixedComplex>,
+    pub zoom: f64,
+    /// Visible height of the default view in complex units
+    pub view_height: f64,
+    /// Escape radius replacing the radius of the formula
+    pub bailout: Option<f64>,
+    /// Window size if different from the image size
+    pub window: Option<(u32, u32)>,
+    /// Size of exported images, the image size if None
+    pub export_size: Option<(u32, u32)>,
+    /// A/B sequence for the Lyapunov generator
+    pub sequence: Arc<Vec<bool>>,
+    /// User defined iteration formula
+    pub formula: Option<Arc<Expression>>,
+    /// File extension of exported images, "png" or "jpg"
+    pub export_format: String,
+    /// JPEG quality of exported images from 1 to 100
+    pub quality: u8,
+    /// Rows rendered at a time for exported images, the whole image if None
+    pub export_bands: Option<u32>,
+    /// File extension of exported orbits, "csv" or "json"
+    pub orbit_format: String,
+    /// Schedule of formulas for hybrid fractals
+    pub schedule: Vec<Fractal>,
+    /// Log frames that miss the vsync interval
+    pub frame_diagnostics: bool,
+    /// Pin the render workers to CPUs spread over the NUMA nodes
+    pub pin_threads: bool,
+    /// Log the time of every render stage
+    pub log_timings: bool,
+    /// Render in bands without equalization and caches for small devices
+    pub low_mem: bool,
+    /// Renders that do not depend on thread timing or the power state
+    pub deterministic: bool,
+    /// Sequences of actions bound to function keys
+    pub macros: Vec<Macro>,
+    /// Color schemes loaded from palette files
+    pub palettes: Vec<ColorScheme>,
+    /// Write a desktop entry and icon instead of starting the viewer
+    pub install_desktop_entry: bool,
+    /// Address to broadcast view changes on
+    pub host: Option<String>,
+    /// Address of a host to follow
+    pub join: Option<String>,
+    /// Order in which pixels are calculated
+    pub strategy: Strategy,
+    /// Kernel calculating plain Mandelbrot views, the formula renderer if None
+    pub backend: Option<Backend>,
+    /// Exponent of the growth of the iterations with the zoom depth
+    pub iteration_curve: f64,
+    /// Record the orbit statistics of every pixel
+    pub orbit_stats: bool,
+    /// Iterate shallow views of the Mandelbrot set in f32
+    pub single_precision: bool,
+    /// Jittered samples blended for each pixel, 1 samples the pixel center
+    pub jitter: u32,
+}
+
+impl Options {
+    /// Parses the command line arguments, excluding the program name
+    pub fn parse<I>(args: I) -> Result<Self, String>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut args = args;
+        let mut options = Options {
+            width: 1000,
+            height: 800,
+            iterations: 150,
+            center: None,
+            zoom: 1.0,
+            view_height: DEFAULT_VIEW.height,
+            bailout: None,
+            window: None,
+            export_size: None,
+            sequence: Arc::new(lyapunov::parse_sequence(lyapunov::DEFAULT_SEQUENCE)?),
+            formula: None,
+            export_format: "png".to_string(),
+            quality: 90,
+            export_bands: None,
+            orbit_format: "csv".to_string(),
+            schedule: vec![
+                Fractal::Mandelbrot,
+                Fractal::Mandelbrot,
+                Fractal::BurningShip,
+            ],
+            frame_diagnostics: false,
+            pin_threads: false,
+            log_timings: true,
+            low_mem: false,
+            deterministic: false,
+            macros: Vec::new(),
+            palettes: Vec::new(),
+            install_desktop_entry: false,
+            host: None,
+            join: None,
+            strategy: Strategy::BruteForce,
+            backend: None,
+            iteration_curve: 1.0,
+            orbit_stats: false,
+            single_precision: false,
+            jitter: 1,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for option '{}'", arg))
+            };
+
+            match arg.as_str() {
+                "--width" => options.width = validate_size("width", parse(&value()?)?)?,
+                "--height" => options.height = validate_size("height", parse(&value()?)?)?,
+                "--iterations" => options.iterations = validate_iterations(parse(&value()?)?)?,
+                "--center" => options.center = Some(parse_center(&value()?)?),
+                "--zoom" => options.zoom = parse_zoom(&value()?)?,
+                "--view-height" => options.view_height = validate_view_height(parse(&value()?)?)?,
+                "--bailout" => options.bailout = Some(validate_bailout(parse(&value()?)?)?),
+                "--window" => options.window = Some(parse_size(&value()?)?),
+                "--export-size" => options.export_size = Some(parse_export_size(&value()?)?),
+                "--sequence" => options.sequence = Arc::new(lyapunov::parse_sequence(&value()?)?),
+                "--export-format" => options.export_format = parse_export_format(&value()?)?,
+                "--quality" => options.quality = validate_quality(parse(&value()?)?)?,
+                "--export-bands" => options.export_bands = Some(validate_bands(parse(&value()?)?)?),
+                "--orbit-format" => options.orbit_format = parse_orbit_format(&value()?)?,
+                "--hybrid" => options.schedule = fractal::parse_schedule(&value()?)?,
+                "--frame-diagnostics" => options.frame_diagnostics = true,
+                "--quiet-timings" => options.log_timings = false,
+                "--pin-threads" if cfg!(feature = "affinity") => options.pin_threads = true,
+                "--pin-threads" => {
+                    return Err("--pin-threads needs a build with the affinity feature".to_string())
+                }
+                "--low-mem" => options.low_mem = true,
+                "--deterministic" => options.deterministic = true,
+                "--orbit-stats" => options.orbit_stats = true,
+                "--f32" => options.single_precision = true,
+                "--jitter" => options.jitter = validate_jitter(parse(&value()?)?)?,
+                "--install-desktop-entry" => options.install_desktop_entry = true,
+                "--host" => options.host = Some(value()?),
+                "--join" => options.join = Some(value()?),
+                "--renderer" => options.strategy = value()?.parse()?,
+                "--backend" => options.backend = Some(value()?.parse()?),
+                "--iteration-curve" => {
+                    options.iteration_curve = validate_iteration_curve(parse(&value()?)?)?
+                }
+                "--palettes" => options
+                    .palettes
+                    .extend(palette::load_palettes(Path::new(&value()?))?),
+                "--macro" => options.macros.push(macros::parse_macro(&value()?)?),
+                "--formula" => options.formula = Some(Arc::new(Expression::parse(&value()?)?)),
+                _ => return Err(format!("Unknown option '{}'", arg)),
+            }
+        }
+
+        if options.export_bands.is_some() && options.export_format != "png" {
+            return Err("--export-bands only writes PNG files".to_string());
+        }
+        if let (Some((width, height)), None) = (options.export_size, options.export_bands) {
+            if width > MAX_SIZE || height > MAX_SIZE {
+                return Err(format!(
+                    "Export size {}x{} needs --export-bands beyond {} pixels per side",
+                    width, height, MAX_SIZE
+                ));
+            }
+        }
+        Ok(options)
+    }
+
+    /// View restored by reset, the default view with the given center and height
+    pub fn view(&self) -> View {
+        View {
+            center: self
+                .center
+                .as_ref()
+                .map_or(DEFAULT_VIEW.center, FixedComplex::to_f64),
+            height: self.view_height,
+        }
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid number '{}'", value))
+}
+
+pub fn parse_orbit_format(value: &str) -> Result<String, String> {
+    match value.to_lowercase().as_str() {
+        format @ "csv" | format @ "json" => Ok(format.to_string()),
+        _ => Err(format!(
+            "Invalid orbit format '{}', expected csv or json",
+            value
+        )),
+    }
+}
+
+pub fn parse_export_format(value: &str) -> Result<String, String> {
+    match value.to_lowercase().as_str() {
+        "png" => Ok("png".to_string()),
+        "jpg" | "jpeg" => Ok("jpg".to_string()),
+        format @ "webp" | format @ "avif" => {
+            Err(format!("Export format {} is not supported yet", format))
+        }
+        _ => Err(format!(
+            "Invalid export format '{}', expected png or jpg",
+            value
+        )),
+    }
+}
+
+/// Parses an image size given as "WIDTHxHEIGHT"
+pub fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let mut parts = value.split('x');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(w), Some(h), None) => Ok((
+            validate_size("width", parse(w)?)?,
+            validate_size("height", parse(h)?)?,
+        )),
+        _ => Err(format!("Invalid size '{}', expected 'WIDTHxHEIGHT'", value)),
+    }
+}
+
+/// Parses "re,im" with decimal numbers of any length
+pub fn parse_center(value: &str) -> Result<FixedComplex, String> {
+    let mut parts = value.split(',');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(re), Some(im), None) => {
+            let center = FixedComplex::new(Fixed::parse(re)?, Fixed::parse(im)?);
+            let approx = center.to_f64();
+            match approx.re.is_finite() && approx.im.is_finite() {
+                true => Ok(center),
+                false => Err(format!("Center '{}' is out of range", value)),
+            }
+        }
+        _ => Err(format!("Invalid coordinate '{}', expected 're,im'", value)),
+    }
+}
+
+/// Parses an export size, sides beyond `MAX_SIZE` are only possible for
+/// exports in bands, which is checked after all options are known
+pub fn parse_export_size(value: &str) -> Result<(u32, u32), String> {
+    let mut parts = value.split('x');
+    let validate = |name: &str, size: u32| match size == 0 || size > MAX_BANDED_SIZE {
+        true => Err(format!(
+            "Export {} {} must be between 1 and {}",
+            name, size, MAX_BANDED_SIZE
+        )),
+        false => Ok(size),
+    };
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(w), Some(h), None) => Ok((
+            validate("width", parse(w)?)?,
+            validate("height", parse(h)?)?,
+        )),
+        _ => Err(format!("Invalid size '{}', expected 'WIDTHxHEIGHT'", value)),
+    }
+}
+
+/// Checks that an image dimension is usable as texture size
+pub fn validate_size(name: &str, size: u32) -> Result<u32, String> {
+    if size == 0 || size > MAX_SIZE {
+        return Err(format!(
+            "Image {} {} must be between 1 and {}",
+            name, size, MAX_SIZE
+        ));
+    }
+    Ok(size)
+}
+
+pub fn validate_iterations(iterations: u32) -> Result<u32, String> {
+    if iterations == 0 {
+        return Err("Maximum iterations must be greater than 0".to_string());
+    }
+    Ok(iterations)
+}
+
+pub fn validate_quality(quality: u8) -> Result<u8, String> {
+    if quality == 0 || quality > 100 {
+        return Err(format!("Quality {} must be between 1 and 100", quality));
+    }
+    Ok(quality)
+}
+
+pub fn validate_bands(rows: u32) -> Result<u32, String> {
+    if rows == 0 {
+        return Err("Export bands need at least one row".to_string());
+    }
+    Ok(rows)
+}
+
+pub fn parse_zoom(value: &str) -> Result<f64, String> {
+    validate_zoom(parse(value)?)
+}
+
+pub fn validate_zoom(zoom: f64) -> Result<f64, String> {
+    if !zoom.is_finite() || zoom <= 0.0 {
+        return Err(format!("Zoom {} must be a finite number above 0", zoom));
+    }
+    Ok(zoom)
+}
+
+pub fn validate_bailout(radius: f64) -> Result<f64, String> {
+    if !radius.is_finite() || radius <= 0.0 {
+        return Err(format!(
+            "Bailout radius {} must be a finite number above 0",
+            radius
+        ));
+    }
+    Ok(radius)
+}
+
+pub fn validate_view_height(height: f64) -> Result<f64, String> {
+    if !height.is_finite() || height <= 0.0 {
+        return Err(format!(
+            "View height {} must be a finite number above 0",
+            height
+        ));
+    }
+    Ok(height)
+}
+
+pub fn validate_jitter(samples: u32) -> Result<u32, String> {
+    if !(1..=MAX_JITTER_SAMPLES).contains(&samples) {
+        return Err(format!(
+            "Jitter samples {} must be between 1 and {}",
+            samples, MAX_JITTER_SAMPLES
+        ));
+    }
+    Ok(samples)
+}
+
+pub fn validate_iteration_curve(exponent: f64) -> Result<f64, String> {
+    if !exponent.is_finite() || exponent <= 0.0 {
+        return Err(format!(
+            "Iteration curve {} must be a finite number above 0",
+            exponent
+        ));
+    }
+    Ok(exponent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::complex::Complex;
+
+    fn parse_args(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let options = parse_args(&["--width", "640", "--center", "-0.75,0.1"]).unwrap();
+        assert_eq!(options.width, 640);
+        let center = options.center.unwrap();
+        assert_eq!(center.to_f64(), Complex::new(-0.75, 0.1));
+
+        let options = parse_args(&["--export-size", "1920x1080"]).unwrap();
+        assert_eq!(options.export_size, Some((1920, 1080)));
+        let poster = ["--export-size", "32000x24000", "--export-bands", "256"];
+        let options = parse_args(&poster).unwrap();
+        assert_eq!(options.export_size, Some((32000, 24000)));
+        assert!(parse_args(&poster[..2]).is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(parse_args(&["--width", "0"]).is_err());
+        assert!(parse_args(&["--height", "100000"]).is_err());
+        assert!(parse_args(&["--iterations", "0"]).is_err());
+        assert!(parse_args(&["--iterations", "-5"]).is_err());
+        assert!(parse_args(&["--center", "NaN,0"]).is_err());
+        assert!(parse_args(&["--center", "1"]).is_err());
+        assert!(parse_args(&["--center", "1e400,0"]).is_err());
+        assert!(parse_args(&["--center", "0,1e2000000000"]).is_err());
+        assert!(parse_args(&["--center", &format!("0.{},0", "1".repeat(5000))]).is_err());
+        assert!(parse_args(&["--zoom", "0"]).is_err());
+        assert!(parse_args(&["--zoom", "inf"]).is_err());
+        assert!(parse_args(&["--zoom"]).is_err());
+        assert!(parse_args(&["--view-height", "-1"]).is_err());
+        assert!(parse_args(&["--bailout", "0"]).is_err());
+        assert!(parse_args(&["--window", "800"]).is_err());
+        assert!(parse_args(&["--window", "800x0"]).is_err());
+        assert!(parse_args(&["--hybrid", "mandelbrot,julia"]).is_err());
+        assert!(parse_args(&["--export-format", "webp"]).is_err());
+        assert!(parse_args(&["--quality", "101"]).is_err());
+        assert!(parse_args(&["--export-bands", "0"]).is_err());
+        assert!(parse_args(&["--export-bands", "64", "--export-format", "jpg"]).is_err());
+        assert!(parse_args(&["--iteration-curve", "0"]).is_err());
+        assert!(parse_args(&["--jitter", "0"]).is_err());
+        assert!(parse_args(&["--unknown"]).is_err());
+    }
+}
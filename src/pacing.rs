@@ -0,0 +1,94 @@
+use crate::timing;
+
+use std::time::{Duration, Instant};
+
+/// Refresh rate assumed if the display does not report one
+const DEFAULT_REFRESH_RATE: i32 = 60;
+
+/// Measures the stages of each frame to find the cause of frames that miss
+/// the vsync interval. With vsync the present stage waits for the display,
+/// a frame counts as missed once it takes more than one and a half intervals.
+pub struct FramePacing {
+    interval: Duration,
+    last: Instant,
+    /// start of the previous frame, None before the first frame
+    frame_start: Option<Instant>,
+    stages: Vec<(&'static str, Duration)>,
+}
+
+/// A frame that missed the vsync interval
+#[derive(Debug)]
+pub struct MissedFrame {
+    pub total: Duration,
+    pub stage: &'static str,
+    pub stage_time: Duration,
+}
+
+impl FramePacing {
+    pub fn new(refresh_rate: i32) -> Self {
+        let rate = match refresh_rate > 0 {
+            true => refresh_rate,
+            false => DEFAULT_REFRESH_RATE,
+        };
+        FramePacing {
+            interval: Duration::from_secs(1) / rate as u32,
+            last: Instant::now(),
+            frame_start: None,
+            stages: Vec::new(),
+        }
+    }
+
+    /// Starts measuring a new frame, the time since the previous frame
+    /// is recorded for the frame rate
+    pub fn start(&mut self) {
+        self.stages.clear();
+        self.last = Instant::now();
+        if let Some(previous) = self.frame_start.replace(self.last) {
+            timing::add(timing::FRAME, self.last - previous);
+        }
+    }
+
+    /// Ends a stage of the frame, the stage started at the end of the previous one
+    pub fn stage(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.stages.push((name, now - self.last));
+        self.last = now;
+    }
+
+    /// Ends the frame, returns the slowest stage if the frame was missed
+    pub fn finish(&mut self) -> Option<MissedFrame> {
+        let total = self.stages.iter().map(|&(_, time)| time).sum();
+        if total <= self.interval * 3 / 2 {
+            return None;
+        }
+
+        let &(stage, stage_time) = self.stages.iter().max_by_key(|&&(_, time)| time)?;
+        Some(MissedFrame {
+            total,
+            stage,
+            stage_time,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missed_frame() {
+        let mut pacing = FramePacing::new(0);
+        assert_eq!(pacing.interval, Duration::from_secs(1) / 60);
+
+        pacing.start();
+        pacing.stage("events");
+        assert!(pacing.finish().is_none());
+
+        pacing.start();
+        pacing.stage("events");
+        pacing.stages.push(("render", Duration::from_millis(40)));
+        let missed = pacing.finish().unwrap();
+        assert_eq!(missed.stage, "render");
+        assert!(missed.total >= Duration::from_millis(40));
+    }
+}
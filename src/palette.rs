@@ -1,5 +1,28 @@
 use sdl2::pixels::Color;
 
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+/// File extension of gradient palette files
+const PALETTE_EXTENSION: &str = "palette";
+
+/// File extension of Fractint color maps
+const MAP_EXTENSION: &str = "map";
+
+/// File extension of UltraFractal gradient collections
+const UGR_EXTENSION: &str = "ugr";
+
+/// Positions of an UltraFractal gradient, the indices of its colors
+const UGR_POSITIONS: f64 = 400.0;
+
+/// Colors sampled from palettes without stops for their definition
+const DEFINITION_SAMPLES: u32 = 16;
+
+/// Hue step in degrees between atom domains of consecutive periods, the
+/// golden angle keeps the hues of nearby periods far apart
+const GOLDEN_ANGLE: f64 = 137.507_764;
+
 /// defined color scheme
 #[derive(Copy, Clone)]
 pub enum ColorScheme {
@@ -7,6 +30,463 @@ pub enum ColorScheme {
     Rainbow,
     Redish,
     Blue,
+    /// palette registered at startup
+    Custom(&'static dyn Palette),
+}
+
+/// A color scheme contributed without changing `ColorScheme`
+pub trait Palette: Send + Sync {
+    /// Unique name to select the palette
+    fn name(&self) -> &str;
+
+    /// Color of n iterations, n == max is inside the set
+    fn color(&self, n: u32, max: u32) -> Color;
+
+    /// Colors of the palette in the format of palette files, evenly spaced
+    /// samples unless the palette has stops of its own
+    fn definition(&self) -> String {
+        (0..DEFINITION_SAMPLES)
+            .map(|n| hex(self.color(n, DEFINITION_SAMPLES)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Palettes registered at startup, they live until the program ends
+static REGISTRY: RwLock<Vec<&'static dyn Palette>> = RwLock::new(Vec::new());
+
+/// Color table of the last colored image
+static TABLE: Mutex<Option<Arc<ColorTable>>> = Mutex::new(None);
+
+/// Adds a palette to the registry and returns its color scheme
+pub fn register(palette: Box<dyn Palette>) -> Result<ColorScheme, String> {
+    let name = palette.name().to_string();
+    if name.parse::<ColorScheme>().is_ok() {
+        return Err(format!("Color scheme '{}' is already defined", name));
+    }
+
+    let palette: &'static dyn Palette = Box::leak(palette);
+    REGISTRY.write().unwrap().push(palette);
+    Ok(ColorScheme::Custom(palette))
+}
+
+/// All registered palettes in the order of registration
+pub fn registered() -> Vec<ColorScheme> {
+    REGISTRY
+        .read()
+        .unwrap()
+        .iter()
+        .map(|&palette| ColorScheme::Custom(palette))
+        .collect()
+}
+
+/// How the colors between the stops of a gradient are blended
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    /// straight lines between neighboring stops
+    Linear,
+    /// Catmull-Rom spline through the stops, without kinks at the stops
+    Spline,
+}
+
+/// Palette of colors at positions from 0 to 1, the built-in schemes are
+/// presets of it
+pub struct Gradient {
+    name: String,
+    /// positions in ascending order with their colors
+    stops: Vec<(f64, Color)>,
+    interpolation: Interpolation,
+}
+
+impl Gradient {
+    pub fn new(
+        name: &str,
+        stops: Vec<(f64, Color)>,
+        interpolation: Interpolation,
+    ) -> Result<Self, String> {
+        if stops.len() < 2 {
+            return Err(format!("Palette '{}' needs at least 2 colors", name));
+        }
+        let in_range = stops.iter().all(|(p, _)| (0.0..=1.0).contains(p));
+        if !in_range || stops.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+            return Err(format!(
+                "Positions of palette '{}' must ascend from 0 to 1",
+                name
+            ));
+        }
+
+        Ok(Gradient {
+            name: name.to_string(),
+            stops,
+            interpolation,
+        })
+    }
+
+    /// Parses a gradient with one hex color like `#ff8000` per line, evenly
+    /// spaced unless every color follows its position like `0.25 #ff8000`.
+    /// A line `spline` blends the colors with a spline instead of lines.
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let mut interpolation = Interpolation::Linear;
+        let mut colors = Vec::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match line {
+                "linear" => interpolation = Interpolation::Linear,
+                "spline" => interpolation = Interpolation::Spline,
+                _ => colors.push(parse_stop(name, line)?),
+            }
+        }
+
+        let last = colors.len().saturating_sub(1).max(1) as f64;
+        let stops = colors
+            .iter()
+            .enumerate()
+            .map(|(i, &(position, color))| match position {
+                Some(position) => Ok((position, color)),
+                None if colors.iter().all(|(p, _)| p.is_none()) => Ok((i as f64 / last, color)),
+                None => Err(format!(
+                    "Palette '{}' mixes colors with and without positions",
+                    name
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Gradient::new(name, stops, interpolation)
+    }
+
+    /// Parses a Fractint color map, a line of red, green and blue from 0
+    /// to 255 per color, optionally followed by a comment. The colors are
+    /// evenly spaced.
+    pub fn parse_map(name: &str, text: &str) -> Result<Self, String> {
+        let colors = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let mut channels = line.split_whitespace().map(str::parse::<u8>);
+                match (channels.next(), channels.next(), channels.next()) {
+                    (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Ok(Color::RGB(r, g, b)),
+                    _ => Err(format!("Invalid color '{}' in palette '{}'", line, name)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let last = colors.len().saturating_sub(1).max(1) as f64;
+        let stops = (0..).map(|i| i as f64 / last).zip(colors).collect();
+        Gradient::new(name, stops, Interpolation::Linear)
+    }
+
+    /// Parses the gradients of an UltraFractal collection, blocks like
+    /// `name { gradient: title="..." smooth=yes index=0 color=255 ... }`
+    /// with colors as 0xBBGGRR at indices from 0 to 399. The gradients wrap
+    /// around, the colors before the first and after the last index blend
+    /// between them. Each block gives its gradient or why it is invalid.
+    pub fn parse_ugr(text: &str) -> Vec<Result<Self, String>> {
+        let mut gradients = Vec::new();
+        // gradient of the open block, checked when the block ends
+        let mut block: Option<Result<Gradient, String>> = None;
+        let mut section = "";
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some(name) = line.strip_suffix('{') {
+                block = Some(Ok(Gradient {
+                    name: name.trim().to_string(),
+                    stops: Vec::new(),
+                    interpolation: Interpolation::Linear,
+                }));
+                section = "";
+                continue;
+            }
+            if line == "}" {
+                if let Some(gradient) = block.take() {
+                    gradients.push(gradient.and_then(|gradient| {
+                        let stops = wrap(gradient.stops);
+                        Gradient::new(&gradient.name, stops, gradient.interpolation)
+                    }));
+                }
+                continue;
+            }
+            if let Some(label) = line.strip_suffix(':') {
+                section = label;
+                continue;
+            }
+            if let Some(Ok(gradient)) = &mut block {
+                if section == "gradient" {
+                    if let Err(e) = gradient.parse_ugr_line(line) {
+                        block = Some(Err(e));
+                    }
+                }
+            }
+        }
+        gradients
+    }
+
+    /// Adds the title, the interpolation or the colors of a line of an
+    /// UltraFractal gradient
+    fn parse_ugr_line(&mut self, line: &str) -> Result<(), String> {
+        let mut index = None;
+        for (key, value) in ugr_values(line) {
+            let name = &self.name;
+            let invalid = || format!("Invalid {} '{}' in gradient '{}'", key, value, name);
+            match key {
+                "title" => self.name = value.to_string(),
+                "smooth" if value == "yes" => self.interpolation = Interpolation::Spline,
+                "index" => index = Some(value.parse::<f64>().map_err(|_| invalid())?),
+                "color" => {
+                    let bgr = value.parse::<u32>().map_err(|_| invalid())?;
+                    let color = Color::RGB(bgr as u8, (bgr >> 8) as u8, (bgr >> 16) as u8);
+                    let index = index.ok_or_else(invalid)?;
+                    let position = index.rem_euclid(UGR_POSITIONS) / UGR_POSITIONS;
+                    self.stops.push((position, color));
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Color at a position from 0 to 1, positions outside get the end colors
+    pub fn at(&self, position: f64) -> Color {
+        let stops = &self.stops;
+        let position = position.clamp(0.0, 1.0);
+        let above = stops
+            .partition_point(|(p, _)| *p <= position)
+            .clamp(1, stops.len() - 1);
+        let ((start, below_color), (end, above_color)) = (stops[above - 1], stops[above]);
+        let t = match end > start {
+            true => ((position - start) / (end - start)).clamp(0.0, 1.0),
+            false => 1.0,
+        };
+
+        match self.interpolation {
+            Interpolation::Linear => mix(below_color, above_color, t),
+            Interpolation::Spline => {
+                let before = stops[(above - 1).saturating_sub(1)].1;
+                let after = stops[(above + 1).min(stops.len() - 1)].1;
+                spline([before, below_color, above_color, after], t)
+            }
+        }
+    }
+}
+
+impl Palette for Gradient {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn color(&self, n: u32, max: u32) -> Color {
+        if n >= max {
+            return Color::RGB(0, 0, 0);
+        }
+        self.at(n as f64 / max as f64)
+    }
+
+    fn definition(&self) -> String {
+        let stops = self
+            .stops
+            .iter()
+            .map(|&(position, color)| format!("{} {}", position, hex(color)));
+        match self.interpolation {
+            Interpolation::Linear => stops.collect::<Vec<_>>().join("\n"),
+            Interpolation::Spline => std::iter::once("spline".to_string())
+                .chain(stops)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+/// Color as `#rrggbb` like in palette files
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// Key and value pairs like `index=0 title="Some name"` of a line of an
+/// UltraFractal gradient
+fn ugr_values(line: &str) -> Vec<(&str, &str)> {
+    let mut values = Vec::new();
+    let mut rest = line.trim_start();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(char::is_whitespace).unwrap_or((after, "")),
+        };
+        values.push((key.trim(), value));
+        rest = next.trim_start();
+    }
+    values
+}
+
+/// Sorts the stops of a wrapping gradient and closes it at 0 and 1 with
+/// the blend of the last and the first color
+fn wrap(mut stops: Vec<(f64, Color)>) -> Vec<(f64, Color)> {
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if let (Some(&(first, first_color)), Some(&(last, last_color))) = (stops.first(), stops.last())
+    {
+        let gap = 1.0 - last + first;
+        let t = match gap > 0.0 {
+            true => (1.0 - last) / gap,
+            false => 0.0,
+        };
+        let edge = mix(last_color, first_color, t);
+        if first > 0.0 {
+            stops.insert(0, (0.0, edge));
+        }
+        if last < 1.0 {
+            stops.push((1.0, edge));
+        }
+    }
+    stops
+}
+
+/// Parses a hex color like `#ff8000`, optionally after its position
+fn parse_stop(name: &str, line: &str) -> Result<(Option<f64>, Color), String> {
+    let invalid = || format!("Invalid color '{}' in palette '{}'", line, name);
+    let (position, hex) = match line.split_once(char::is_whitespace) {
+        Some((position, hex)) => (Some(position.parse().map_err(|_| invalid())?), hex.trim()),
+        None => (None, line),
+    };
+    let hex = hex.trim_start_matches('#');
+    match (hex.len(), u32::from_str_radix(hex, 16)) {
+        (6, Ok(rgb)) => Ok((
+            position,
+            Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8),
+        )),
+        _ => Err(invalid()),
+    }
+}
+
+/// Color on the Catmull-Rom spline from the second to the third color,
+/// the first and the last color give the slopes
+fn spline(colors: [Color; 4], t: f64) -> Color {
+    let channel = |channel: fn(&Color) -> u8| {
+        let [p0, p1, p2, p3] = colors.map(|c| channel(&c) as f64);
+        let value = 0.5
+            * (2.0 * p1
+                + (p2 - p0) * t
+                + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+                + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t);
+        value.round().clamp(0.0, 255.0) as u8
+    };
+    Color::RGB(channel(|c| c.r), channel(|c| c.g), channel(|c| c.b))
+}
+
+/// Gradients of the built-in schemes green, rainbow, redish and blue
+fn presets() -> &'static [Gradient; 4] {
+    static PRESETS: OnceLock<[Gradient; 4]> = OnceLock::new();
+    PRESETS.get_or_init(|| {
+        let rgb = |position, r, g, b| (position, Color::RGB(r, g, b));
+        let preset = |name, stops| Gradient::new(name, stops, Interpolation::Linear).unwrap();
+        [
+            // black - green - white
+            preset(
+                "green",
+                vec![
+                    rgb(0.0, 0, 0, 0),
+                    rgb(0.0625, 0, 64, 0),
+                    rgb(0.25, 0, 128, 0),
+                    rgb(0.5, 0, 180, 0),
+                    rgb(0.75, 90, 221, 90),
+                    rgb(1.0, 180, 255, 180),
+                ],
+            ),
+            // hues from red to purple
+            preset(
+                "rainbow",
+                vec![
+                    rgb(0.0, 255, 0, 0),
+                    rgb(0.2, 255, 255, 0),
+                    rgb(0.4, 0, 255, 0),
+                    rgb(0.6, 0, 255, 255),
+                    rgb(0.8, 0, 0, 255),
+                    rgb(1.0, 255, 0, 255),
+                ],
+            ),
+            // red - yellow
+            preset(
+                "redish",
+                vec![
+                    rgb(0.0, 0, 0, 0),
+                    rgb(0.125, 128, 0, 0),
+                    rgb(0.25, 180, 0, 0),
+                    rgb(0.5, 255, 0, 0),
+                    rgb(1.0, 255, 255, 0),
+                ],
+            ),
+            // blue - purple
+            preset(
+                "blue",
+                vec![
+                    rgb(0.0, 0, 0, 0),
+                    rgb(1.0 / 12.0, 0, 0, 128),
+                    rgb(1.0 / 6.0, 0, 0, 180),
+                    rgb(1.0 / 3.0, 0, 0, 255),
+                    rgb(1.0, 255, 0, 255),
+                ],
+            ),
+        ]
+    })
+}
+
+/// Registers the gradients of the palette files, Fractint maps and
+/// UltraFractal collections in the directory. The file name without
+/// extension becomes the name of the scheme, the gradients of a
+/// collection keep their titles. Invalid gradients are skipped.
+pub fn load_palettes(dir: &Path) -> Result<Vec<ColorScheme>, String> {
+    let extensions = [PALETTE_EXTENSION, MAP_EXTENSION, UGR_EXTENSION];
+    let mut paths = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read palette directory {:?}: {}", dir, e))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let extension = path.extension().and_then(|ext| ext.to_str());
+            extension.is_some_and(|ext| extensions.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut schemes = Vec::new();
+    for path in &paths {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("Invalid palette file name {:?}", path))?;
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read palette {:?}: {}", path, e))?;
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let gradients = match extension.to_lowercase().as_str() {
+            MAP_EXTENSION => vec![Gradient::parse_map(name, &text)],
+            UGR_EXTENSION => Gradient::parse_ugr(&text),
+            _ => vec![Gradient::parse(name, &text)],
+        };
+        // one broken palette does not keep the others from loading
+        for gradient in gradients {
+            match gradient.and_then(|gradient| register(Box::new(gradient))) {
+                Ok(scheme) => schemes.push(scheme),
+                Err(e) => println!("Skipping palette in {:?}: {}", path, e),
+            }
+        }
+    }
+    Ok(schemes)
+}
+
+/// Colored image, 4 bytes of red, green, blue and alpha per pixel in row order
+pub struct RgbaBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl RgbaBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        RgbaBuffer {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Number of bytes per row
+    pub fn pitch(&self) -> usize {
+        self.width as usize * 4
+    }
 }
 
 /// Converts a HSV color to a SDL Color
@@ -39,80 +519,214 @@ pub fn hsv(h: f64, s: f64, v: f64) -> Color {
     }
 }
 
-/// colors: rainbow
-fn color_rainbow(n: u32, max: u32) -> Color {
-    let v = match n {
-        _ if n == max => 0.0,
-        _ => 1.0,
-    };
+impl ColorScheme {
+    /// Stable name to reference the scheme in saved files
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorScheme::Green => "green",
+            ColorScheme::Rainbow => "rainbow",
+            ColorScheme::Redish => "redish",
+            ColorScheme::Blue => "blue",
+            ColorScheme::Custom(palette) => palette.name(),
+        }
+    }
 
-    hsv(300.0 * (n as f64 / max as f64), 1.0, v)
+    /// Palette drawing the scheme, a preset gradient for the built-in schemes
+    pub fn palette(self) -> &'static dyn Palette {
+        let [green, rainbow, redish, blue] = presets();
+        match self {
+            ColorScheme::Green => green,
+            ColorScheme::Rainbow => rainbow,
+            ColorScheme::Redish => redish,
+            ColorScheme::Blue => blue,
+            ColorScheme::Custom(palette) => palette,
+        }
+    }
 }
 
-/// colors: blue - purple
-fn color_blue(n: u32, max: u32) -> Color {
-    let blue_limit = max / 3;
-    if n < blue_limit {
-        let ratio = n as f64 / blue_limit as f64;
-        let level = (ratio.sqrt() * 255.0) as u8;
+impl std::str::FromStr for ColorScheme {
+    type Err = String;
 
-        Color::RGB(0, 0, level)
-    } else {
-        let v = match n {
-            _ if n == max => 0.0,
-            _ => 1.0,
-        };
-
-        let ratio = (n - blue_limit) as f64 / (max - blue_limit) as f64;
-        hsv(240.0 + 60.0 * ratio, 1.0, v)
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().to_lowercase().as_str() {
+            "green" => Ok(ColorScheme::Green),
+            "rainbow" => Ok(ColorScheme::Rainbow),
+            "redish" => Ok(ColorScheme::Redish),
+            "blue" => Ok(ColorScheme::Blue),
+            other => registered()
+                .into_iter()
+                .find(|scheme| scheme.name().to_lowercase() == other)
+                .ok_or_else(|| format!("Unknown color scheme '{}'", name)),
+        }
     }
 }
 
-/// colors: red - yellow
-fn color_red(n: u32, max: u32) -> Color {
-    let red_limit = max / 2;
-    if n < red_limit {
-        let ratio = n as f64 / red_limit as f64;
-        let level = (ratio.sqrt() * 255.0) as u8;
+pub fn color(color_type: ColorScheme, n: u32, max: u32) -> Color {
+    color_type.palette().color(n, max)
+}
 
-        Color::RGB(level, 0, 0)
-    } else {
-        let v = match n {
-            _ if n == max => 0.0,
-            _ => 1.0,
-        };
+/// Color between a and b, t from 0 at a to 1 at b
+fn mix(a: Color, b: Color, t: f64) -> Color {
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+    Color::RGB(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b))
+}
+
+/// How the iteration counts map onto the palette: the palette is rotated
+/// by the offset and repeated scale times over the iteration range
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mapping {
+    /// fraction of the palette the colors are shifted by
+    pub offset: f64,
+    pub scale: f64,
+}
 
-        let ratio = (n - red_limit) as f64 / (max - red_limit) as f64;
-        hsv(60.0 * ratio, 1.0, v)
+impl Default for Mapping {
+    fn default() -> Self {
+        Mapping {
+            offset: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Mapping {
+    /// Iteration count whose color n gets, the maximum stays inside
+    pub fn map(self, n: u32, max: u32) -> u32 {
+        if n >= max || self == Mapping::default() {
+            return n;
+        }
+        let position = (n as f64 / max as f64 * self.scale + self.offset).rem_euclid(1.0);
+        ((position * max as f64) as u32).min(max - 1)
     }
 }
 
-/// colors: black - green - white
-fn color_green(n: u32, max: u32) -> Color {
-    if n < max {
-        let ratio = n as f64 / (max - 1) as f64;
-        let level = (ratio.sqrt() * 255.0) as u8;
+/// Colors of all iteration counts of a scheme up to a maximum, so
+/// coloring an image looks them up instead of calculating them per pixel
+pub struct ColorTable {
+    scheme: &'static str,
+    max: u32,
+    mapping: Mapping,
+    colors: Vec<Color>,
+}
 
-        let mut rb = 0;
-        if ratio > 0.5 {
-            rb = ((ratio - 0.5) / 0.5 * 180.0) as u8;
+impl ColorTable {
+    pub fn new(scheme: ColorScheme, max: u32, mapping: Mapping) -> Self {
+        ColorTable {
+            scheme: scheme.name(),
+            max,
+            mapping,
+            colors: (0..=max)
+                .map(|n| color(scheme, mapping.map(n, max), max))
+                .collect(),
         }
+    }
 
-        Color::RGB(rb, level, rb)
-    } else {
-        Color::RGB(0, 0, 0)
+    /// Table of the scheme, maximum and mapping, only calculated again
+    /// after one of them changed
+    pub fn shared(scheme: ColorScheme, max: u32, mapping: Mapping) -> Arc<ColorTable> {
+        let mut last = TABLE.lock().unwrap();
+        match &*last {
+            Some(table)
+                if table.scheme == scheme.name()
+                    && table.max == max
+                    && table.mapping == mapping =>
+            {
+                table.clone()
+            }
+            _ => {
+                let table = Arc::new(ColorTable::new(scheme, max, mapping));
+                *last = Some(table.clone());
+                table
+            }
+        }
+    }
+
+    /// Same as `color` with the maximum of the table
+    pub fn color(&self, n: u32) -> Color {
+        self.colors[n.min(self.max) as usize]
+    }
+
+    /// Color for points converging to a finite attractor instead of
+    /// escaping, the inverted scheme color keeps converged regions apart
+    /// from escaped ones
+    pub fn converged(&self, n: u32) -> Color {
+        let c = self.color(n);
+        Color::RGB(255 - c.r, 255 - c.g, 255 - c.b)
+    }
+
+    /// Color for a fractional iteration count, interpolated between the
+    /// colors of the neighboring counts below the maximum
+    pub fn smooth(&self, n: f64) -> Color {
+        let last = self.max.saturating_sub(1) as f64;
+        let n = n.clamp(0.0, last);
+        let below = self.color(n.floor() as u32);
+        let above = self.color(n.ceil() as u32);
+        mix(below, above, n.fract())
     }
 }
 
-pub fn color(color_type: ColorScheme, n: u32, max: u32) -> Color {
-    match color_type {
-        ColorScheme::Green => color_green(n, max),
-        ColorScheme::Rainbow => color_rainbow(n, max),
-        ColorScheme::Redish => color_red(n, max),
-        ColorScheme::Blue => color_blue(n, max),
+/// Gray level for a distance to the set in pixels, black at the boundary
+/// and white from the given distance on
+pub fn color_distance(distance: f64, scale: f64) -> Color {
+    let level = ((distance / scale).clamp(0.0, 1.0).sqrt() * 255.0) as u8;
+    Color::RGB(level, level, level)
+}
+
+/// Color for the closest approach of an orbit to a trap, orbits passing
+/// close to the trap get the bright end of the scheme
+pub fn color_trap(color_type: ColorScheme, distance: f64, range: f64) -> Color {
+    let steps = 1000;
+    let closeness = 1.0 - (distance / range).clamp(0.0, 1.0).sqrt();
+    color(color_type, (closeness * (steps - 1) as f64) as u32, steps)
+}
+
+/// Maps a signed value to a color, negative values are drawn with the
+/// color scheme and positive values fade from black to dark blue.
+/// The value is clamped to [-scale, scale].
+pub fn color_signed(color_type: ColorScheme, value: f64, scale: f64) -> Color {
+    let ratio = (value / scale).clamp(-1.0, 1.0);
+    if ratio < 0.0 {
+        let steps = 1000;
+        color(color_type, (-ratio * (steps - 1) as f64) as u32, steps)
+    } else {
+        Color::RGB(0, 0, (ratio * 128.0) as u8)
     }
 }
 
+/// Color for the continuous potential `ln|z| / 2^n` given as its negative
+/// base 2 logarithm, mapped on a log scale so deep escapes spread over the
+/// same colors as shallow ones
+pub fn color_potential(color_type: ColorScheme, log_potential: f64, max: u32) -> Color {
+    let steps = 1000;
+    let position = log_potential.max(0.0).ln_1p() / (max as f64).ln_1p();
+    color(
+        color_type,
+        (position.min(1.0) * (steps - 1) as f64) as u32,
+        steps,
+    )
+}
+
+/// Color for the angle of the last z of an escaped orbit in radians as hue,
+/// the brightness cycles with the fractional escape count every period
+pub fn color_angle(angle: f64, n: f64, period: f64) -> Color {
+    let hue = angle.to_degrees().rem_euclid(360.0);
+    let wave = (n / period * std::f64::consts::TAU).cos();
+    hsv(hue, 0.8, 0.7 + 0.3 * wave)
+}
+
+/// Color for the atom domain of a pixel, the iteration at which its orbit
+/// came closest to 0, brighter towards the smallest |z| at the nucleus
+pub fn color_atom_domain(period: u32, min_norm: f64) -> Color {
+    let hue = (period as f64 * GOLDEN_ANGLE).rem_euclid(360.0);
+    hsv(hue, 0.75, 1.0 - 0.5 * min_norm.clamp(0.0, 1.0))
+}
+
+/// Darkens a color, factor 1 keeps the color and 0 makes it black
+pub fn shade(c: Color, factor: f64) -> Color {
+    let scale = |v: u8| (v as f64 * factor.clamp(0.0, 1.0)).round() as u8;
+    Color::RGBA(scale(c.r), scale(c.g), scale(c.b), c.a)
+}
+
 // Returns a vector of one color for each given iteration number
 // fn colors(count: u32) -> Vec<Color> {
 //     let mut c: Vec<Color> = Vec::with_capacity((count + 1) as usize);
@@ -123,3 +737,212 @@ pub fn color(color_type: ColorScheme, n: u32, max: u32) -> Color {
 
 //     c
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{PI, TAU};
+
+    #[test]
+    fn test_names() {
+        for &scheme in [
+            ColorScheme::Green,
+            ColorScheme::Rainbow,
+            ColorScheme::Redish,
+            ColorScheme::Blue,
+        ]
+        .iter()
+        {
+            let parsed: ColorScheme = scheme.name().parse().unwrap();
+            assert_eq!(parsed.name(), scheme.name());
+        }
+        assert!("purple".parse::<ColorScheme>().is_err());
+    }
+
+    #[test]
+    fn test_definition() {
+        let text = "spline\n0 #000000\n0.25 #ff8000\n1 #ffffff";
+        let gradient = Gradient::parse("fire", text).unwrap();
+        assert_eq!(gradient.definition(), text);
+        let parsed = Gradient::parse("fire", &gradient.definition()).unwrap();
+        for n in 0..10 {
+            assert_eq!(parsed.color(n, 10), gradient.color(n, 10));
+        }
+        assert!(ColorScheme::Green
+            .palette()
+            .definition()
+            .starts_with("0 #000000\n0.0625 #004000"));
+    }
+
+    #[test]
+    fn test_register() {
+        let gradient = Gradient::parse("test-fire", "#000000\n\n#ff8000\n").unwrap();
+        let scheme = register(Box::new(gradient)).unwrap();
+        let parsed: ColorScheme = "Test-Fire".parse().unwrap();
+        assert_eq!(parsed.name(), "test-fire");
+
+        assert_eq!(color(scheme, 0, 100), Color::RGB(0, 0, 0));
+        assert_eq!(color(scheme, 50, 100), Color::RGB(128, 64, 0));
+        assert_eq!(color(scheme, 100, 100), Color::RGB(0, 0, 0));
+
+        let duplicate = Gradient::parse("green", "#000000\n#ffffff").unwrap();
+        assert!(register(Box::new(duplicate)).is_err());
+        assert!(Gradient::parse("short", "#ffffff").is_err());
+        assert!(Gradient::parse("invalid", "#fff\n#000000").is_err());
+    }
+
+    #[test]
+    fn test_gradient() {
+        let text = "spline\n0 #000000\n0.2 #ff0000\n0.5 #00ff00\n1 #0000ff";
+        let gradient = Gradient::parse("test-stops", text).unwrap();
+        assert_eq!(gradient.interpolation, Interpolation::Spline);
+        assert_eq!(gradient.at(0.2), Color::RGB(255, 0, 0));
+        assert_eq!(gradient.at(0.5), Color::RGB(0, 255, 0));
+        assert_eq!(gradient.at(1.5), Color::RGB(0, 0, 255));
+        assert_ne!(
+            gradient.at(0.35),
+            mix(gradient.at(0.2), gradient.at(0.5), 0.5)
+        );
+        assert!(Gradient::parse("mixed", "0 #000000\n#ffffff").is_err());
+        assert!(Gradient::parse("unordered", "0.5 #000000\n0.2 #ffffff").is_err());
+
+        // the rainbow preset sweeps the hues like hsv
+        for n in [0, 10, 25, 50, 99] {
+            let (a, b) = (
+                color(ColorScheme::Rainbow, n, 100),
+                hsv(3.0 * n as f64, 1.0, 1.0),
+            );
+            let close = |a: u8, b: u8| (a as i32 - b as i32).abs() <= 1;
+            assert!(
+                close(a.r, b.r) && close(a.g, b.g) && close(a.b, b.b),
+                "{}",
+                n
+            );
+        }
+    }
+
+    #[test]
+    fn test_palette_formats() {
+        let map =
+            Gradient::parse_map("test-map", "0 0 0 black\n255 128 0\n  0 0 255 blue\n").unwrap();
+        assert_eq!(map.at(0.5), Color::RGB(255, 128, 0));
+        assert_eq!(map.at(0.25), Color::RGB(128, 64, 0));
+        assert!(Gradient::parse_map("short", "0 0\n0 0 0").is_err());
+
+        let ugr = "first {\ngradient:\n  title=\"Sunset Glow\" smooth=no\n  index=100 color=255\n  \
+                   index=300 color=16711680\nopacity:\n  index=0 opacity=255\n}\n\
+                   second {\ngradient:\n  smooth=yes\n  index=0 color=65280\n  index=399 color=0\n}\n";
+        let gradients: Vec<_> = Gradient::parse_ugr(ugr)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(gradients.len(), 2);
+        let (sunset, second) = (&gradients[0], &gradients[1]);
+        assert_eq!(sunset.name(), "Sunset Glow");
+        assert_eq!(second.name(), "second");
+        assert_eq!(second.interpolation, Interpolation::Spline);
+        // colors are 0xBBGGRR, the ends blend the last and the first color
+        assert_eq!(sunset.at(0.25), Color::RGB(255, 0, 0));
+        assert_eq!(sunset.at(0.75), Color::RGB(0, 0, 255));
+        assert_eq!(sunset.at(0.0), sunset.at(1.0));
+        assert_eq!(sunset.at(0.0), Color::RGB(128, 0, 128));
+        assert_eq!(second.at(0.0), Color::RGB(0, 255, 0));
+        assert!(Gradient::parse_ugr("bad {\ngradient:\n  index=0 color=red\n}")[0].is_err());
+
+        // invalid and duplicate gradients are skipped, the others load
+        let dir = std::env::temp_dir().join("mandelbrust_palettes");
+        fs::create_dir_all(&dir).unwrap();
+        let collection = "empty {\ngradient:\n  smooth=yes\n}\n\
+                          dup {\ngradient:\n  title=\"green\"\n  index=0 color=0\n  index=200 color=255\n}\n\
+                          good {\ngradient:\n  title=\"test-ugr-good\"\n  index=0 color=0\n  index=200 color=255\n}\n";
+        fs::write(dir.join("collection.ugr"), collection).unwrap();
+        let schemes = load_palettes(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<_> = schemes.iter().map(|scheme| scheme.name()).collect();
+        assert_eq!(names, vec!["test-ugr-good"]);
+    }
+
+    #[test]
+    fn test_color_potential() {
+        let max = 1000;
+        assert_eq!(
+            color_potential(ColorScheme::Blue, -1.0, max),
+            color(ColorScheme::Blue, 0, 1000)
+        );
+        assert_eq!(
+            color_potential(ColorScheme::Blue, 1e9, max),
+            color(ColorScheme::Blue, 999, 1000)
+        );
+        // log scale, 10 iterations reach a third of the palette
+        let ten = color_potential(ColorScheme::Rainbow, 10.0, max);
+        assert_eq!(ten, color(ColorScheme::Rainbow, 346, 1000));
+    }
+
+    #[test]
+    fn test_color_angle() {
+        let period = 16.0;
+        assert_eq!(
+            color_angle(0.0, 0.0, period),
+            color_angle(TAU, period, period)
+        );
+        assert_eq!(
+            color_angle(-PI / 2.0, 3.0, period),
+            color_angle(1.5 * PI, 3.0, period)
+        );
+        let bright = color_angle(0.0, 0.0, period);
+        let dark = color_angle(0.0, period / 2.0, period);
+        assert!(bright.r > dark.r);
+    }
+
+    #[test]
+    fn test_color_atom_domain() {
+        let nucleus = color_atom_domain(3, 0.0);
+        assert_ne!(nucleus, color_atom_domain(4, 0.0));
+        assert_eq!(color_atom_domain(1, 0.0), hsv(GOLDEN_ANGLE, 0.75, 1.0));
+        // dimmer away from the nucleus, but not black
+        let edge = color_atom_domain(3, 5.0);
+        assert_eq!(edge, color_atom_domain(3, 1.0));
+        let brightness = |c: Color| c.r as u32 + c.g as u32 + c.b as u32;
+        assert!(brightness(edge) < brightness(nucleus));
+    }
+
+    #[test]
+    fn test_color_smooth() {
+        let scheme = ColorScheme::Rainbow;
+        let table = ColorTable::new(scheme, 100, Mapping::default());
+        assert_eq!(table.smooth(10.0), color(scheme, 10, 100));
+        assert_eq!(table.smooth(150.0), color(scheme, 99, 100));
+
+        let between = table.smooth(10.5);
+        let (below, above) = (color(scheme, 10, 100), color(scheme, 11, 100));
+        assert!(between.r >= below.r.min(above.r) && between.r <= below.r.max(above.r));
+        assert!(between.g >= below.g.min(above.g) && between.g <= below.g.max(above.g));
+    }
+
+    #[test]
+    fn test_color_table() {
+        let table = ColorTable::new(ColorScheme::Blue, 100, Mapping::default());
+        for n in [0, 37, 99, 100] {
+            assert_eq!(table.color(n), color(ColorScheme::Blue, n, 100));
+        }
+        assert_eq!(table.color(150), color(ColorScheme::Blue, 100, 100));
+        let c = color(ColorScheme::Blue, 37, 100);
+        assert_eq!(
+            table.converged(37),
+            Color::RGB(255 - c.r, 255 - c.g, 255 - c.b)
+        );
+
+        let shared = ColorTable::shared(ColorScheme::Green, 60, Mapping::default());
+        assert_eq!((shared.scheme, shared.max), ("green", 60));
+
+        // shifted by a quarter and repeated twice over the iterations
+        let mapping = Mapping {
+            offset: 0.25,
+            scale: 2.0,
+        };
+        let table = ColorTable::new(ColorScheme::Blue, 100, mapping);
+        assert_eq!(table.color(0), color(ColorScheme::Blue, 25, 100));
+        assert_eq!(table.color(40), color(ColorScheme::Blue, 5, 100));
+        assert_eq!(table.color(100), color(ColorScheme::Blue, 100, 100));
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Directory of the power supplies on Linux
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+/// Time between checks of the power supply
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Returns true if a battery in the power supply directory is discharging,
+/// false if there is none or the state is unknown
+pub fn on_battery(power_supply: &Path) -> bool {
+    let read = |path: &Path, name: &str| {
+        fs::read_to_string(path.join(name))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    fs::read_dir(power_supply).is_ok_and(|entries| {
+        entries.filter_map(Result::ok).any(|entry| {
+            let path = entry.path();
+            read(&path, "type") == "Battery" && read(&path, "status") == "Discharging"
+        })
+    })
+}
+
+/// Tracks whether rendering is throttled to save power
+pub struct PowerSaving {
+    /// set by the user, replaces the detection
+    forced: Option<bool>,
+    on_battery: bool,
+    last_check: Option<SystemTime>,
+}
+
+impl Default for PowerSaving {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PowerSaving {
+    pub fn new() -> Self {
+        PowerSaving {
+            forced: None,
+            on_battery: false,
+            last_check: None,
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.forced.unwrap_or(self.on_battery)
+    }
+
+    /// Checks the power supply at most once per interval,
+    /// returns true if power saving was switched on or off
+    pub fn poll(&mut self) -> bool {
+        let due = self
+            .last_check
+            .is_none_or(|t| t.elapsed().map_or(true, |e| e >= CHECK_INTERVAL));
+        if !due || self.forced.is_some() {
+            return false;
+        }
+
+        self.last_check = Some(SystemTime::now());
+        let active = self.active();
+        self.on_battery = on_battery(Path::new(POWER_SUPPLY));
+        self.active() != active
+    }
+
+    /// Overrides the detection with the opposite of the current state
+    pub fn toggle(&mut self) {
+        self.forced = Some(!self.active());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_on_battery() {
+        let dir = std::env::temp_dir().join(format!("mandelbrust-power-{}", std::process::id()));
+        let supply = |name: &str, kind: &str, status: &str| {
+            let path = dir.join(name);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("type"), format!("{}\n", kind)).unwrap();
+            fs::write(path.join("status"), format!("{}\n", status)).unwrap();
+        };
+
+        assert!(!on_battery(&dir));
+        supply("AC", "Mains", "");
+        supply("BAT0", "Battery", "Charging");
+        assert!(!on_battery(&dir));
+        supply("BAT0", "Battery", "Discharging");
+        assert!(on_battery(&dir));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+use crate::fractal::Outcome;
+use crate::mandelbrot::{self, RenderContext};
+use crate::timing;
+use crate::types::MandelImage;
+use crate::types::Transform;
+
+use std::ops::{Add, Mul};
+use std::time::SystemTime;
+
+/// Julia constant of the default quaternion Julia set
+pub const DEFAULT_C: Quaternion = Quaternion {
+    a: -0.2,
+    b: 0.8,
+    c: 0.0,
+    d: 0.0,
+};
+
+/// Quaternion a + bi + cj + dk
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Quaternion {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+}
+
+impl Quaternion {
+    pub fn new(a: f64, b: f64, c: f64, d: f64) -> Self {
+        Quaternion { a, b, c, d }
+    }
+
+    pub fn norm_sqr(&self) -> f64 {
+        self.a * self.a + self.b * self.b + self.c * self.c + self.d * self.d
+    }
+
+    /// Square, cheaper than the general multiplication
+    pub fn square(&self) -> Self {
+        Quaternion {
+            a: self.a * self.a - self.b * self.b - self.c * self.c - self.d * self.d,
+            b: 2.0 * self.a * self.b,
+            c: 2.0 * self.a * self.c,
+            d: 2.0 * self.a * self.d,
+        }
+    }
+}
+
+impl Add for Quaternion {
+    type Output = Quaternion;
+
+    fn add(self, q: Quaternion) -> Quaternion {
+        Quaternion::new(self.a + q.a, self.b + q.b, self.c + q.c, self.d + q.d)
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, q: Quaternion) -> Quaternion {
+        Quaternion {
+            a: self.a * q.a - self.b * q.b - self.c * q.c - self.d * q.d,
+            b: self.a * q.b + self.b * q.a + self.c * q.d - self.d * q.c,
+            c: self.a * q.c - self.b * q.d + self.c * q.a + self.d * q.b,
+            d: self.a * q.d + self.b * q.c - self.c * q.b + self.d * q.a,
+        }
+    }
+}
+
+/// Calculates the number of iterations for q to escape the
+/// quaternion Julia set of c
+pub fn julia(q: Quaternion, c: Quaternion, max_iter: u32) -> u32 {
+    let mut q = q;
+    let mut iter = 0;
+
+    while q.norm_sqr() < 4.0 && iter < max_iter {
+        q = q.square() + c;
+        iter += 1;
+    }
+
+    iter
+}
+
+/// multithreaded image generation of the 2D slice through the
+/// quaternion Julia set at the fixed coordinates of the transform
+pub fn generate_image_thread(
+    context: &mut RenderContext,
+    transform: &Transform,
+    c: Quaternion,
+    image: &mut MandelImage,
+) {
+    let start = SystemTime::now();
+
+    let trans = transform.clone();
+    let max_iter = image.max_iterations;
+
+    let thread_count = mandelbrot::generate_tiles(
+        &mut context.outcomes,
+        image.width,
+        image.height,
+        move |x, y| Outcome::new(julia(trans.pos_to_quaternion(x, y), c, max_iter), false),
+        |tile, outcomes| image.set_iterations(tile, outcomes),
+    );
+
+    timing::record(
+        "generate",
+        start,
+        format_args!(
+            "Generated quaternion Julia slice {:?} with {} threads",
+            transform.slice(),
+            thread_count
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_square() {
+        let q = Quaternion::new(0.5, -1.0, 0.25, 2.0);
+        assert_eq!(q.square(), q * q);
+
+        let i = Quaternion::new(0.0, 1.0, 0.0, 0.0);
+        let j = Quaternion::new(0.0, 0.0, 1.0, 0.0);
+        assert_eq!(i * j, Quaternion::new(0.0, 0.0, 0.0, 1.0));
+        assert_eq!(j * i, Quaternion::new(0.0, 0.0, 0.0, -1.0));
+    }
+}
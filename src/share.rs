@@ -0,0 +1,277 @@
+use crate::deep::FixedComplex;
+use crate::options;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Most iterations a host may ask its viewers to render
+const MAX_ITERATIONS: u32 = 1 << 24;
+
+/// Longest incomplete message a viewer buffers before giving up on the host
+const MAX_PENDING: usize = 64 * 1024;
+
+/// View broadcast by the host, each viewer renders it at its own size
+#[derive(Clone, Debug)]
+pub struct SharedView {
+    pub center: FixedComplex,
+    pub zoom: f64,
+    pub iterations: u32,
+}
+
+impl SharedView {
+    /// One line like "view -0.75,0.1 1e3 450", the center with all digits
+    pub fn to_line(&self) -> String {
+        format!(
+            "view {},{} {:e} {}\n",
+            self.center.re, self.center.im, self.zoom, self.iterations
+        )
+    }
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["view", center, zoom, iterations] => Ok(SharedView {
+                center: options::parse_center(center)?,
+                zoom: options::parse_zoom(zoom)?,
+                iterations: parse_iterations(iterations)?,
+            }),
+            _ => Err(format!("Invalid view message '{}'", line.trim())),
+        }
+    }
+}
+
+fn parse_iterations(value: &str) -> Result<u32, String> {
+    let iterations = value
+        .parse()
+        .map_err(|_| format!("Invalid iterations '{}'", value))?;
+    match options::validate_iterations(iterations)? > MAX_ITERATIONS {
+        true => Err(format!(
+            "Shared iterations {} exceed {}",
+            iterations, MAX_ITERATIONS
+        )),
+        false => Ok(iterations),
+    }
+}
+
+/// Role of this instance in a shared exploration
+pub enum Session {
+    Host(Host),
+    Viewer(Viewer),
+}
+
+impl Session {
+    /// Hosts or joins a session as given by the options, None if neither
+    pub fn open(host: Option<&str>, join: Option<&str>) -> Result<Option<Self>, String> {
+        match (host, join) {
+            (Some(address), None) => Ok(Some(Session::Host(Host::bind(address)?))),
+            (None, Some(address)) => Ok(Some(Session::Viewer(Viewer::connect(address)?))),
+            (None, None) => Ok(None),
+            (Some(_), Some(_)) => Err("Use either --host or --join".to_string()),
+        }
+    }
+}
+
+/// Accepts viewers and sends them every change of the view
+pub struct Host {
+    listener: TcpListener,
+    viewers: Vec<TcpStream>,
+    /// last message sent, also greets new viewers
+    last: Option<String>,
+}
+
+impl Host {
+    pub fn bind(address: &str) -> Result<Self, String> {
+        let listener = TcpListener::bind(address)
+            .map_err(|e| format!("Failed to host on {}: {}", address, e))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to host on {}: {}", address, e))?;
+        println!("Hosting views on {}", address);
+        Ok(Host {
+            listener,
+            viewers: Vec::new(),
+            last: None,
+        })
+    }
+
+    /// Accepts waiting viewers and sends the view if it changed, viewers
+    /// that disconnected or don't keep up with the messages are dropped
+    pub fn broadcast(&mut self, view: &SharedView) {
+        let line = view.to_line();
+        let changed = self.last.as_ref() != Some(&line);
+
+        while let Ok((mut stream, address)) = self.listener.accept() {
+            println!("Viewer joined from {}", address);
+            if stream.set_nonblocking(true).is_err() {
+                continue;
+            }
+            if changed || stream.write_all(line.as_bytes()).is_ok() {
+                self.viewers.push(stream);
+            }
+        }
+
+        if changed {
+            self.viewers
+                .retain_mut(|stream| stream.write_all(line.as_bytes()).is_ok());
+            self.last = Some(line);
+        }
+    }
+
+    pub fn viewer_count(&self) -> usize {
+        self.viewers.len()
+    }
+}
+
+/// Connection to a host, receiving its views
+pub struct Viewer {
+    stream: Option<TcpStream>,
+    /// bytes of an incomplete message
+    pending: Vec<u8>,
+}
+
+impl Viewer {
+    pub fn connect(address: &str) -> Result<Self, String> {
+        let stream = TcpStream::connect(address)
+            .map_err(|e| format!("Failed to join {}: {}", address, e))?;
+        stream
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to join {}: {}", address, e))?;
+        println!("Joined {}", address);
+        Ok(Viewer {
+            stream: Some(stream),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Returns the latest complete view received since the last call
+    pub fn poll(&mut self) -> Option<SharedView> {
+        let stream = self.stream.as_mut()?;
+        let mut buffer = [0; 4096];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => {
+                    println!("Host closed the session");
+                    self.stream = None;
+                    break;
+                }
+                Ok(n) if self.pending.len() + n > MAX_PENDING => {
+                    println!("Host sent an overlong message");
+                    self.stream = None;
+                    self.pending.clear();
+                    break;
+                }
+                Ok(n) => self.pending.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    println!("Lost connection to host: {}", e);
+                    self.stream = None;
+                    break;
+                }
+            }
+        }
+
+        let end = self.pending.iter().rposition(|&b| b == b'\n')?;
+        let lines: Vec<u8> = self.pending.drain(..=end).collect();
+        String::from_utf8_lossy(&lines)
+            .lines()
+            .filter_map(|line| match SharedView::parse(line) {
+                Ok(view) => Some(view),
+                Err(e) => {
+                    println!("{}", e);
+                    None
+                }
+            })
+            .next_back()
+    }
+
+    pub fn connected(&self) -> bool {
+        self.stream.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deep::Fixed;
+
+    #[test]
+    fn test_session() {
+        let center = FixedComplex::new(
+            Fixed::parse("-0.7436438870371587").unwrap(),
+            Fixed::parse("0.1318259042053988").unwrap(),
+        );
+        let view = SharedView {
+            center,
+            zoom: 2.5e9,
+            iterations: 1350,
+        };
+        // the center is exact, parsing may only add fraction bits
+        let line = view.to_line();
+        assert_eq!(SharedView::parse(&line).unwrap().to_line(), line);
+        assert!(SharedView::parse("view 0,0 1").is_err());
+        assert!(SharedView::parse("view 0,0 1 0").is_err());
+        assert!(SharedView::parse("view 0,0 1 4000000000").is_err());
+        assert!(SharedView::parse("view 1e9999999,0 1 100").is_err());
+
+        let mut host = Host::bind("127.0.0.1:0").unwrap();
+        let address = host.listener.local_addr().unwrap().to_string();
+        let mut viewer = Viewer::connect(&address).unwrap();
+
+        // the new viewer gets the current view, later only changes
+        let mut received = None;
+        for _ in 0..200 {
+            host.broadcast(&view);
+            received = received.or_else(|| viewer.poll());
+            if received.is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(received.map(|view| view.to_line()), Some(line));
+        assert_eq!(host.viewer_count(), 1);
+
+        let zoomed = SharedView { zoom: 5e9, ..view };
+        host.broadcast(&zoomed);
+        host.broadcast(&zoomed);
+        let mut received = None;
+        for _ in 0..200 {
+            received = viewer.poll();
+            if received.is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(received.map(|view| view.zoom), Some(5e9));
+        assert!(viewer.poll().is_none());
+
+        drop(host);
+        for _ in 0..200 {
+            viewer.poll();
+            if !viewer.connected() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert!(!viewer.connected());
+    }
+
+    #[test]
+    fn test_overlong_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let mut viewer = Viewer::connect(&address).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(&[b'x'; MAX_PENDING + 1]).unwrap();
+
+        for _ in 0..200 {
+            viewer.poll();
+            if !viewer.connected() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert!(!viewer.connected());
+        assert!(viewer.pending.is_empty());
+    }
+}
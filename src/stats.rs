@@ -0,0 +1,81 @@
+use crate::types::Transform;
+
+use num::complex::Complex;
+
+use std::time::Duration;
+
+/// Totals of the current session
+#[derive(Default)]
+pub struct SessionStats {
+    pub renders: u32,
+    pub compute_time: Duration,
+    pub deepest_zoom: f64,
+    pub max_iterations: u32,
+    /// Centers of all rendered views, consecutive renders of the same view are counted once
+    pub locations: Vec<Complex<f64>>,
+}
+
+impl SessionStats {
+    /// Adds a finished render of the transform
+    pub fn record(&mut self, transform: &Transform, max_iterations: u32, elapsed: Duration) {
+        self.renders += 1;
+        self.compute_time += elapsed;
+        self.deepest_zoom = self.deepest_zoom.max(transform.zoom_factor());
+        self.max_iterations = self.max_iterations.max(max_iterations);
+
+        let center = transform.center();
+        if self.locations.last() != Some(&center) {
+            self.locations.push(center);
+        }
+    }
+
+    /// One line summary for the window title
+    pub fn summary(&self) -> String {
+        format!(
+            "{} renders in {:.1} s, deepest zoom {:.3e}, {} iterations, {} locations",
+            self.renders,
+            self.compute_time.as_secs_f64(),
+            self.deepest_zoom,
+            self.max_iterations,
+            self.locations.len()
+        )
+    }
+
+    pub fn print(&self) {
+        println!("Renders: {}", self.renders);
+        println!("Compute time: {:?}", self.compute_time);
+        println!("Deepest zoom: {:e}", self.deepest_zoom);
+        println!("Maximum iterations: {}", self.max_iterations);
+        println!("Locations visited: {}", self.locations.len());
+        for z in self.locations.iter().rev().take(5) {
+            println!("  [{}, {}i]", z.re, z.im);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut stats = SessionStats::default();
+        let mut transform = Transform::new((200, 100));
+        stats.record(&transform, 100, Duration::from_millis(5));
+        stats.record(&transform, 50, Duration::from_millis(5));
+
+        transform.zoom(4.0);
+        transform.center_at(&Complex::new(-1.0, 0.5));
+        stats.record(&transform, 80, Duration::from_millis(5));
+
+        assert_eq!(stats.renders, 3);
+        assert_eq!(stats.compute_time, Duration::from_millis(15));
+        assert_eq!(stats.deepest_zoom, 4.0);
+        assert_eq!(stats.max_iterations, 100);
+        assert_eq!(stats.locations.len(), 2);
+        assert_eq!(
+            stats.summary(),
+            "3 renders in 0.0 s, deepest zoom 4.000e0, 100 iterations, 2 locations"
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Times kept per stage for the rolling statistics
+const WINDOW: usize = 60;
+
+/// Stage of the main loop iterations, it gives the frame rate
+pub const FRAME: &str = "frame";
+
+/// Recent times of every stage, in the order the stages were first recorded
+static STAGES: Mutex<Vec<StageTimes>> = Mutex::new(Vec::new());
+
+/// Whether every recorded time is printed
+static LOG: AtomicBool = AtomicBool::new(true);
+
+struct StageTimes {
+    stage: &'static str,
+    times: VecDeque<Duration>,
+}
+
+/// Rolling statistics of the last recorded times of a stage
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub stage: &'static str,
+    pub count: usize,
+    pub last: Duration,
+    pub mean: Duration,
+    pub max: Duration,
+}
+
+impl Summary {
+    /// Runs per second at the mean time, the frame rate for `FRAME`
+    pub fn rate(&self) -> f64 {
+        match self.mean.is_zero() {
+            true => 0.0,
+            false => 1.0 / self.mean.as_secs_f64(),
+        }
+    }
+}
+
+/// Turns printing every recorded time on or off
+pub fn set_logging(on: bool) {
+    LOG.store(on, Ordering::Relaxed);
+}
+
+/// Records the time since start for the stage and prints it after the
+/// message, e.g. "Equalized image in: 1.2ms". Returns the time.
+pub fn record(stage: &'static str, start: SystemTime, message: fmt::Arguments) -> Duration {
+    let elapsed = start.elapsed().unwrap_or_default();
+    if LOG.load(Ordering::Relaxed) {
+        println!("{} in: {:?}", message, elapsed);
+    }
+    add(stage, elapsed);
+    elapsed
+}
+
+/// Adds a time of the stage to the statistics without printing it
+pub fn add(stage: &'static str, elapsed: Duration) {
+    let mut stages = STAGES.lock().unwrap();
+    let index = match stages.iter().position(|times| times.stage == stage) {
+        Some(index) => index,
+        None => {
+            stages.push(StageTimes {
+                stage,
+                times: VecDeque::with_capacity(WINDOW),
+            });
+            stages.len() - 1
+        }
+    };
+    let times = &mut stages[index].times;
+    if times.len() == WINDOW {
+        times.pop_front();
+    }
+    times.push_back(elapsed);
+}
+
+/// Statistics of the stage, None if it was never recorded
+pub fn summary(stage: &str) -> Option<Summary> {
+    let stages = STAGES.lock().unwrap();
+    let times = stages.iter().find(|times| times.stage == stage)?;
+    Some(summarize(times))
+}
+
+fn summarize(times: &StageTimes) -> Summary {
+    let count = times.times.len();
+    Summary {
+        stage: times.stage,
+        count,
+        last: times.times.back().copied().unwrap_or_default(),
+        mean: times.times.iter().sum::<Duration>() / count.max(1) as u32,
+        max: times.times.iter().max().copied().unwrap_or_default(),
+    }
+}
+
+/// Prints the statistics of all stages
+pub fn print() {
+    let stages = STAGES.lock().unwrap();
+    println!("Timings of the last {} runs:", WINDOW);
+    for summary in stages.iter().map(summarize) {
+        println!(
+            "  {:<10} last {:>12?} mean {:>12?} max {:>12?}",
+            summary.stage, summary.last, summary.mean, summary.max
+        );
+        if summary.stage == FRAME {
+            println!("  {:<10} {:.1} frames per second", "", summary.rate());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        assert!(summary("test-missing").is_none());
+        for ms in 1..=WINDOW as u64 + 2 {
+            add("test-stage", Duration::from_millis(ms));
+        }
+        let summary = summary("test-stage").unwrap();
+        assert_eq!(summary.count, WINDOW);
+        assert_eq!(summary.last, Duration::from_millis(WINDOW as u64 + 2));
+        assert_eq!(summary.max, summary.last);
+        // the two oldest times dropped out of the window
+        assert_eq!(summary.mean, Duration::from_micros(32_500));
+        assert!((summary.rate() - 1000.0 / 32.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,76 @@
+use num::complex::Complex;
+
+/// Radius of the circle trap
+const CIRCLE_RADIUS: f64 = 0.5;
+
+/// Shape the orbit distance is measured to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TrapShape {
+    Point,
+    /// horizontal line through the center
+    Line,
+    /// circle around the center
+    Circle,
+}
+
+/// Orbit trap, pixels are colored by the closest approach of their orbit
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Trap {
+    pub shape: TrapShape,
+    pub center: Complex<f64>,
+}
+
+impl Trap {
+    pub fn new(shape: TrapShape, center: Complex<f64>) -> Self {
+        Trap { shape, center }
+    }
+
+    /// Distance of z to the trap
+    pub fn distance(&self, z: Complex<f64>) -> f64 {
+        match self.shape {
+            TrapShape::Point => (z - self.center).norm(),
+            TrapShape::Line => (z.im - self.center.im).abs(),
+            TrapShape::Circle => ((z - self.center).norm() - CIRCLE_RADIUS).abs(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.shape {
+            TrapShape::Point => "point",
+            TrapShape::Line => "line",
+            TrapShape::Circle => "circle",
+        }
+    }
+}
+
+/// Selects the next trap shape at the same center, None turns trap coloring off
+pub fn next_trap(trap: Option<Trap>) -> Option<Trap> {
+    match trap {
+        None => Some(Trap::new(TrapShape::Point, Complex::new(0.0, 0.0))),
+        Some(trap) => match trap.shape {
+            TrapShape::Point => Some(Trap::new(TrapShape::Line, trap.center)),
+            TrapShape::Line => Some(Trap::new(TrapShape::Circle, trap.center)),
+            TrapShape::Circle => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance() {
+        let center = Complex::new(1.0, 1.0);
+        let z = Complex::new(1.0, 3.0);
+        assert_eq!(Trap::new(TrapShape::Point, center).distance(z), 2.0);
+        assert_eq!(Trap::new(TrapShape::Line, center).distance(z), 2.0);
+        assert_eq!(Trap::new(TrapShape::Circle, center).distance(z), 1.5);
+
+        let mut trap = None;
+        for _ in 0..4 {
+            trap = next_trap(trap);
+        }
+        assert_eq!(trap, None);
+    }
+}
@@ -1,57 +1,251 @@
+use crate::deep::{self, FixedComplex};
+use crate::fractal::Outcome;
+use crate::quaternion::Quaternion;
+use crate::timing;
+
 use num::complex::Complex;
 
-use std::ops::{Deref, DerefMut};
+use std::ops::Range;
 use std::time::SystemTime;
 
-/// Transforms to/from pixels and complex numbers
+/// Conformal map applied to the view plane before iterating
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlaneMap {
+    Identity,
+    /// c -> 1/c, shows the set inside-out
+    Inverse,
+}
+
+impl PlaneMap {
+    /// Maps a point of the view plane to the parameter plane
+    pub fn apply(self, z: Complex<f64>) -> Complex<f64> {
+        match self {
+            PlaneMap::Identity => z,
+            PlaneMap::Inverse => z.inv(),
+        }
+    }
+
+    /// Maps a point of the parameter plane back to the view plane
+    pub fn _invert(self, c: Complex<f64>) -> Complex<f64> {
+        match self {
+            PlaneMap::Identity => c,
+            PlaneMap::Inverse => c.inv(),
+        }
+    }
+}
+
+/// Layout of the pixels on the view plane
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Flat,
+    /// log-polar strip around the view center for zoom videos, x is the angle
+    /// and y the logarithm of the radius, starting at the corners of the flat
+    /// view and shrinking by the same factor per row as the pixels are wide
+    Exponential,
+}
+
+/// Region of the complex plane shown in a window, the visible width
+/// follows from the aspect ratio of the window
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct View {
+    pub center: Complex<f64>,
+    /// visible height in complex units
+    pub height: f64,
+}
+
+/// Largest offset in pixels between a row and the reflection of another
+/// row for the two to be treated as mirror images
+const MIRROR_TOLERANCE: f64 = 1e-3;
+
+/// The whole Mandelbrot set, zoom factors are relative to its height
+pub const DEFAULT_VIEW: View = View {
+    center: Complex { re: -0.75, im: 0.0 },
+    height: 2.8,
+};
+
+/// Transforms to/from pixels and complex numbers. The view center is
+/// relative to an origin kept in arbitrary precision, so deep zooms
+/// don't run out of f64 precision for the view position.
 #[derive(Clone)]
 pub struct Transform {
-    x: f64,
-    y: f64,
+    /// view center relative to the origin, at the middle of the window
+    center: Complex<f64>,
+    /// pixels per complex unit
     scale: f64,
+    origin: FixedComplex,
+    /// origin rounded to f64
+    origin_f64: Complex<f64>,
     window_size: (u32, u32),
+    /// fixed j and k coordinates of 2D slices through 4D space
+    slice: (f64, f64),
+    map: PlaneMap,
+    projection: Projection,
+    /// view restored by reset
+    view: View,
 }
 
 impl Transform {
     pub fn new(window_size: (u32, u32)) -> Self {
         let mut t = Transform {
+            center: Complex::new(0.0, 0.0),
             scale: 1.0,
-            x: 0.0,
-            y: 0.0,
+            origin: FixedComplex::zero(deep::precision_bits(1.0)),
+            origin_f64: Complex::new(0.0, 0.0),
             window_size,
+            slice: (0.0, 0.0),
+            map: PlaneMap::Identity,
+            projection: Projection::Flat,
+            view: DEFAULT_VIEW,
         };
         t.reset();
         t
     }
 
+    /// Returns a copy for another window size, keeping the center,
+    /// scale is the number of pixels per unit
+    pub fn resized(&self, window_size: (u32, u32), scale: f64) -> Self {
+        let mut t = self.clone();
+        t.window_size = window_size;
+        t.scale = scale;
+        t
+    }
+
+    /// Returns a copy showing only rows top to top + rows of the flat
+    /// view, row y of the band is row top + y of this view
+    pub fn band(&self, top: i32, rows: u32) -> Self {
+        let mut t = self.clone();
+        let (width, height) = self.window_size;
+        let shift = (height as f64 - rows as f64) / 2.0 - top as f64;
+        t.window_size = (width, rows);
+        t.center = self.center + Complex::new(0.0, shift / self.scale);
+        t
+    }
+
     pub fn reset(&mut self) {
-        self.scale = self.window_size.0 as f64 * 0.28;
-        self.x = self.window_size.0 as f64 * 0.7;
-        self.y = self.window_size.1 as f64 * 0.5;
+        self.scale = self.window_size.1 as f64 / self.view.height;
+        self.slice = (0.0, 0.0);
+        let center = self.view.center;
+        self.center_at(&center);
+    }
+
+    /// Replaces the view restored by reset and resets to it
+    pub fn set_view(&mut self, view: View) {
+        self.view = view;
+        self.reset();
+    }
+
+    /// Maps a pixel to the view plane, before the plane map is applied
+    pub fn pos_to_plane(&self, x: i32, y: i32) -> Complex<f64> {
+        self.origin_f64 + self.pos_to_delta(x, y)
+    }
+
+    /// Offset of a pixel from the origin in the view plane
+    pub fn pos_to_delta(&self, x: i32, y: i32) -> Complex<f64> {
+        self.subpixel_delta(x as f64, y as f64)
+    }
+
+    /// Offset of a position between pixels from the origin, pixel
+    /// centers are at whole numbers
+    fn subpixel_delta(&self, x: f64, y: f64) -> Complex<f64> {
+        match self.projection {
+            Projection::Flat => self.flat_delta(x, y),
+            Projection::Exponential => {
+                let width = self.window_size.0 as f64;
+                let step = 2.0 * std::f64::consts::PI / width;
+                let radius = self.corner_radius() * (-step * y).exp();
+                self.center + Complex::from_polar(&radius, &(step * x))
+            }
+        }
+    }
+
+    /// Offset of a position from the origin without the projection
+    fn flat_delta(&self, x: f64, y: f64) -> Complex<f64> {
+        let (width, height) = self.window_size;
+        self.center
+            + Complex::new(
+                (x - width as f64 / 2.0) / self.scale,
+                (height as f64 / 2.0 - y) / self.scale,
+            )
+    }
+
+    /// Distance from the center to the corners of the flat view
+    fn corner_radius(&self) -> f64 {
+        let (width, height) = self.window_size;
+        (width as f64).hypot(height as f64) / 2.0 / self.scale
+    }
+
+    /// Returns the sum of the indices of rows mirrored about the real axis,
+    /// row y shows the conjugates of row axis - y. None if the rows do not
+    /// line up with their reflections or the projection is not flat,
+    /// both plane maps commute with the conjugation
+    pub fn mirror_axis(&self) -> Option<i32> {
+        if self.projection != Projection::Flat {
+            return None;
+        }
+        let im = self.origin_f64.im + self.center.im;
+        let axis = self.window_size.1 as f64 + 2.0 * im * self.scale;
+        match (axis - axis.round()).abs() < MIRROR_TOLERANCE && axis.abs() < i32::MAX as f64 {
+            true => Some(axis.round() as i32),
+            false => None,
+        }
+    }
+
+    /// Whole pixels the view moved since the previous transform, pixel
+    /// (x, y) shows pixel (x + dx, y + dy) of the previous view. None if
+    /// anything but the center changed or the move is not whole pixels.
+    pub fn pan_offset(&self, previous: &Transform) -> Option<(i32, i32)> {
+        let same_view = self.scale == previous.scale
+            && self.window_size == previous.window_size
+            && self.slice == previous.slice
+            && self.map == previous.map
+            && self.projection == Projection::Flat
+            && previous.projection == Projection::Flat;
+        if !same_view {
+            return None;
+        }
+        let moved = (self.origin.offset_from(&previous.origin) + self.center - previous.center)
+            * self.scale;
+        let (dx, dy) = (moved.re, -moved.im);
+        let whole = (dx - dx.round()).abs() < MIRROR_TOLERANCE
+            && (dy - dy.round()).abs() < MIRROR_TOLERANCE;
+        let (width, height) = self.window_size;
+        match whole && dx.abs() < width as f64 && dy.abs() < height as f64 {
+            true => Some((dx.round() as i32, dy.round() as i32)),
+            false => None,
+        }
     }
 
     pub fn pos_to_complex(&self, x: i32, y: i32) -> Complex<f64> {
-        Complex::new(
-            (x as f64 - self.x) / self.scale,
-            ((self.window_size.1 as f64 - y as f64) - self.y) / self.scale,
-        )
+        self.map.apply(self.pos_to_plane(x, y))
+    }
+
+    /// Maps a position between pixels to the parameter plane
+    pub fn subpixel_to_complex(&self, x: f64, y: f64) -> Complex<f64> {
+        self.map.apply(self.origin_f64 + self.subpixel_delta(x, y))
+    }
+
+    /// Maps a pixel to a quaternion in the slice plane
+    pub fn pos_to_quaternion(&self, x: i32, y: i32) -> Quaternion {
+        let z = self.pos_to_complex(x, y);
+        Quaternion::new(z.re, z.im, self.slice.0, self.slice.1)
     }
 
     pub fn _complex_to_point(&self, z: Complex<f64>) -> (i32, i32) {
+        let z = self.map._invert(z) - self.origin_f64 - self.center;
+        let (width, height) = self.window_size;
         (
-            (z.re * self.scale + self.x) as i32,
-            -(z.im * self.scale + self.y) as i32 + self.window_size.1 as i32,
+            (z.re * self.scale + width as f64 / 2.0) as i32,
+            height as i32 - (z.im * self.scale + height as f64 / 2.0) as i32,
         )
     }
 
     pub fn zoom(&mut self, factor: f64) {
-        let z_center = self.pos_to_complex(
-            (self.window_size.0 / 2) as i32,
-            (self.window_size.1 / 2) as i32,
+        let z_center = self.flat_delta(
+            (self.window_size.0 / 2) as f64,
+            (self.window_size.1 / 2) as f64,
         );
         self.scale *= factor;
-        self.x = self.window_size.0 as f64 / 2.0 - z_center.re * self.scale;
-        self.y = self.window_size.1 as f64 / 2.0 - z_center.im * self.scale;
+        self.center = z_center;
 
         if self.zoom_factor() > 1_000_000.0 {
             println!("Zoom: {:e}", self.zoom_factor());
@@ -60,86 +254,558 @@ impl Transform {
         }
     }
 
+    /// Height of the default view divided by the visible height
     pub fn zoom_factor(&self) -> f64 {
-        self.scale / (self.window_size.0 as f64 * 0.28)
+        DEFAULT_VIEW.height * self.scale / self.window_size.1 as f64
+    }
+
+    pub fn center(&self) -> Complex<f64> {
+        self.origin_f64 + self.center
+    }
+
+    pub fn origin(&self) -> &FixedComplex {
+        &self.origin
+    }
+
+    /// Center of the view in the precision needed for the current scale
+    pub fn precise_center(&self) -> FixedComplex {
+        let bits = deep::precision_bits(1.0 / self.pixel_size());
+        self.origin.add_f64(&self.center, bits)
+    }
+
+    fn set_origin(&mut self, origin: FixedComplex) {
+        self.origin_f64 = origin.to_f64();
+        self.origin = origin;
     }
 
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Size of the smallest pixel in complex units
+    pub fn pixel_size(&self) -> f64 {
+        match self.projection {
+            Projection::Flat => 1.0 / self.scale,
+            Projection::Exponential => {
+                let (width, height) = self.window_size;
+                let step = 2.0 * std::f64::consts::PI / width as f64;
+                self.corner_radius() * (-step * height as f64).exp() * step
+            }
+        }
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        self.window_size
+    }
+
+    pub fn map(&self) -> PlaneMap {
+        self.map
+    }
+
+    pub fn set_map(&mut self, map: PlaneMap) {
+        self.map = map;
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn slice(&self) -> (f64, f64) {
+        self.slice
+    }
+
+    /// Moves the slice plane along the j and k axes
+    pub fn move_slice(&mut self, j: f64, k: f64) {
+        self.slice.0 += j;
+        self.slice.1 += k;
+    }
+
+    /// Centers the view plane at z
     pub fn center_at(&mut self, z: &Complex<f64>) {
-        self.x = self.window_size.0 as f64 / 2.0 - z.re * self.scale;
-        self.y = self.window_size.1 as f64 / 2.0 - z.im * self.scale;
+        let bits = deep::precision_bits(1.0 / self.pixel_size());
+        self.set_center(&FixedComplex::from_f64(z, bits));
+    }
+
+    /// Centers the view plane at a point given in arbitrary precision
+    pub fn set_center(&mut self, z: &FixedComplex) {
+        self.set_origin(z.clone());
+        self.center = Complex::new(0.0, 0.0);
+    }
+
+    /// Centers the view at a pixel, the new center is calculated in
+    /// the precision needed for the current scale
+    pub fn recenter(&mut self, x: i32, y: i32) {
+        let delta = self.pos_to_delta(x, y);
+        let bits = deep::precision_bits(1.0 / self.pixel_size());
+        self.set_origin(self.origin.add_f64(&delta, bits));
+        self.center = Complex::new(0.0, 0.0);
     }
 }
 
-/// Information for each pixel in MandelImage
-#[derive(Clone)]
-pub struct MandelPixel {
-    pub x: i32,
-    pub y: i32,
-    pub iterations: u32,
-    pub iterations_equalized: u32,
+/// Orbit data of a pixel, only recorded on request as it costs memory
+#[derive(Copy, Clone, Debug)]
+pub struct OrbitStats {
+    /// smallest |z| after the first step
+    pub min_norm: f64,
+    /// iteration at which the smallest |z| was reached
+    pub min_iteration: u32,
+    /// last z of the orbit
+    pub z: Complex<f64>,
+    /// derivative of z by c, only for the Mandelbrot formula
+    pub derivative: Option<Complex<f64>>,
+    /// closest approach of the orbit to the orbit trap, infinite without one
+    pub trap: f64,
 }
 
-impl MandelPixel {
-    pub fn new(x: i32, y: i32) -> Self {
-        MandelPixel {
-            x,
-            y,
-            iterations: 0,
-            iterations_equalized: 0,
+impl Default for OrbitStats {
+    fn default() -> Self {
+        OrbitStats {
+            min_norm: f64::INFINITY,
+            min_iteration: 0,
+            z: Complex::new(0.0, 0.0),
+            derivative: None,
+            trap: f64::INFINITY,
         }
     }
 }
 
-/// Generated image data for the Mandelbrot set
+/// Rectangle of pixel positions, left and top inclusive
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PixelRect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl PixelRect {
+    /// Rectangle spanned by two opposite corners in any order
+    pub fn new(a: (i32, i32), b: (i32, i32)) -> Self {
+        PixelRect {
+            left: a.0.min(b.0),
+            top: a.1.min(b.1),
+            right: a.0.max(b.0),
+            bottom: a.1.max(b.1),
+        }
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.left && x < self.right && y >= self.top && y < self.bottom
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left == self.right || self.top == self.bottom
+    }
+
+    pub fn width(&self) -> u32 {
+        (self.right - self.left) as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        (self.bottom - self.top) as u32
+    }
+
+    /// Number of pixels in the rectangle
+    pub fn len(&self) -> usize {
+        self.width() as usize * self.height() as usize
+    }
+
+    /// Splits the rectangle into square tiles of the given size, row by
+    /// row, tiles at the right and bottom border may be smaller
+    pub fn tiles(&self, size: u32) -> impl Iterator<Item = PixelRect> {
+        let rect = *self;
+        let size = size.max(1) as usize;
+        (rect.top..rect.bottom).step_by(size).flat_map(move |top| {
+            (rect.left..rect.right)
+                .step_by(size)
+                .map(move |left| PixelRect {
+                    left,
+                    top,
+                    right: (left + size as i32).min(rect.right),
+                    bottom: (top + size as i32).min(rect.bottom),
+                })
+        })
+    }
+
+    /// The rectangle moved by dx pixels right and dy pixels down
+    pub fn offset(&self, dx: i32, dy: i32) -> PixelRect {
+        PixelRect {
+            left: self.left + dx,
+            top: self.top + dy,
+            right: self.right + dx,
+            bottom: self.bottom + dy,
+        }
+    }
+}
+
+/// Iteration counts of the pixels, low memory images use two bytes per
+/// pixel until a count doesn't fit
+#[derive(Debug, PartialEq)]
+pub enum IterationCounts {
+    Wide(Vec<u32>),
+    Narrow(Vec<u16>),
+}
+
+impl IterationCounts {
+    pub fn len(&self) -> usize {
+        match self {
+            IterationCounts::Wide(counts) => counts.len(),
+            IterationCounts::Narrow(counts) => counts.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> u32 {
+        match self {
+            IterationCounts::Wide(counts) => counts[i],
+            IterationCounts::Narrow(counts) => counts[i] as u32,
+        }
+    }
+
+    pub fn set(&mut self, i: usize, n: u32) {
+        match self {
+            IterationCounts::Wide(counts) => counts[i] = n,
+            IterationCounts::Narrow(counts) if n <= u16::MAX as u32 => counts[i] = n as u16,
+            IterationCounts::Narrow(_) => {
+                self.widen();
+                self.set(i, n);
+            }
+        }
+    }
+
+    /// Switches to four bytes per pixel, for counts beyond u16
+    fn widen(&mut self) {
+        if let IterationCounts::Narrow(counts) = self {
+            *self = IterationCounts::Wide(counts.iter().map(|&n| n as u32).collect());
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    fn copy_within(&mut self, range: Range<usize>, target: usize) {
+        match self {
+            IterationCounts::Wide(counts) => counts.copy_within(range, target),
+            IterationCounts::Narrow(counts) => counts.copy_within(range, target),
+        }
+    }
+
+    /// Copies len counts of the source starting at from to target
+    fn copy_from(&mut self, target: usize, source: &IterationCounts, from: usize, len: usize) {
+        match (self, source) {
+            (IterationCounts::Wide(counts), IterationCounts::Wide(source)) => {
+                counts[target..target + len].copy_from_slice(&source[from..from + len])
+            }
+            (IterationCounts::Narrow(counts), IterationCounts::Narrow(source)) => {
+                counts[target..target + len].copy_from_slice(&source[from..from + len])
+            }
+            (counts, source) => {
+                for i in 0..len {
+                    counts.set(target + i, source.get(from + i));
+                }
+            }
+        }
+    }
+}
+
+/// A row of pixels with the position of its first pixel, pixels are the
+/// indices of the row in the arrays of the image
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub x: i32,
+    pub y: i32,
+    pub pixels: Range<usize>,
+}
+
+/// Generated image data for the Mandelbrot set, each value of a pixel
+/// is stored in its own array in row order, see `index` and `position`.
+/// Low memory images leave the arrays they don't need empty.
 pub struct MandelImage {
     pub width: u32,
     pub height: u32,
     pub max_iterations: u32,
-    data: Vec<MandelPixel>,
+    pub iterations: IterationCounts,
+    pub iterations_equalized: Vec<u32>,
+    pub converged: Vec<bool>,
+    /// fractional iteration count for smooth coloring
+    pub smooth: Vec<f64>,
+    /// last z of the orbit at escape
+    pub z: Vec<Complex<f64>>,
+    /// closest approach of the orbit to the orbit trap
+    pub trap: Vec<f64>,
+    /// iteration of the smallest |z| of the orbit, the atom domain
+    pub period: Vec<u32>,
+    /// smallest |z| of the orbit
+    pub min_norm: Vec<f64>,
+    /// signed value for generators not based on iteration counts, low
+    /// memory images allocate it when a generator sets it
+    pub value: Vec<f64>,
+    /// estimated distance to the set in pixels, empty until it is estimated
+    pub distance: Vec<f64>,
+    /// estimated distance of points in the set to its boundary in pixels,
+    /// 0 outside the set or without a cycle, empty like the distance
+    pub interior: Vec<f64>,
+    /// orbit statistics of every pixel, empty unless recorded
+    stats: Vec<OrbitStats>,
 }
 
 impl MandelImage {
     pub fn new(width: u32, height: u32, max_iterations: u32) -> Self {
         let start = SystemTime::now();
-        let mut pixels = Vec::with_capacity((width * height) as usize);
-        for y in 0..height {
-            for x in 0..width {
-                pixels.push(MandelPixel::new(x as i32, y as i32));
-            }
-        }
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .expect("Image size overflows the address space");
+        let image = MandelImage {
+            width,
+            height,
+            max_iterations,
+            iterations: IterationCounts::Wide(vec![0; len]),
+            iterations_equalized: vec![0; len],
+            converged: vec![false; len],
+            smooth: vec![0.0; len],
+            z: vec![Complex::new(0.0, 0.0); len],
+            trap: vec![0.0; len],
+            period: vec![0; len],
+            min_norm: vec![0.0; len],
+            value: vec![0.0; len],
+            distance: Vec::new(),
+            interior: Vec::new(),
+            stats: Vec::new(),
+        };
+
+        timing::record("image", start, format_args!("Created image"));
+        image
+    }
 
-        println!("Created image in: {:?}", start.elapsed().unwrap());
+    /// Image keeping only the iteration counts and the converged flags,
+    /// without the arrays for the histogram, smooth and orbit coloring
+    pub fn low_memory(width: u32, height: u32, max_iterations: u32) -> Self {
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .expect("Image size overflows the address space");
         MandelImage {
             width,
             height,
             max_iterations,
-            data: pixels,
+            iterations: match max_iterations <= u16::MAX as u32 {
+                true => IterationCounts::Narrow(vec![0; len]),
+                false => IterationCounts::Wide(vec![0; len]),
+            },
+            iterations_equalized: Vec::new(),
+            converged: vec![false; len],
+            smooth: Vec::new(),
+            z: Vec::new(),
+            trap: Vec::new(),
+            period: Vec::new(),
+            min_norm: Vec::new(),
+            value: Vec::new(),
+            distance: Vec::new(),
+            interior: Vec::new(),
+            stats: Vec::new(),
         }
     }
 
+    /// True if the image keeps no smooth iteration counts and last z
+    pub fn is_low_memory(&self) -> bool {
+        self.smooth.is_empty() && !self.is_empty()
+    }
+
+    /// Number of pixels
+    pub fn len(&self) -> usize {
+        self.iterations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iterations.is_empty()
+    }
+
+    /// Index of the pixel in the arrays
+    pub fn index(&self, x: i32, y: i32) -> usize {
+        x as usize + y as usize * self.width as usize
+    }
+
+    /// Position of the pixel with the index
+    pub fn position(&self, index: usize) -> (i32, i32) {
+        let width = self.width as usize;
+        ((index % width) as i32, (index / width) as i32)
+    }
+
+    /// Rectangle covering the whole image
+    pub fn rect(&self) -> PixelRect {
+        PixelRect::new((0, 0), (self.width as i32, self.height as i32))
+    }
+
+    /// Iterates over the rows from top to bottom, the ranges index the
+    /// arrays for reading and writing alike
+    pub fn rows(&self) -> impl Iterator<Item = Row> {
+        self.tile_rows(&self.rect())
+    }
+
+    /// Iterates over the rows of a tile of the image from top to bottom
+    pub fn tile_rows(&self, tile: &PixelRect) -> impl Iterator<Item = Row> {
+        let rect = self.rect();
+        assert!(
+            tile.is_empty()
+                || rect.contains(tile.left, tile.top)
+                    && rect.contains(tile.right - 1, tile.bottom - 1)
+        );
+        let (width, tile) = (self.width as usize, *tile);
+        (tile.top..tile.bottom).map(move |y| {
+            let start = tile.left as usize + y as usize * width;
+            Row {
+                x: tile.left,
+                y,
+                pixels: start..start + tile.width() as usize,
+            }
+        })
+    }
+
+    /// Iterates over square tiles of the given size, row by row
+    pub fn tiles(&self, size: u32) -> impl Iterator<Item = PixelRect> {
+        self.rect().tiles(size)
+    }
+
     pub fn iterations(&self, x: i32, y: i32) -> u32 {
-        self.data[(x + y * self.width as i32) as usize].iterations
+        self.iterations.get(self.index(x, y))
+    }
+
+    pub fn value(&self, x: i32, y: i32) -> f64 {
+        self.value[self.index(x, y)]
     }
 
-    pub fn set_iterations(&mut self, rows: std::ops::Range<i32>, iters: &[u32]) {
-        let start_index = (rows.start * self.width as i32) as usize;
-        for (index, i) in iters.iter().enumerate() {
-            self.data[start_index + index].iterations = *i;
+    /// Orbit statistics of a pixel, None if they were not recorded
+    pub fn stats(&self, x: i32, y: i32) -> Option<&OrbitStats> {
+        self.stats.get(self.index(x, y))
+    }
+
+    /// Calls set with the index of every pixel in the tile and the
+    /// matching entry of data, data holds the width of the tile entries
+    /// for each row
+    fn set_tile<T, F>(&mut self, tile: &PixelRect, data: &[T], set: F)
+    where
+        F: Fn(&mut Self, usize, &T),
+    {
+        for (row, data) in self.tile_rows(tile).zip(data.chunks(tile.width() as usize)) {
+            for (index, d) in row.pixels.zip(data) {
+                set(self, index, d);
+            }
         }
     }
-}
 
-impl Deref for MandelImage {
-    type Target = [MandelPixel];
+    /// Stores the outcomes of a finished tile, data holds the rows of
+    /// the tile from top to bottom
+    pub fn set_iterations(&mut self, tile: &PixelRect, outcomes: &[Outcome]) {
+        self.set_tile(tile, outcomes, |image, i, o| {
+            image.iterations.set(i, o.iterations);
+            image.converged[i] = o.converged;
+            if !image.smooth.is_empty() {
+                image.smooth[i] = o.smooth;
+                image.z[i] = o.z;
+                image.trap[i] = o.trap;
+                image.period[i] = o.period;
+                image.min_norm[i] = o.min_norm;
+            }
+        });
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.data
+    /// Copies the outcomes of the computed rows to the other rows mirrored
+    /// about the real axis, row y gets the conjugate of row axis - y
+    pub fn mirror_rows(&mut self, computed: Range<i32>, axis: i32) {
+        let rows: Vec<Row> = self.rows().collect();
+        for row in rows.iter().filter(|row| !computed.contains(&row.y)) {
+            let source = rows[(axis - row.y) as usize].pixels.start;
+            self.copy_row(source, row.pixels.start, row.pixels.len());
+            if let Some(z) = self.z.get_mut(row.pixels.clone()) {
+                z.iter_mut().for_each(|z| *z = z.conj());
+            }
+        }
+    }
+
+    /// Copies the outcomes of len pixels starting at index source to the
+    /// pixels starting at index target
+    fn copy_row(&mut self, source: usize, target: usize, len: usize) {
+        let range = source..source + len;
+        self.iterations.copy_within(range.clone(), target);
+        self.converged.copy_within(range.clone(), target);
+        if !self.is_low_memory() {
+            self.smooth.copy_within(range.clone(), target);
+            self.z.copy_within(range.clone(), target);
+            self.trap.copy_within(range.clone(), target);
+            self.period.copy_within(range.clone(), target);
+            self.min_norm.copy_within(range, target);
+        }
+    }
+
+    /// Copies the outcomes of the pixels also shown in the source image,
+    /// pixel (x, y) gets pixel (x + dx, y + dy) of the source. Returns the
+    /// copied rectangle, the other pixels are left to be calculated.
+    pub fn copy_shifted(&mut self, source: &MandelImage, dx: i32, dy: i32) -> PixelRect {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let known = PixelRect {
+            left: (-dx).max(0),
+            top: (-dy).max(0),
+            right: (width - dx).min(width),
+            bottom: (height - dy).min(height),
+        };
+        if known.right <= known.left || known.bottom <= known.top {
+            return PixelRect::new((0, 0), (0, 0));
+        }
+        let len = known.width() as usize;
+        for row in self.tile_rows(&known) {
+            let target = row.pixels.start;
+            let from = source.index(row.x + dx, row.y + dy);
+            self.iterations
+                .copy_from(target, &source.iterations, from, len);
+            self.converged[target..target + len]
+                .copy_from_slice(&source.converged[from..from + len]);
+            if !self.is_low_memory() && !source.is_low_memory() {
+                self.smooth[target..target + len].copy_from_slice(&source.smooth[from..from + len]);
+                self.z[target..target + len].copy_from_slice(&source.z[from..from + len]);
+                self.trap[target..target + len].copy_from_slice(&source.trap[from..from + len]);
+                self.period[target..target + len].copy_from_slice(&source.period[from..from + len]);
+                self.min_norm[target..target + len]
+                    .copy_from_slice(&source.min_norm[from..from + len]);
+            }
+        }
+        known
+    }
+
+    /// Stores the orbit statistics of a tile
+    pub fn set_stats(&mut self, tile: &PixelRect, stats: &[OrbitStats]) {
+        let len = self.len();
+        if self.stats.len() != len {
+            self.stats.clear();
+            self.stats.resize(len, OrbitStats::default());
+        }
+        self.set_tile(tile, stats, |image, i, s| image.stats[i] = *s);
+    }
+
+    /// Drops the orbit statistics, keeping the memory for the next render
+    pub fn clear_stats(&mut self) {
+        self.stats.clear();
+    }
+
+    pub fn set_values(&mut self, tile: &PixelRect, values: &[f64]) {
+        self.value.resize(self.len(), 0.0);
+        self.set_tile(tile, values, |image, i, v| image.value[i] = *v);
     }
-}
 
-impl DerefMut for MandelImage {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.data
+    /// Stores the distance and interior distance of each pixel
+    pub fn set_distances(&mut self, tile: &PixelRect, distances: &[[f64; 2]]) {
+        self.distance.resize(self.len(), 0.0);
+        self.interior.resize(self.len(), 0.0);
+        self.set_tile(tile, distances, |image, i, &[d, interior]| {
+            image.distance[i] = d;
+            image.interior[i] = interior;
+        });
     }
 }
 
@@ -177,5 +843,131 @@ mod tests {
 
         transform.zoom(0.5);
         assert_eq!(transform.zoom_factor(), 5.0);
+
+        // the zoom factor only depends on the visible height
+        let wide = Transform::new((600, 300));
+        assert_eq!(wide.zoom_factor(), 1.0);
+        assert_eq!(wide.center(), DEFAULT_VIEW.center);
+
+        let mut transform = Transform::new((200, 300));
+        transform.set_view(View {
+            center: Complex::new(1.0, 0.0),
+            height: 5.6,
+        });
+        assert_eq!(transform.zoom_factor(), 0.5);
+        transform.zoom(4.0);
+        transform.reset();
+        assert_eq!(transform.center(), Complex::new(1.0, 0.0));
+        assert_eq!(transform.zoom_factor(), 0.5);
+    }
+
+    #[test]
+    fn test_band() {
+        let transform = Transform::new((200, 300));
+        let band = transform.band(120, 50);
+        assert_eq!(band.window_size(), (200, 50));
+        let (a, b) = (
+            band.pos_to_complex(30, 7),
+            transform.pos_to_complex(30, 127),
+        );
+        assert!((a - b).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_low_memory() {
+        let mut image = MandelImage::low_memory(4, 3, 1000);
+        assert!(image.is_low_memory());
+        assert!(image.smooth.is_empty() && image.z.is_empty() && image.value.is_empty());
+        image.set_iterations(&PixelRect::new((0, 0), (1, 1)), &[Outcome::new(1000, true)]);
+        assert_eq!(
+            image.iterations,
+            IterationCounts::Narrow(vec![1000, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+        );
+
+        // counts beyond u16 switch to four bytes per pixel
+        image.iterations.set(5, 100_000);
+        assert_eq!(image.iterations(0, 0), 1000);
+        assert_eq!(image.iterations(1, 1), 100_000);
+        assert!(matches!(image.iterations, IterationCounts::Wide(_)));
+
+        // values are allocated when a generator sets them
+        image.set_values(&PixelRect::new((1, 1), (2, 2)), &[2.5]);
+        assert_eq!(image.value(1, 1), 2.5);
+        assert_eq!(image.value.len(), 12);
+    }
+
+    #[test]
+    fn test_plane_map() {
+        let mut transform = Transform::new((200, 300));
+        transform.set_map(PlaneMap::Inverse);
+
+        let plane = transform.pos_to_plane(150, 100);
+        assert_eq!(transform.pos_to_complex(150, 100), plane.inv());
+        assert_eq!(transform._complex_to_point(plane.inv()), (150, 100));
+    }
+
+    #[test]
+    fn test_exponential() {
+        let mut transform = Transform::new((200, 300));
+        transform.set_projection(Projection::Exponential);
+        let center = transform.center();
+
+        // the first pixel is at the corner radius on the positive real axis
+        let radius = 200f64.hypot(300.0) / 2.0 / transform.scale();
+        let z = transform.pos_to_plane(0, 0) - center;
+        assert!((z - Complex::new(radius, 0.0)).norm() < 1e-12);
+
+        // each row shrinks the radius by the angle step of a column
+        let step = 2.0 * std::f64::consts::PI / 200.0;
+        let ratio = (transform.pos_to_plane(50, 11) - center).norm()
+            / (transform.pos_to_plane(50, 10) - center).norm();
+        assert!((ratio - (-step).exp()).abs() < 1e-12);
+        assert!(transform.pixel_size() < 1.0 / transform.scale());
+    }
+
+    #[test]
+    fn test_rows_and_tiles() {
+        let mut image = MandelImage::new(5, 3, 10);
+        image.set_iterations(&PixelRect::new((0, 1), (5, 3)), &[Outcome::default(); 10]);
+        image.set_values(&PixelRect::new((0, 1), (5, 2)), &[1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(image.value(2, 1), 3.0);
+
+        // a tile only writes its own pixels
+        let tile = PixelRect::new((3, 0), (5, 2));
+        assert_eq!(tile.len(), 4);
+        image.set_values(&tile, &[6.0, 7.0, 8.0, 9.0]);
+        assert_eq!(image.value(4, 1), 9.0);
+        assert_eq!(image.value(2, 1), 3.0);
+        assert_eq!(image.value(0, 0), 0.0);
+        assert_eq!(image.len(), 15);
+        assert_eq!(image.position(image.index(3, 2)), (3, 2));
+
+        let rows: Vec<Row> = image.rows().collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            (rows[2].x, rows[2].y, rows[2].pixels.clone()),
+            (0, 2, 10..15)
+        );
+        assert_eq!(
+            image.value[rows[1].pixels.clone()],
+            [1.0, 2.0, 3.0, 8.0, 9.0]
+        );
+
+        let tiles: Vec<PixelRect> = image.tiles(2).collect();
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[5], PixelRect::new((4, 2), (5, 3)));
+        let rows: Vec<Row> = image.tile_rows(&tiles[1]).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!((rows[1].x, rows[1].y, rows[1].pixels.clone()), (2, 1, 7..9));
+        assert_eq!(image.value[rows[1].pixels.clone()], [3.0, 8.0]);
+        let rows: Vec<Row> = image.tile_rows(&tiles[5]).collect();
+        assert_eq!(
+            rows,
+            vec![Row {
+                x: 4,
+                y: 2,
+                pixels: 14..15
+            }]
+        );
     }
 }
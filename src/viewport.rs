@@ -0,0 +1,100 @@
+use crate::types::Transform;
+
+use sdl2::rect::Rect;
+
+/// An image area of a fixed size, mapped onto the window and the
+/// complex plane regardless of the window aspect ratio
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    pub fn new(width: u32, height: u32) -> Self {
+        Viewport { width, height }
+    }
+
+    /// Returns a transform for this viewport covering the whole region of
+    /// the given transform, the region is extended symmetrically along the
+    /// axis where the aspect ratio has room to spare
+    pub fn fit(&self, transform: &Transform) -> Transform {
+        let (width, height) = transform.window_size();
+        let ratio = (self.width as f64 / width as f64).min(self.height as f64 / height as f64);
+
+        transform.resized((self.width, self.height), transform.scale() * ratio)
+    }
+
+    /// Largest rectangle with the aspect ratio of this viewport centered in
+    /// the window, the rest of the window is left as black bars
+    pub fn letterbox(&self, window_size: (u32, u32)) -> Rect {
+        let ratio = (window_size.0 as f64 / self.width as f64)
+            .min(window_size.1 as f64 / self.height as f64);
+        let width = ((self.width as f64 * ratio).round() as u32).max(1);
+        let height = ((self.height as f64 * ratio).round() as u32).max(1);
+
+        Rect::new(
+            (window_size.0 - width) as i32 / 2,
+            (window_size.1 - height) as i32 / 2,
+            width,
+            height,
+        )
+    }
+
+    /// Maps a window position to the closest viewport position
+    pub fn window_to_viewport(&self, window_size: (u32, u32), x: i32, y: i32) -> (i32, i32) {
+        let rect = self.letterbox(window_size);
+        let map = |pos: i32, start: i32, length: u32, size: u32| {
+            let p = (pos - start) as i64 * size as i64 / length as i64;
+            p.max(0).min(size as i64 - 1) as i32
+        };
+
+        (
+            map(x, rect.x(), rect.width(), self.width),
+            map(y, rect.y(), rect.height(), self.height),
+        )
+    }
+
+    /// Maps a viewport position to the window
+    pub fn viewport_to_window(&self, window_size: (u32, u32), x: i32, y: i32) -> (i32, i32) {
+        let rect = self.letterbox(window_size);
+
+        (
+            rect.x() + (x as i64 * rect.width() as i64 / self.width as i64) as i32,
+            rect.y() + (y as i64 * rect.height() as i64 / self.height as i64) as i32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        let transform = Transform::new((200, 100));
+
+        // a square viewport extends the view vertically
+        let square = Viewport::new(400, 400).fit(&transform);
+        assert_eq!(square.scale(), transform.scale() * 2.0);
+        assert_eq!(square.center(), transform.center());
+        assert_eq!(
+            square.pos_to_complex(0, 200),
+            transform.pos_to_complex(0, 50)
+        );
+        assert_eq!(
+            square.pos_to_complex(400, 200),
+            transform.pos_to_complex(200, 50)
+        );
+    }
+
+    #[test]
+    fn test_letterbox() {
+        let viewport = Viewport::new(400, 200);
+
+        assert_eq!(viewport.letterbox((400, 400)), Rect::new(0, 100, 400, 200));
+        assert_eq!(viewport.window_to_viewport((400, 400), 100, 50), (100, 0));
+        assert_eq!(viewport.window_to_viewport((400, 400), 100, 150), (100, 50));
+        assert_eq!(viewport.viewport_to_window((400, 400), 100, 50), (100, 150));
+    }
+}
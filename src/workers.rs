@@ -0,0 +1,206 @@
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+/// Directory listing the NUMA nodes and their CPUs on Linux
+const NODE_DIR: &str = "/sys/devices/system/node";
+
+/// Whether the global pool pins its workers to CPUs
+static PINNING: AtomicBool = AtomicBool::new(false);
+
+/// Work handed to a worker thread
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Threads started once and reused for the rows of every render, so a
+/// frame does not pay for spawning threads
+pub struct WorkerPool {
+    sender: Mutex<Sender<Job>>,
+    size: usize,
+}
+
+impl WorkerPool {
+    /// Starts size worker threads waiting for jobs
+    pub fn new(size: usize) -> Self {
+        WorkerPool::pinned(size, Vec::new())
+    }
+
+    /// Starts size worker threads, worker i runs on cpus[i % cpus.len()]
+    /// if there are any
+    pub fn pinned(size: usize, cpus: Vec<usize>) -> Self {
+        let size = size.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..size {
+            let receiver = receiver.clone();
+            let cpu = match cpus.is_empty() {
+                true => None,
+                false => Some(cpus[index % cpus.len()]),
+            };
+            thread::Builder::new()
+                .name(format!("render-{}", index))
+                .spawn(move || {
+                    if let Some(cpu) = cpu {
+                        pin(cpu);
+                    }
+                    work(&receiver)
+                })
+                .expect("Failed to start render worker");
+        }
+        WorkerPool {
+            sender: Mutex::new(sender),
+            size,
+        }
+    }
+
+    /// Number of worker threads
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Runs the job on the next idle worker
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.sender
+            .lock()
+            .unwrap()
+            .send(Box::new(job))
+            .expect("Render workers stopped");
+    }
+}
+
+/// Runs jobs until the pool is dropped, a panicking job only loses its
+/// own results and the worker takes the next job
+fn work(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+    }
+}
+
+/// Pins the calling thread to the CPU
+#[cfg(all(feature = "affinity", target_os = "linux"))]
+fn pin(cpu: usize) {
+    // SAFETY: the set is a plain bit mask owned by this frame
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result != 0 {
+        println!("Failed to pin render worker to CPU {}", cpu);
+    }
+}
+
+#[cfg(not(all(feature = "affinity", target_os = "linux")))]
+fn pin(_cpu: usize) {}
+
+/// Pins the workers of the global pool to CPUs spread over the NUMA
+/// nodes, only takes effect before the first render
+pub fn set_pinning(on: bool) {
+    PINNING.store(on, Ordering::Relaxed);
+}
+
+/// CPUs of each NUMA node ordered by node, empty if the system does not
+/// list its nodes
+pub fn numa_nodes() -> Vec<Vec<usize>> {
+    let mut nodes: Vec<(usize, Vec<usize>)> = fs::read_dir(NODE_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let node = name.strip_prefix("node")?.parse().ok()?;
+            let cpus = fs::read_to_string(entry.path().join("cpulist")).ok()?;
+            Some((node, parse_cpu_list(&cpus)?))
+        })
+        .filter(|(_, cpus)| !cpus.is_empty())
+        .collect();
+    nodes.sort();
+    nodes.into_iter().map(|(_, cpus)| cpus).collect()
+}
+
+/// Parses a kernel CPU list like "0-3,8,10-11"
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|part| !part.is_empty()) {
+        match part.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cpus.push(part.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// Takes the CPUs of the nodes in turns, so consecutive workers and
+/// pools smaller than the machine are spread evenly over the nodes
+fn interleave(nodes: &[Vec<usize>]) -> Vec<usize> {
+    let longest = nodes.iter().map(Vec::len).max().unwrap_or(0);
+    (0..longest)
+        .flat_map(|i| nodes.iter().filter_map(move |cpus| cpus.get(i).copied()))
+        .collect()
+}
+
+/// Pool shared by all renders with one worker per available core,
+/// started by the first render
+pub fn global() -> &'static WorkerPool {
+    static POOL: OnceLock<WorkerPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+        let nodes = numa_nodes();
+        println!(
+            "Starting {} render workers on {} NUMA nodes",
+            cores,
+            nodes.len().max(1)
+        );
+        let cpus = match PINNING.load(Ordering::Relaxed) {
+            true if nodes.is_empty() => (0..cores).collect(),
+            true => interleave(&nodes),
+            false => Vec::new(),
+        };
+        WorkerPool::pinned(cores, cpus)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool() {
+        let pool = WorkerPool::new(2);
+        let (tx, rx) = mpsc::channel();
+        for i in 0..8 {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
+        }
+        let mut done: Vec<i32> = rx.iter().take(8).collect();
+        done.sort();
+        assert_eq!(done, (0..8).collect::<Vec<_>>());
+
+        // the single worker survives a panicking job
+        let pool = WorkerPool::new(1);
+        pool.execute(|| panic!("Injected panic in worker"));
+        pool.execute(move || tx.send(42).unwrap());
+        assert_eq!(rx.recv(), Ok(42));
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[test]
+    fn test_placement() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+
+        let nodes = vec![vec![0, 1, 2], vec![4, 5]];
+        assert_eq!(interleave(&nodes), vec![0, 4, 1, 5, 2]);
+        assert!(interleave(&[]).is_empty());
+    }
+}